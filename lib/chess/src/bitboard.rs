@@ -0,0 +1,174 @@
+use crate::coord::Coord;
+use crate::pieces::Side;
+
+/// Set of squares on the board, one bit per square. Bit `i` is the square
+/// with `Coord::from_index(i)`, so bit 0 is a8 and bit 63 is h1.
+pub type Bitboard = u64;
+
+pub const EMPTY: Bitboard = 0;
+
+/// Returns a bitboard with only the given square set
+pub const fn coord_bb(coord: Coord) -> Bitboard {
+    1 << coord.get_index()
+}
+
+/// Returns whether the square is in the bitboard
+pub const fn contains(bb: Bitboard, coord: Coord) -> bool {
+    bb & coord_bb(coord) != 0
+}
+
+/// Iterates through the squares of a bitboard from a8 to h1
+pub struct BitIter(pub Bitboard);
+
+impl Iterator for BitIter {
+    type Item = Coord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None
+        }
+
+        let index = self.0.trailing_zeros() as usize;
+        // Clear the lowest set bit
+        self.0 &= self.0 - 1;
+
+        Some(Coord::from_index(index))
+    }
+}
+
+/// Builds a table of the squares reachable from each square by a single
+/// step in each of the directions
+const fn step_table(dirs: &[[i8; 2]]) -> [Bitboard; 64] {
+    let mut table = [EMPTY; 64];
+
+    let mut i = 0;
+    while i < 64 {
+        let x = (i % 8) as i8;
+        let y = 7 - (i / 8) as i8;
+
+        let mut d = 0;
+        while d < dirs.len() {
+            let to_x = x + dirs[d][0];
+            let to_y = y + dirs[d][1];
+
+            if to_x >= 0 && to_x < 8 && to_y >= 0 && to_y < 8 {
+                table[i] |= 1 << (to_x as usize + 8 * (7 - to_y as usize));
+            }
+            d += 1;
+        }
+        i += 1;
+    }
+
+    table
+}
+
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = step_table(&[
+    [2,1], [2,-1], [1,2], [1,-2],
+    [-2, 1], [-2,-1], [-1,2], [-1,-2]
+]);
+
+pub const KING_ATTACKS: [Bitboard; 64] = step_table(&[
+    [1,1], [-1,1], [1,-1], [-1,-1],
+    [1,0], [0,1], [-1,0], [0,-1]
+]);
+
+/// Squares attacked by a pawn on each square, indexed by `Side`
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    step_table(&[[-1,-1], [1,-1]]),
+    step_table(&[[-1,1], [1,1]])
+];
+
+/// Directions used by sliding pieces. The first four move towards lower
+/// indexes and the last four towards higher indexes.
+const SLIDE_DIRS: [[i8; 2]; 8] = [
+    [0,1], [1,1], [-1,1], [-1,0],
+    [0,-1], [-1,-1], [1,-1], [1,0]
+];
+
+const BISHOP_DIRS: [usize; 4] = [1, 2, 5, 6];
+const ROOK_DIRS: [usize; 4] = [0, 3, 4, 7];
+
+/// Squares from each square to the edge of the board in each direction,
+/// not including the square itself
+const RAYS: [[Bitboard; 64]; 8] = {
+    let mut rays = [[EMPTY; 64]; 8];
+
+    let mut d = 0;
+    while d < 8 {
+        let mut i = 0;
+        while i < 64 {
+            let mut x = (i % 8) as i8 + SLIDE_DIRS[d][0];
+            let mut y = 7 - (i / 8) as i8 + SLIDE_DIRS[d][1];
+
+            while x >= 0 && x < 8 && y >= 0 && y < 8 {
+                rays[d][i] |= 1 << (x as usize + 8 * (7 - y as usize));
+                x += SLIDE_DIRS[d][0];
+                y += SLIDE_DIRS[d][1];
+            }
+            i += 1;
+        }
+        d += 1;
+    }
+
+    rays
+};
+
+/// Squares attacked along a ray, stopping at (and including) the first
+/// occupied square
+fn ray_attacks(dir: usize, index: usize, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[dir][index];
+    let blockers = ray & occupied;
+
+    if blockers == 0 {
+        return ray
+    }
+
+    let blocker = if dir < 4 {
+        63 - blockers.leading_zeros() as usize
+    } else {
+        blockers.trailing_zeros() as usize
+    };
+
+    ray ^ RAYS[dir][blocker]
+}
+
+pub fn bishop_attacks(coord: Coord, occupied: Bitboard) -> Bitboard {
+    BISHOP_DIRS.iter()
+        .fold(EMPTY, |acc, &d| acc | ray_attacks(d, coord.get_index(), occupied))
+}
+
+pub fn rook_attacks(coord: Coord, occupied: Bitboard) -> Bitboard {
+    ROOK_DIRS.iter()
+        .fold(EMPTY, |acc, &d| acc | ray_attacks(d, coord.get_index(), occupied))
+}
+
+pub fn queen_attacks(coord: Coord, occupied: Bitboard) -> Bitboard {
+    bishop_attacks(coord, occupied) | rook_attacks(coord, occupied)
+}
+
+pub fn knight_attacks(coord: Coord) -> Bitboard {
+    KNIGHT_ATTACKS[coord.get_index()]
+}
+
+pub fn king_attacks(coord: Coord) -> Bitboard {
+    KING_ATTACKS[coord.get_index()]
+}
+
+pub fn pawn_attacks(coord: Coord, side: Side) -> Bitboard {
+    PAWN_ATTACKS[side as usize][coord.get_index()]
+}
+
+/// Gets the direction in `SLIDE_DIRS` to go from one square to another,
+/// if they share a rank, file or diagonal
+fn get_slide_dir(from: Coord, to: Coord) -> Option<usize> {
+    (0..8).find(|&d| contains(RAYS[d][from.get_index()], to))
+}
+
+/// Squares strictly between two squares on the same rank, file or
+/// diagonal. Empty if the squares don't share a line
+pub fn between(from: Coord, to: Coord) -> Bitboard {
+    match get_slide_dir(from, to) {
+        Some(d) => RAYS[d][from.get_index()] & !RAYS[d][to.get_index()] & !coord_bb(to),
+        None => EMPTY
+    }
+}
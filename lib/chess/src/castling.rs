@@ -0,0 +1,101 @@
+use crate::coord::Coord;
+use crate::pieces::Side;
+
+/// Which side of the king a rook castles from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CastlingSide {
+    /// Towards the h file, written `O-O`
+    KingSide,
+    /// Towards the a file, written `O-O-O`
+    QueenSide
+}
+
+impl CastlingSide {
+    /// Gets the files the king and rook end up on after castling
+    pub fn get_king_and_rook_to_x(&self) -> (i8, i8) {
+        match self {
+            CastlingSide::KingSide => (6, 5),
+            CastlingSide::QueenSide => (2, 3)
+        }
+    }
+}
+
+pub const ALL_CASTLING_SIDES: [CastlingSide; 2] = [CastlingSide::KingSide, CastlingSide::QueenSide];
+
+/// The rooks each side may still castle with. Rights are stored by the file
+/// of the rook so Chess960 positions can be described as well as standard
+/// ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CastlingRights {
+    /// Indexed by `Side` then `CastlingSide`
+    rook_files: [[Option<i8>; 2]; 2],
+}
+
+impl CastlingRights {
+    /// Neither side may castle
+    pub const NONE: Self = Self { rook_files: [[None; 2]; 2] };
+
+    /// Both sides may castle either way with the rooks in the corners, as
+    /// in the standard starting position
+    pub const STANDARD: Self = Self { rook_files: [[Some(7), Some(0)]; 2] };
+
+    /// Gets the file of the rook the side may castle with, if it may castle
+    /// that way
+    pub fn get_rook_file(&self, side: Side, castling_side: CastlingSide) -> Option<i8> {
+        self.rook_files[side as usize][castling_side as usize]
+    }
+
+    /// Gets the square of the rook the side may castle with, if it may
+    /// castle that way
+    pub fn get_rook_coord(&self, side: Side, castling_side: CastlingSide) -> Option<Coord> {
+        self.get_rook_file(side, castling_side)
+            .map(|x| Coord::from_x_and_y(x, side.get_back_rank_y()))
+    }
+
+    /// Whether the side may castle that way
+    pub fn has(&self, side: Side, castling_side: CastlingSide) -> bool {
+        self.get_rook_file(side, castling_side).is_some()
+    }
+
+    /// Whether the side may castle either way
+    pub fn has_any(&self, side: Side) -> bool {
+        self.rook_files[side as usize].iter().any(Option::is_some)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Lets the side castle with the rook on the given file
+    pub fn set(&mut self, side: Side, castling_side: CastlingSide, rook_x: i8) {
+        self.rook_files[side as usize][castling_side as usize] = Some(rook_x);
+    }
+
+    /// Stops the side castling that way
+    pub fn remove(&mut self, side: Side, castling_side: CastlingSide) {
+        self.rook_files[side as usize][castling_side as usize] = None;
+    }
+
+    /// Stops the side castling at all, such as when its king moves
+    pub fn remove_side(&mut self, side: Side) {
+        self.rook_files[side as usize] = [None; 2];
+    }
+
+    /// Removes any right to castle with a rook on the square, for when the
+    /// rook moves or is taken
+    pub fn remove_rook_at(&mut self, coord: Coord) {
+        let [x, y] = coord.get_x_and_y();
+
+        for side in [Side::White, Side::Black] {
+            if side.get_back_rank_y() != y {
+                continue
+            }
+
+            for castling_side in ALL_CASTLING_SIDES {
+                if self.get_rook_file(side, castling_side) == Some(x) {
+                    self.remove(side, castling_side);
+                }
+            }
+        }
+    }
+}
@@ -1,17 +1,9 @@
-#[derive(Copy, Clone, Hash)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 /// Coordinate system for the board
 pub struct Coord {
     index: usize
 }
 
-impl Eq for Coord {}
-
-impl PartialEq for Coord {
-    fn eq(&self, other: &Self) -> bool {
-        self.index == other.index
-    }
-}
-
 impl std::fmt::Debug for Coord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let [x, y] = self.get_x_and_y();
@@ -20,16 +12,51 @@ impl std::fmt::Debug for Coord {
     }
 }
 
+/// Writes the square in algebraic form, e.g. `e4`
+impl std::fmt::Display for Coord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [x, y] = self.get_x_and_y();
+        write!(f, "{}{}", (b'a' + x as u8) as char, (b'1' + y as u8) as char)
+    }
+}
+
+/// Error from reading a square that isn't a file `a`-`h` followed by a
+/// rank `1`-`8`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CoordParseError;
+
+impl std::fmt::Display for CoordParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid square")
+    }
+}
+
+impl std::error::Error for CoordParseError {}
+
+/// Reads a square in algebraic form, e.g. `e4`
+impl std::str::FromStr for Coord {
+    type Err = CoordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Ok(Coord::from_x_and_y((file - b'a') as i8, (rank - b'1') as i8))
+            },
+            _ => Err(CoordParseError)
+        }
+    }
+}
+
 impl Coord {
-    pub fn from_index(index: usize) -> Self {
+    pub const fn from_index(index: usize) -> Self {
         Self {index}
     }
 
-    pub fn from_x_and_y(x: i8, y: i8) -> Self {
+    pub const fn from_x_and_y(x: i8, y: i8) -> Self {
         Self {index: x as usize + (8 * (7-(y as usize)))}
     }
 
-    pub fn get_index(&self) -> usize {
+    pub const fn get_index(&self) -> usize {
         self.index
     }
 
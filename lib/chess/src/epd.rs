@@ -0,0 +1,178 @@
+//! Reading positions in Extended Position Description.
+//!
+//! Each line of an EPD file is the first four fields of a FEN followed by
+//! operations ending in semicolons, such as `bm Qg6; id "WAC.001";`. Test
+//! suites use `bm` for the best moves, `am` for moves to avoid, `id` to name
+//! the position and `c0` to `c9` for comments.
+
+use crate::game::{Game, FenParseError};
+use crate::moves::Move;
+use crate::san::SanError;
+
+/// Reasons an EPD file can't be read. Lines are counted from 1
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdError {
+    /// A line had fewer than the four position fields
+    MissingFields(usize),
+    InvalidFen { line: usize, error: FenParseError },
+    UnterminatedString(usize),
+    /// A clock operation had a value that isn't a number
+    InvalidOperand { line: usize, opcode: String, operand: String },
+    IllegalMove { line: usize, san: String, error: SanError },
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingFields(line) => write!(f, "line {} doesn't have the four position fields", line),
+            Self::InvalidFen { line, error } => write!(f, "invalid position on line {}: {}", line, error),
+            Self::UnterminatedString(line) => write!(f, "string on line {} is never closed", line),
+            Self::InvalidOperand { line, opcode, operand } =>
+                write!(f, "invalid operand {} for {} on line {}", operand, opcode, line),
+            Self::IllegalMove { line, san, error } => write!(f, "{} on line {}: {}", san, line, error)
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+/// An opcode and its operands, with the quotes taken off strings
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpdOperation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+/// A position from an EPD file along with its operations
+#[derive(Clone, Debug)]
+pub struct EpdRecord {
+    /// The position, with its clocks taken from the `hmvc` and `fmvn`
+    /// operations if they are given
+    pub game: Game,
+    /// Operations in the order they were written
+    pub operations: Vec<EpdOperation>,
+    /// Moves from the `bm` operation
+    pub best_moves: Vec<Move>,
+    /// Moves from the `am` operation
+    pub avoid_moves: Vec<Move>,
+}
+
+impl EpdRecord {
+    pub fn get_operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter()
+            .find(|op| op.opcode == opcode)
+            .map(|op| op.operands.as_slice())
+    }
+
+    /// Name of the position from the `id` operation
+    pub fn get_id(&self) -> Option<&str> {
+        self.get_operands("id")?.first().map(String::as_str)
+    }
+
+    /// Comment from one of `c0` to `c9`
+    pub fn get_comment(&self, number: u8) -> Option<&str> {
+        self.get_operands(&format!("c{}", number))?.first().map(String::as_str)
+    }
+}
+
+/// Splits the operations after the position into opcodes and operands
+fn parse_operations(text: &str, line: usize) -> Result<Vec<EpdOperation>, EpdError> {
+    let mut operations = Vec::new();
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    // The last operation doesn't need its semicolon
+    let mut finish = |words: &mut Vec<String>| {
+        if !words.is_empty() {
+            let opcode = words.remove(0);
+            operations.push(EpdOperation { opcode, operands: std::mem::take(words) });
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => finish(&mut words),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(EpdError::UnterminatedString(line))
+                    }
+                }
+                words.push(string);
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+    finish(&mut words);
+
+    Ok(operations)
+}
+
+fn parse_record(text: &str, line: usize) -> Result<EpdRecord, EpdError> {
+    let mut rest = text.trim_start();
+    let mut fields = Vec::new();
+
+    while fields.len() < 4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(EpdError::MissingFields(line))
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    let operations = parse_operations(rest, line)?;
+
+    let clock = |opcode: &str, default: &str| -> Result<String, EpdError> {
+        match operations.iter().find(|op| op.opcode == opcode).and_then(|op| op.operands.first()) {
+            Some(operand) if operand.parse::<u32>().is_ok() => Ok(operand.clone()),
+            Some(operand) => Err(EpdError::InvalidOperand {
+                line,
+                opcode: opcode.to_string(),
+                operand: operand.clone()
+            }),
+            None => Ok(default.to_string())
+        }
+    };
+
+    let fen = format!("{} {} {}", fields.join(" "), clock("hmvc", "0")?, clock("fmvn", "1")?);
+    let game = Game::from_fen(&fen).map_err(|error| EpdError::InvalidFen { line, error })?;
+
+    let moves = |opcode: &str| -> Result<Vec<Move>, EpdError> {
+        let operands = operations.iter()
+            .filter(|op| op.opcode == opcode)
+            .flat_map(|op| &op.operands);
+
+        operands
+            .map(|san| game.parse_san(san).map_err(|error| EpdError::IllegalMove { line, san: san.clone(), error }))
+            .collect()
+    };
+
+    let best_moves = moves("bm")?;
+    let avoid_moves = moves("am")?;
+
+    Ok(EpdRecord { game, operations, best_moves, avoid_moves })
+}
+
+/// Reads every position in EPD text, skipping blank lines
+pub fn parse_epd(text: &str) -> Result<Vec<EpdRecord>, EpdError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| parse_record(line, i + 1))
+        .collect()
+}
@@ -0,0 +1,479 @@
+//! Static evaluation of positions.
+//!
+//! Each term of the evaluation is scored separately for the middlegame and
+//! the endgame from White's point of view, then the two are blended by how
+//! much material is left on the board. Every term can be queried on its own
+//! and `Evaluation` gives a breakdown of the whole thing.
+
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+use crate::bitboard::{self, Bitboard, BitIter};
+use crate::coord::Coord;
+use crate::game::Game;
+use crate::pieces::{PieceType, Side, ALL_PIECE_TYPES};
+
+/// A score split into middlegame and endgame parts, in centipawns
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32
+}
+
+impl Score {
+    pub const ZERO: Score = Score::new(0, 0);
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Blends the middlegame and endgame parts for a game phase between
+    /// `0` (endgame) and `MAX_PHASE` (middlegame)
+    pub fn taper(&self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, n: i32) -> Score {
+        Score::new(self.mg * n, self.eg * n)
+    }
+}
+
+/// Value of each piece, indexed by `PieceType`
+pub const PIECE_VALUES: [Score; 6] = [
+    Score::new(0, 0),
+    Score::new(1025, 936),
+    Score::new(365, 297),
+    Score::new(477, 512),
+    Score::new(337, 281),
+    Score::new(82, 94)
+];
+
+/// Phase of the game with all the pieces on the board
+pub const MAX_PHASE: i32 = 24;
+
+/// How much each piece counts towards the game phase, indexed by `PieceType`
+const PHASE_WEIGHTS: [i32; 6] = [0, 4, 1, 2, 1, 0];
+
+const BISHOP_PAIR: Score = Score::new(30, 50);
+const DOUBLED_PAWN: Score = Score::new(-10, -20);
+const ISOLATED_PAWN: Score = Score::new(-10, -15);
+/// Bonus for a passed pawn by its rank, counted from the side's back rank
+const PASSED_PAWN: [Score; 8] = [
+    Score::new(0, 0), Score::new(5, 10), Score::new(10, 20), Score::new(20, 40),
+    Score::new(35, 70), Score::new(60, 120), Score::new(100, 200), Score::new(0, 0)
+];
+/// Bonus for each pawn sheltering the king
+const PAWN_SHIELD: Score = Score::new(10, 0);
+/// Penalty for each attack on the squares around the king
+const KING_ZONE_ATTACK: Score = Score::new(-8, -2);
+
+/// Bonus per square a piece can move to and the number of squares that is
+/// scored as neutral, indexed by `PieceType`
+const MOBILITY: [(Score, i32); 6] = [
+    (Score::new(0, 0), 0),
+    (Score::new(1, 2), 14),
+    (Score::new(5, 5), 7),
+    (Score::new(2, 4), 7),
+    (Score::new(4, 4), 4),
+    (Score::new(0, 0), 0)
+];
+
+/// Piece-square tables from White's point of view, written with a8 first so
+/// they can be indexed by `Coord::get_index`
+const PAWN_MG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+const PAWN_EG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     15,  15,  15,  15,  15,  15,  15,  15,
+      5,   5,   5,   5,   5,   5,   5,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+const KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50
+];
+
+const BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20
+];
+
+const ROOK: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0
+];
+
+const QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20
+];
+
+const KING_MG: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20
+];
+
+const KING_EG: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50
+];
+
+/// Middlegame and endgame tables for each piece, indexed by `PieceType`
+const PIECE_SQUARE_TABLES: [(&[i32; 64], &[i32; 64]); 6] = [
+    (&KING_MG, &KING_EG),
+    (&QUEEN, &QUEEN),
+    (&BISHOP, &BISHOP),
+    (&ROOK, &ROOK),
+    (&KNIGHT, &KNIGHT),
+    (&PAWN_MG, &PAWN_EG)
+];
+
+/// Sums a term for White and subtracts it for Black
+fn white_minus_black(term: impl Fn(Side) -> Score) -> Score {
+    term(Side::White) - term(Side::Black)
+}
+
+/// Index into the piece-square tables, which are written for White
+fn table_index(coord: Coord, side: Side) -> usize {
+    match side {
+        Side::White => coord.get_index(),
+        // Flip the board vertically for Black
+        Side::Black => coord.get_index() ^ 56
+    }
+}
+
+/// How far through the game it is, from `MAX_PHASE` with all the pieces
+/// on the board down to `0` with only kings and pawns
+pub fn phase(game: &Game) -> i32 {
+    let phase: i32 = ALL_PIECE_TYPES.iter()
+        .map(|&t| PHASE_WEIGHTS[t as usize] *
+            (game.get_piece_bb(Side::White, t) | game.get_piece_bb(Side::Black, t)).count_ones() as i32)
+        .sum();
+
+    phase.min(MAX_PHASE)
+}
+
+/// Value of the pieces on the board
+pub fn material(game: &Game) -> Score {
+    white_minus_black(|side| {
+        ALL_PIECE_TYPES.iter()
+            .fold(Score::ZERO, |acc, &t|
+                acc + PIECE_VALUES[t as usize] * game.get_piece_bb(side, t).count_ones() as i32)
+    })
+}
+
+/// Bonuses and penalties for the squares pieces are on
+pub fn piece_squares(game: &Game) -> Score {
+    white_minus_black(|side| {
+        let mut score = Score::ZERO;
+
+        for piece_type in ALL_PIECE_TYPES {
+            let (mg, eg) = PIECE_SQUARE_TABLES[piece_type as usize];
+
+            for coord in BitIter(game.get_piece_bb(side, piece_type)) {
+                let i = table_index(coord, side);
+                score += Score::new(mg[i], eg[i]);
+            }
+        }
+
+        score
+    })
+}
+
+/// Bonus for the number of squares pieces can move to. Squares attacked by
+/// enemy pawns are not counted
+pub fn mobility(game: &Game) -> Score {
+    let occupied = game.get_occupied_bb();
+
+    white_minus_black(|side| {
+        let enemy_pawn_attacks = BitIter(game.get_piece_bb(side.other(), PieceType::Pawn))
+            .fold(bitboard::EMPTY, |acc, c| acc | bitboard::pawn_attacks(c, side.other()));
+        let available = !game.get_side_bb(side) & !enemy_pawn_attacks;
+
+        let mut score = Score::ZERO;
+
+        for piece_type in [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen] {
+            let (weight, neutral) = MOBILITY[piece_type as usize];
+
+            for coord in BitIter(game.get_piece_bb(side, piece_type)) {
+                let attacks = match piece_type {
+                    PieceType::Knight => bitboard::knight_attacks(coord),
+                    PieceType::Bishop => bitboard::bishop_attacks(coord, occupied),
+                    PieceType::Rook => bitboard::rook_attacks(coord, occupied),
+                    _ => bitboard::queen_attacks(coord, occupied)
+                };
+
+                score += weight * ((attacks & available).count_ones() as i32 - neutral);
+            }
+        }
+
+        score
+    })
+}
+
+/// Squares on a file
+fn file_bb(x: i8) -> Bitboard {
+    (0..8).fold(bitboard::EMPTY, |acc, y| acc | bitboard::coord_bb(Coord::from_x_and_y(x, y)))
+}
+
+/// Squares on the file and the files either side of it
+fn adjacent_files_bb(x: i8) -> Bitboard {
+    ((x - 1).max(0)..=(x + 1).min(7)).fold(bitboard::EMPTY, |acc, x| acc | file_bb(x))
+}
+
+/// Squares strictly in front of the rank from the point of view of `side`
+fn ranks_in_front_bb(y: i8, side: Side) -> Bitboard {
+    (0..8)
+        .filter(|&rank| (rank - y) * side.get_dir() > 0)
+        .fold(bitboard::EMPTY, |acc, rank| {
+            (0..8).fold(acc, |acc, x| acc | bitboard::coord_bb(Coord::from_x_and_y(x, rank)))
+        })
+}
+
+/// Penalties for doubled and isolated pawns and bonuses for passed pawns
+pub fn pawn_structure(game: &Game) -> Score {
+    white_minus_black(|side| {
+        let pawns = game.get_piece_bb(side, PieceType::Pawn);
+        let enemy_pawns = game.get_piece_bb(side.other(), PieceType::Pawn);
+
+        let mut score = Score::ZERO;
+
+        for x in 0..8 {
+            let on_file = (pawns & file_bb(x)).count_ones() as i32;
+
+            if on_file > 1 {
+                score += DOUBLED_PAWN * (on_file - 1);
+            }
+
+            if on_file > 0 && pawns & adjacent_files_bb(x) & !file_bb(x) == bitboard::EMPTY {
+                score += ISOLATED_PAWN * on_file;
+            }
+        }
+
+        for coord in BitIter(pawns) {
+            let [x, y] = coord.get_x_and_y();
+
+            if enemy_pawns & adjacent_files_bb(x) & ranks_in_front_bb(y, side) == bitboard::EMPTY {
+                let ranks_moved = match side {
+                    Side::White => y,
+                    Side::Black => 7 - y
+                };
+                score += PASSED_PAWN[ranks_moved as usize];
+            }
+        }
+
+        score
+    })
+}
+
+/// Bonus for pawns in front of the king and a penalty for enemy pieces
+/// attacking the squares around it
+pub fn king_safety(game: &Game) -> Score {
+    let occupied = game.get_occupied_bb();
+
+    white_minus_black(|side| {
+        let king = game.get_king_loc(side);
+        let [x, y] = king.get_x_and_y();
+
+        // Pawns on the two ranks in front of the king on its file and the
+        // files either side
+        let shield_ranks = ranks_in_front_bb(y, side) & !ranks_in_front_bb(y + 2 * side.get_dir(), side);
+        let shield = game.get_piece_bb(side, PieceType::Pawn) & adjacent_files_bb(x) & shield_ranks;
+
+        let zone = bitboard::king_attacks(king) | bitboard::coord_bb(king);
+        let enemy = side.other();
+
+        let mut attacks = 0;
+        for piece_type in ALL_PIECE_TYPES {
+            for coord in BitIter(game.get_piece_bb(enemy, piece_type)) {
+                let attacked = match piece_type {
+                    PieceType::Queen => bitboard::queen_attacks(coord, occupied),
+                    PieceType::Bishop => bitboard::bishop_attacks(coord, occupied),
+                    PieceType::Rook => bitboard::rook_attacks(coord, occupied),
+                    PieceType::Knight => bitboard::knight_attacks(coord),
+                    PieceType::Pawn => bitboard::pawn_attacks(coord, enemy),
+                    PieceType::King => bitboard::EMPTY
+                };
+
+                attacks += (attacked & zone).count_ones() as i32;
+            }
+        }
+
+        PAWN_SHIELD * shield.count_ones() as i32 + KING_ZONE_ATTACK * attacks
+    })
+}
+
+/// Bonus for having both bishops
+pub fn bishop_pair(game: &Game) -> Score {
+    white_minus_black(|side| {
+        if game.get_piece_bb(side, PieceType::Bishop).count_ones() >= 2 {
+            BISHOP_PAIR
+        } else {
+            Score::ZERO
+        }
+    })
+}
+
+/// Every term of the evaluation for a position, from White's point of view
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Evaluation {
+    pub material: Score,
+    pub piece_squares: Score,
+    pub mobility: Score,
+    pub pawn_structure: Score,
+    pub king_safety: Score,
+    pub bishop_pair: Score,
+    pub phase: i32,
+    pub side_to_move: Side
+}
+
+impl Evaluation {
+    pub fn new(game: &Game) -> Self {
+        Self {
+            material: material(game),
+            piece_squares: piece_squares(game),
+            mobility: mobility(game),
+            pawn_structure: pawn_structure(game),
+            king_safety: king_safety(game),
+            bishop_pair: bishop_pair(game),
+            phase: phase(game),
+            side_to_move: game.get_side_to_play()
+        }
+    }
+
+    /// Sum of every term
+    pub fn total(&self) -> Score {
+        self.material + self.piece_squares + self.mobility +
+        self.pawn_structure + self.king_safety + self.bishop_pair
+    }
+
+    /// Tapered score from White's point of view
+    pub fn white_score(&self) -> i32 {
+        self.total().taper(self.phase)
+    }
+
+    /// Tapered score from the point of view of the side to move
+    pub fn score(&self) -> i32 {
+        match self.side_to_move {
+            Side::White => self.white_score(),
+            Side::Black => -self.white_score()
+        }
+    }
+
+    fn terms(&self) -> [(&'static str, Score); 6] {
+        [
+            ("Material", self.material),
+            ("Piece squares", self.piece_squares),
+            ("Mobility", self.mobility),
+            ("Pawn structure", self.pawn_structure),
+            ("King safety", self.king_safety),
+            ("Bishop pair", self.bishop_pair)
+        ]
+    }
+}
+
+impl std::fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<16}{:>8}{:>8}{:>8}", "Term", "MG", "EG", "Tapered")?;
+
+        for (name, score) in self.terms() {
+            writeln!(f, "{:<16}{:>8}{:>8}{:>8}", name, score.mg, score.eg, score.taper(self.phase))?;
+        }
+
+        let total = self.total();
+        writeln!(f, "{:<16}{:>8}{:>8}{:>8}", "Total", total.mg, total.eg, self.white_score())?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        write!(f, "Score for {:?}: {}", self.side_to_move, self.score())
+    }
+}
+
+/// Evaluates the position in centipawns from the point of view of the side
+/// to move
+pub fn evaluate(game: &Game) -> i32 {
+    Evaluation::new(game).score()
+}
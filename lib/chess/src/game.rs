@@ -1,36 +1,134 @@
-use std::collections::HashSet;
-
-use crate::moves::{Move, get_side_targets, get_piece_moves, MoveError};
+use crate::bitboard::{self, Bitboard, BitIter};
+use crate::castling::{CastlingRights, CastlingSide, ALL_CASTLING_SIDES};
+use crate::movegen::{GenMode, MoveList};
+use crate::moves::{Move, get_piece_moves, is_square_attacked, MoveError, UciMoveError};
 use crate::pieces::*;
 use crate::coord::*;
+use crate::zobrist::KEYS;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum GameErrors {
-    PieceNotOnBoard
+    PieceNotOnBoard,
+    /// A castling right was given but the king or rook are not on the
+    /// back rank on the right sides of each other
+    InvalidCastlingRights,
+    /// The en passant square does not sit behind a pawn of the side not to
+    /// move that could have just double moved
+    InvalidEnPassantSquare
+}
+
+/// The state of the game. Draws that could be claimed, such as by
+/// threefold repetition or the fifty move rule, are treated as claimed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The side that delivered checkmate
+    Checkmate(Side),
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMove,
+    /// Neither side has enough pieces to checkmate
+    InsufficientMaterial,
+    /// Neither side can checkmate with any series of legal moves, such as
+    /// when only bishops on the same colour squares are left
+    DeadPosition
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+
+    pub fn get_winner(&self) -> Option<Side> {
+        match *self {
+            GameStatus::Checkmate(side) => Some(side),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            GameStatus::Ongoing => write!(f, "Game in progress"),
+            GameStatus::Checkmate(side) => write!(f, "{:?} wins by checkmate", side),
+            GameStatus::Stalemate => write!(f, "Draw by stalemate"),
+            GameStatus::ThreefoldRepetition => write!(f, "Draw by threefold repetition"),
+            GameStatus::FiftyMoveRule => write!(f, "Draw by the fifty move rule"),
+            GameStatus::SeventyFiveMove => write!(f, "Draw by the seventy-five move rule"),
+            GameStatus::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            GameStatus::DeadPosition => write!(f, "Draw by dead position")
+        }
+    }
+}
+
+/// Token returned by `Game::make_move` holding everything needed to take
+/// the move back with `Game::unmake_move`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Undo {
+    move_: Move,
+    captured: Option<Piece>,
+    previous_move: Option<Move>,
+    castling_rights: CastlingRights,
+    ep_square: Option<Coord>,
+    halfmove_clock: u32,
+    status: GameStatus
+}
+
+impl Undo {
+    /// Gets the move that was made
+    pub fn get_move(&self) -> Move {
+        self.move_
+    }
+
+    /// Gets the piece taken by the move, if any
+    pub fn get_captured(&self) -> Option<Piece> {
+        self.captured
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Game {
-    board: [Option<Piece>; 64],
-    white_king_loc: Coord,
-    black_king_loc: Coord,
+    /// Squares holding each type of piece, indexed by `PieceType`
+    piece_bbs: [Bitboard; 6],
+    /// Squares holding each side's pieces, indexed by `Side`
+    side_bbs: [Bitboard; 2],
+    /// Squares holding pieces that have not moved yet
+    unmoved_bb: Bitboard,
+    /// The rooks each side may still castle with
+    castling_rights: CastlingRights,
+    /// The square a pawn moves to when taking en passant, set after a pawn
+    /// double move
+    ep_square: Option<Coord>,
+    /// Whether the game is Chess960, which changes how castling moves are
+    /// written
+    chess960: bool,
     current_turn: Side,
     previous_move: Option<Move>,
-    winner: Option<Side>,
+    status: GameStatus,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    /// Zobrist hash of the position, kept up to date as pieces are moved
+    hash: u64,
+    /// Hash of every position reached so far, used to detect repetitions
+    position_history: Vec<u64>,
+    /// Every move made so far with the state needed to take it back
+    move_history: Vec<Undo>,
 }
 
 impl std::fmt::Debug for Game {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut out_str = String::new();
         
-        for (i, op) in self.board.iter().enumerate() {
+        for i in 0..64 {
             if i % 8 == 0 {
                 writeln!(f, "{}", out_str)?;
                 out_str = String::new();
             }
 
-            match op {
-                Some(p) => out_str.push(get_piece_char(p)),
+            match self.get_piece_at(Coord::from_index(i)) {
+                Some(p) => out_str.push(get_piece_char(&p)),
                 None => out_str.push('.')
             }
             out_str.push(' ');
@@ -43,33 +141,125 @@ impl std::fmt::Debug for Game {
     }
 }
 
+/// The board with rank and file labels, drawn in plain letters with White
+/// at the bottom. Use `render::render` for the other ways of drawing it
+impl std::fmt::Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", crate::render::render(self, &crate::render::RenderOptions::default()))
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Games are equal when their positions are the same, no matter how they
+/// were reached
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.piece_bbs == other.piece_bbs &&
+        self.side_bbs == other.side_bbs &&
+        self.current_turn == other.current_turn &&
+        self.castling_rights == other.castling_rights &&
+        self.ep_square == other.ep_square
+    }
+}
+
+impl Eq for Game {}
+
+impl std::hash::Hash for Game {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
 impl Game {
     pub fn new() -> Self {
-        let start_code = "rnbqkbnr\
-                                pppppppp\
-                                ........\
-                                ........\
-                                ........\
-                                ........\
-                                PPPPPPPP\
-                                RNBQKBNR";
+        Game::from_fen(START_FEN).unwrap()
+    }
+
+    /// Creates a Chess960 game from one of the starting positions numbered
+    /// `0` to `959`, using the standard numbering where `518` is the usual
+    /// starting position. Returns `None` for other numbers.
+    pub fn new_chess960(number: u32) -> Option<Self> {
+        if number >= 960 {
+            return None
+        }
+
+        let mut back_rank = [None; 8];
+        let mut n = number as usize;
+
+        // The bishops go on squares of opposite colours
+        back_rank[(n % 4) * 2 + 1] = Some('B');
+        n /= 4;
+        back_rank[(n % 4) * 2] = Some('B');
+        n /= 4;
+
+        // The rest are placed on the empty squares from the a file up
+        let mut place = |index: usize, c: char| {
+            let x = (0..8).filter(|&x| back_rank[x].is_none()).nth(index).unwrap();
+            back_rank[x] = Some(c);
+        };
+
+        place(n % 6, 'Q');
+        n /= 6;
+
+        // Knights' places on the five remaining squares
+        const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+        let (first, second) = KNIGHTS[n];
+        place(second, 'N');
+        place(first, 'N');
+
+        // The king goes between the rooks
+        place(0, 'R');
+        place(0, 'K');
+        place(0, 'R');
+
+        let white: String = back_rank.iter().map(|c| c.unwrap()).collect();
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", 
+            white.to_ascii_lowercase(), 
+            white
+        );
+
+        let mut game = Game::from_fen(&fen).unwrap();
+        game.chess960 = true;
 
-        Game::from_string(start_code, Side::White).unwrap()
+        Some(game)
     }
 
 
     /// Returns the square on the board given by a Coord enum.
     pub fn get_piece_at(&self, coord: Coord) -> Option<Piece> {
-        self.board[coord.get_index()]
+        let side = if bitboard::contains(self.side_bbs[Side::White as usize], coord) {
+            Side::White
+        } else if bitboard::contains(self.side_bbs[Side::Black as usize], coord) {
+            Side::Black
+        } else {
+            return None
+        };
+
+        let piece_type = ALL_PIECE_TYPES.into_iter()
+            .find(|&t| bitboard::contains(self.piece_bbs[t as usize], coord))
+            .unwrap();
+
+        Some(Piece::new(
+            side, 
+            !bitboard::contains(self.unmoved_bb, coord), 
+            piece_type, 
+            coord
+        ))
     }
 
     /// Gets a Piece struct for the White King on the board
     /// 
     /// # Panics
     /// 
-    /// Panics if the square the board thinks the king is on is empty
+    /// Panics if there is no white king on the board
     pub fn get_white_king(&self) -> Piece {
-        self.board[self.white_king_loc.get_index()].unwrap()
+        self.get_piece_at(self.get_king_loc(Side::White)).unwrap()
     }
 
 
@@ -77,9 +267,55 @@ impl Game {
     /// 
     /// # Panics
     /// 
-    /// Panics if the square the board thinks the king is on is empty
+    /// Panics if there is no black king on the board
     pub fn get_black_king(&self) -> Piece {
-        self.board[self.black_king_loc.get_index()].unwrap()
+        self.get_piece_at(self.get_king_loc(Side::Black)).unwrap()
+    }
+
+    /// Gets the square the king of the given side is on
+    pub(crate) fn get_king_loc(&self, side: Side) -> Coord {
+        Coord::from_index(self.get_piece_bb(side, PieceType::King).trailing_zeros() as usize)
+    }
+
+    /// Gets the squares holding pieces of the given side and type
+    pub(crate) fn get_piece_bb(&self, side: Side, piece_type: PieceType) -> Bitboard {
+        self.piece_bbs[piece_type as usize] & self.side_bbs[side as usize]
+    }
+
+    /// Gets the squares holding pieces of the given side
+    pub(crate) fn get_side_bb(&self, side: Side) -> Bitboard {
+        self.side_bbs[side as usize]
+    }
+
+    /// Gets the squares holding any piece
+    pub(crate) fn get_occupied_bb(&self) -> Bitboard {
+        self.side_bbs[0] | self.side_bbs[1]
+    }
+
+    /// Removes the piece on a square, returning it
+    fn remove_piece_at(&mut self, coord: Coord) -> Option<Piece> {
+        let piece = self.get_piece_at(coord)?;
+        let mask = !bitboard::coord_bb(coord);
+
+        self.piece_bbs[piece.get_type() as usize] &= mask;
+        self.side_bbs[piece.get_side() as usize] &= mask;
+        self.unmoved_bb &= mask;
+        self.hash ^= piece_key(piece);
+
+        Some(piece)
+    }
+
+    /// Puts a piece on the square given by its location. The square must
+    /// be empty
+    fn put_piece(&mut self, piece: Piece) {
+        let bb = bitboard::coord_bb(piece.get_loc());
+
+        self.piece_bbs[piece.get_type() as usize] |= bb;
+        self.side_bbs[piece.get_side() as usize] |= bb;
+        if !piece.has_moved() {
+            self.unmoved_bb |= bb;
+        }
+        self.hash ^= piece_key(piece);
     }
 
     /// Gets the previous move
@@ -88,9 +324,14 @@ impl Game {
     }
 
     /// Gets the winner if one side is in checkmate. Returns `None` if
-    /// game is still ongoing
+    /// game is still ongoing or was drawn
     pub fn get_winner(&self) -> Option<Side> {
-        self.winner
+        self.status.get_winner()
+    }
+
+    /// Gets the status of the game, including how it ended if it is over
+    pub fn get_status(&self) -> GameStatus {
+        self.status
     }
 
     /// Gets the side to make a move
@@ -98,6 +339,17 @@ impl Game {
         self.current_turn
     }
 
+    /// Gets the number of halfmoves since the last capture or pawn move
+    pub fn get_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// Gets the fullmove number. Starts at 1 and is incremented after
+    /// each move by Black
+    pub fn get_fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
 
     pub fn apply_move(&mut self, move_: Move) -> Result<(), MoveError> {
         if self.get_valid_moves().contains(&move_) {
@@ -114,83 +366,439 @@ impl Game {
     /// Force the move to be made on the board. There is no check to
     /// ensure the move is valid. Ensure move is valid before calling this
     /// function otherwise unexpected behaviour may be experienced. If unsure
-    /// whether move is valid use the safer `apply_move` function
-    pub fn apply_unchecked_move(&mut self, move_: Move, check_checkmate: bool) -> Self {
+    /// whether move is valid use the safer `apply_move` function.
+    /// 
+    /// Working out whether the game is over requires generating all the
+    /// moves for the next side, so can be skipped with `update_status`
+    pub fn apply_unchecked_move(&mut self, move_: Move, update_status: bool) -> &mut Self {
+        self.make_move(move_);
+
+        if update_status {
+            self.status = self.compute_status();
+        }
+
+        self
+    }
+
+
+    /// Makes the move on the board without checking it is valid and returns
+    /// a token that can be passed to `unmake_move` to take it back.
+    /// 
+    /// Unlike `apply_unchecked_move` the status of the game is not updated,
+    /// which makes this the cheaper option when searching through moves.
+    pub fn make_move(&mut self, move_: Move) -> Undo {
+        let captured = match move_ {
+            Move::Standard(m) => self.get_piece_at(m.to),
+            Move::Promotion(m) => self.get_piece_at(m.to),
+            Move::EnPassant(m) => self.get_piece_at(m.coord_taken),
+            Move::Castle(_) => None
+        };
+
+        let undo = Undo {
+            move_,
+            captured,
+            previous_move: self.previous_move,
+            castling_rights: self.castling_rights,
+            ep_square: self.ep_square,
+            halfmove_clock: self.halfmove_clock,
+            status: self.status
+        };
+
+        // Captures and pawn moves reset the halfmove clock
+        if captured.is_some() || move_.get_piece().is_type(PieceType::Pawn) {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if self.current_turn == Side::Black {
+            self.fullmove_number += 1;
+        }
+
+        // Take out the turn, castling and en passant keys for the old
+        // position, the pieces are updated as they are moved
+        self.hash ^= self.get_state_key();
+
         match move_ {
             Move::Standard(m) => {
-                // Make square "to" have piece
-                self.board[m.to.get_index()] = Some(m.piece.clone().move_to(m.to));
+                // Remove the piece from the old position and anything taken
+                self.remove_piece_at(m.from);
+                self.remove_piece_at(m.to);
 
-                // Remove the piece from the old position
-                self.board[m.from.get_index()] = None;
-
-                // If the piece is a king then the parameters in self need updating
-                if m.piece.is_type(PieceType::King) {
-                    match m.piece.get_side() {
-                        Side::White => self.white_king_loc = m.to,
-                        Side::Black => self.black_king_loc = m.to
-                    }
-                };
+                // Make square "to" have piece
+                self.put_piece(m.piece.clone().move_to(m.to));
             },
             Move::Castle(m) => {
-                // Move king and rook
-                self.board[m.king_to.get_index()] = Some(m.king_piece.clone().move_to(m.king_to));
-                self.board[m.rook_to.get_index()] = Some(m.rook_piece.clone().move_to(m.rook_to));
-
                 // Remove king and rook from old squares
-                self.board[m.king_from.get_index()] = None;
-                self.board[m.rook_from.get_index()] = None;
+                self.remove_piece_at(m.king_from);
+                self.remove_piece_at(m.rook_from);
 
-                // Update the king
-                match m.king_piece.get_side() {
-                    Side::White => self.white_king_loc = m.king_to,
-                    Side::Black => self.black_king_loc = m.king_to
-                };
+                // Move king and rook
+                self.put_piece(m.king_piece.clone().move_to(m.king_to));
+                self.put_piece(m.rook_piece.clone().move_to(m.rook_to));
             },
             Move::Promotion(m) => {
-                // Add promoted piece
-                self.board[m.to.get_index()] = Some(m.new_piece);
+                // Remove old piece and anything taken
+                self.remove_piece_at(m.from);
+                self.remove_piece_at(m.to);
 
-                // Remove old piece
-                self.board[m.from.get_index()] = None;
+                // Add promoted piece
+                self.put_piece(m.new_piece);
             },
             Move::EnPassant(m) => {
-                // Move the pawn
-                self.board[m.to.get_index()] = Some(m.piece.clone().move_to(m.to));
+                // Remove the pawn from the old square and the taken piece
+                self.remove_piece_at(m.from);
+                self.remove_piece_at(m.coord_taken);
 
-                // Remove the pawn from the old square
-                self.board[m.from.get_index()] = None;
-
-                // Remove the taken piece
-                self.board[m.coord_taken.get_index()] = None;
+                // Move the pawn
+                self.put_piece(m.piece.clone().move_to(m.to));
             }
         }
 
+        // Moving the king loses both rights, moving or taking a rook loses
+        // the right to castle with it
+        let side = move_.get_side();
+        if move_.get_piece().is_type(PieceType::King) {
+            self.castling_rights.remove_side(side);
+        }
+        self.castling_rights.remove_rook_at(move_.get_from());
+        self.castling_rights.remove_rook_at(move_.get_to());
+
+        // Only a pawn double move gives an en passant square, which is the
+        // square the pawn passed over
+        self.ep_square = match move_ {
+            Move::Standard(m) if m.piece.is_type(PieceType::Pawn) => {
+                let [x, from_y] = m.from.get_x_and_y();
+                let to_y = m.to.get_x_and_y()[1];
+
+                if (to_y - from_y).abs() == 2 {
+                    Some(Coord::from_x_and_y(x, from_y + side.get_dir()))
+                } else {
+                    None
+                }
+            },
+            _ => None
+        };
+
         // Swap the turn player
         self.current_turn = self.current_turn.other();
 
         // Update the previous move
         self.previous_move = Some(move_);
 
-        if check_checkmate {
-            if self.get_valid_moves().len() == 0 {
-                self.winner = Some(move_.get_side());
+        self.hash ^= self.get_state_key();
+        debug_assert_eq!(self.hash, self.compute_hash(), "Hash out of date after {:?}", move_);
+
+        self.position_history.push(self.hash);
+        self.move_history.push(undo);
+
+        undo
+    }
+
+
+    /// Takes back a move made with `make_move`. Moves must be unmade in the
+    /// reverse order they were made in.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        debug_assert_eq!(self.move_history.last(), Some(&undo), "Moves unmade out of order");
+
+        self.move_history.pop();
+        self.position_history.pop();
+
+        self.hash ^= self.get_state_key();
+
+        match undo.move_ {
+            Move::Standard(m) => {
+                self.remove_piece_at(m.to);
+                self.put_piece(m.piece);
+            },
+            Move::Castle(m) => {
+                self.remove_piece_at(m.king_to);
+                self.remove_piece_at(m.rook_to);
+
+                self.put_piece(m.king_piece);
+                self.put_piece(m.rook_piece);
+            },
+            Move::Promotion(m) => {
+                self.remove_piece_at(m.to);
+                self.put_piece(m.old_piece);
+            },
+            Move::EnPassant(m) => {
+                self.remove_piece_at(m.to);
+                self.put_piece(m.piece);
             }
         }
 
-        *self
+        if let Some(captured) = undo.captured {
+            self.put_piece(captured);
+        }
+
+        self.current_turn = self.current_turn.other();
+
+        if self.current_turn == Side::Black {
+            self.fullmove_number -= 1;
+        }
+
+        self.previous_move = undo.previous_move;
+        self.castling_rights = undo.castling_rights;
+        self.ep_square = undo.ep_square;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.status = undo.status;
+
+        self.hash ^= self.get_state_key();
+        debug_assert_eq!(self.hash, self.compute_hash(), "Hash out of date after unmaking {:?}", undo.move_);
     }
 
 
-    pub fn is_side_in_check(&self, side: Side) -> bool {
-        let other_targets: HashSet<Coord> = get_side_targets(self, side.other());
+    /// Takes back the last move made. Returns the move that was taken back,
+    /// or `None` if no moves have been made
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let undo = *self.move_history.last()?;
+        self.unmake_move(undo);
 
-        let result = other_targets.contains(match side {
-            Side::White => &(self.white_king_loc),
-            Side::Black => &(self.black_king_loc)
-        });
+        Some(undo.move_)
+    }
+
+
+    /// Gets every move made in the game so far, oldest first
+    pub fn get_move_history(&self) -> &[Undo] {
+        &self.move_history
+    }
+
+    /// Works out the status of the game for the current position
+    fn compute_status(&self) -> GameStatus {
+        if self.get_valid_moves().is_empty() {
+            return if self.is_side_in_check(self.current_turn) {
+                GameStatus::Checkmate(self.current_turn.other())
+            } else {
+                GameStatus::Stalemate
+            }
+        }
+
+        if self.halfmove_clock >= 150 {
+            return GameStatus::SeventyFiveMove
+        }
+
+        // Only kings, bishops and knights are left
+        let minor_pieces: Vec<Piece> = self.iter()
+            .filter(|p| !p.is_type(PieceType::King))
+            .collect();
+
+        if minor_pieces.iter().all(|p| p.is_type(PieceType::Bishop) || p.is_type(PieceType::Knight)) {
+            if minor_pieces.len() <= 1 {
+                return GameStatus::InsufficientMaterial
+            }
+
+            // Bishops that are all on the same colour can never checkmate
+            let square_colour = |p: &Piece| {
+                let [x, y] = p.get_loc().get_x_and_y();
+                (x + y) % 2
+            };
+
+            if minor_pieces.iter().all(|p| p.is_type(PieceType::Bishop)) &&
+               minor_pieces.iter().all(|p| square_colour(p) == square_colour(&minor_pieces[0])) {
+                return GameStatus::DeadPosition
+            }
+        }
+
+        if self.get_repetition_count() >= 3 {
+            return GameStatus::ThreefoldRepetition
+        }
+
+        if self.halfmove_clock >= 100 {
+            return GameStatus::FiftyMoveRule
+        }
+
+        GameStatus::Ongoing
+    }
+
+    /// Whether `side` could still checkmate with some series of legal moves,
+    /// which decides if the other side running out of time loses or draws
+    /// under the FIDE rules. This goes by material alone: a lone king can't,
+    /// nor can a single minor piece against a lone king, nor bishops that are
+    /// all on the same colour squares when the other side has only bishops on
+    /// those squares too. Anything else can at least help mate, such as a
+    /// knight against a king and pawn.
+    pub fn has_mating_material(&self, side: Side) -> bool {
+        let pieces: Vec<Piece> = self.iter()
+            .filter(|p| p.is_side(side) && !p.is_type(PieceType::King))
+            .collect();
+        let other_pieces: Vec<Piece> = self.iter()
+            .filter(|p| !p.is_side(side) && !p.is_type(PieceType::King))
+            .collect();
+
+        if pieces.iter().any(|p| !p.is_type(PieceType::Bishop) && !p.is_type(PieceType::Knight)) {
+            return true
+        }
+
+        if pieces.is_empty() || (pieces.len() == 1 && other_pieces.is_empty()) {
+            return false
+        }
+
+        // The other side's pieces can block the king in, unless they are
+        // bishops that can never reach the squares the mate needs
+        let square_colour = |p: &Piece| {
+            let [x, y] = p.get_loc().get_x_and_y();
+            (x + y) % 2
+        };
+
+        !pieces.iter().chain(&other_pieces)
+            .all(|p| p.is_type(PieceType::Bishop) && square_colour(p) == square_colour(&pieces[0]))
+    }
+
+    /// Gets the number of times the current position has been reached,
+    /// including now
+    pub fn get_repetition_count(&self) -> usize {
+        // Positions before the last capture or pawn move can't repeat
+        self.position_history.iter()
+            .rev()
+            .take(self.halfmove_clock as usize + 1)
+            .filter(|h| **h == self.hash)
+            .count()
+    }
+
+    /// Gets the Zobrist hash of the position. Positions that are the same,
+    /// including the side to move, castling rights and en passant square,
+    /// have the same hash.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Works out the hash of the position from scratch
+    fn compute_hash(&self) -> u64 {
+        self.iter().fold(self.get_state_key(), |hash, piece| hash ^ piece_key(piece))
+    }
+
+    /// Hash of the parts of the position other than the pieces
+    fn get_state_key(&self) -> u64 {
+        let mut key = 0;
+
+        for side in [Side::White, Side::Black] {
+            for castling_side in ALL_CASTLING_SIDES {
+                if let Some(x) = self.castling_rights.get_rook_file(side, castling_side) {
+                    key ^= KEYS.castling[side as usize][x as usize];
+                }
+            }
+        }
+
+        if self.current_turn == Side::Black {
+            key ^= KEYS.black_to_move;
+        }
+
+        if let Some(coord) = self.ep_square {
+            key ^= KEYS.en_passant[coord.get_x_and_y()[0] as usize];
+        }
+
+        key
+    }
+
+    /// Gets the rooks each side may still castle with
+    pub fn get_castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    /// Replaces the castling rights, such as to stop a side castling in a
+    /// position loaded with `from_string`. Each right needs the king and
+    /// the rook on the back rank with the rook on the side it castles to.
+    pub fn set_castling_rights(&mut self, castling_rights: CastlingRights) -> Result<(), GameErrors> {
+        if !self.is_valid_castling_rights(castling_rights) {
+            return Err(GameErrors::InvalidCastlingRights)
+        }
+
+        self.hash ^= self.get_state_key();
+        self.castling_rights = castling_rights;
+        self.update_state_hash();
+
+        Ok(())
+    }
+
+    /// Gets the square a pawn moves to when taking en passant, which is
+    /// set after a pawn double move whether or not the capture is possible
+    pub fn get_en_passant_square(&self) -> Option<Coord> {
+        self.ep_square
+    }
+
+    /// Replaces the en passant square. The square must be behind a pawn of
+    /// the side not to move that could have just double moved.
+    pub fn set_en_passant_square(&mut self, ep_square: Option<Coord>) -> Result<(), GameErrors> {
+        if ep_square.is_some_and(|coord| !self.is_valid_en_passant_square(coord)) {
+            return Err(GameErrors::InvalidEnPassantSquare)
+        }
+
+        self.hash ^= self.get_state_key();
+        self.ep_square = ep_square;
+        self.update_state_hash();
+
+        Ok(())
+    }
+
+    /// Adds the state key back into the hash after the state has been
+    /// changed outside of a move, then updates what depends on the hash
+    fn update_state_hash(&mut self) {
+        self.hash ^= self.get_state_key();
+
+        if let Some(last) = self.position_history.last_mut() {
+            *last = self.hash;
+        }
+        self.status = self.compute_status();
+    }
+
+    /// Whether the king and rook of every right are on the back rank, with
+    /// the rook on the side of the king it castles to
+    fn is_valid_castling_rights(&self, castling_rights: CastlingRights) -> bool {
+        [Side::White, Side::Black].into_iter().all(|side| {
+            let king = self.get_king_loc(side);
+            let [king_x, king_y] = king.get_x_and_y();
+
+            ALL_CASTLING_SIDES.into_iter().all(|castling_side| {
+                let rook = match castling_side {
+                    CastlingSide::KingSide => castling_rights.get_rook_coord(side, castling_side)
+                        .filter(|c| c.get_x_and_y()[0] > king_x),
+                    CastlingSide::QueenSide => castling_rights.get_rook_coord(side, castling_side)
+                        .filter(|c| c.get_x_and_y()[0] < king_x)
+                };
+
+                match rook {
+                    Some(rook) => king_y == side.get_back_rank_y() &&
+                        bitboard::contains(self.get_piece_bb(side, PieceType::Rook), rook),
+                    None => !castling_rights.has(side, castling_side)
+                }
+            })
+        })
+    }
+
+    /// Whether the square is behind a pawn of the side not to move that
+    /// could have just double moved
+    fn is_valid_en_passant_square(&self, coord: Coord) -> bool {
+        let side = self.current_turn.other();
+        let dir = side.get_dir();
+        let [x, y] = coord.get_x_and_y();
+
+        if y != side.get_back_rank_y() + 2*dir {
+            return false
+        }
+
+        let occupied = self.get_occupied_bb();
 
-        result
+        bitboard::contains(self.get_piece_bb(side, PieceType::Pawn), Coord::from_x_and_y(x, y + dir)) &&
+            !bitboard::contains(occupied, coord) &&
+            !bitboard::contains(occupied, Coord::from_x_and_y(x, y - dir))
+    }
+
+    /// Whether the game is Chess960
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Sets whether the game is Chess960. The rules are the same for
+    /// standard starting positions, but castling is written as the king
+    /// taking its own rook in UCI notation
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+
+    pub fn is_side_in_check(&self, side: Side) -> bool {
+        is_square_attacked(self, self.get_king_loc(side), side.other(), self.get_occupied_bb())
     }
 
 
@@ -218,23 +826,70 @@ impl Game {
 
 
     pub fn get_valid_moves(&self) -> Vec<Move> {
-        self.iter()
-            .filter(|x| x.is_side(self.current_turn))
-            .map(|x| get_piece_moves(self, x))
-            .fold(Vec::<Move>::new(), |mut acc, mut x| {acc.append(&mut x); acc})
+        let mut moves = MoveList::new();
+        self.generate_legal_moves(GenMode::All, &mut moves);
+
+        moves.to_vec()
+    }
+
+    /// Writes the move in the long algebraic form used by UCI. In Chess960
+    /// castling is written as the king taking its own rook, e.g. `e1h1`,
+    /// otherwise this is the same as `Move::to_uci`
+    pub fn move_to_uci(&self, move_: &Move) -> String {
+        match move_ {
+            Move::Castle(m) if self.chess960 => format!("{}{}", m.king_from, m.rook_from),
+            _ => move_.to_uci()
+        }
+    }
+
+    /// Finds the legal move written in the long algebraic form used by UCI,
+    /// e.g. `e2e4` or `e7e8q`. Castling can also be written as the king
+    /// taking its own rook, which is the only form accepted in Chess960.
+    pub fn parse_uci_move(&self, uci: &str) -> Result<Move, UciMoveError> {
+        let uci = uci.trim();
+
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(UciMoveError::InvalidSyntax)
+        }
+
+        let from: Coord = uci[0..2].parse().map_err(|_| UciMoveError::InvalidSyntax)?;
+        let to: Coord = uci[2..4].parse().map_err(|_| UciMoveError::InvalidSyntax)?;
+
+        let promotion = match uci[4..].to_ascii_lowercase().as_str() {
+            "" => None,
+            "q" => Some(PieceType::Queen),
+            "r" => Some(PieceType::Rook),
+            "b" => Some(PieceType::Bishop),
+            "n" => Some(PieceType::Knight),
+            _ => return Err(UciMoveError::InvalidSyntax)
+        };
+
+        self.get_valid_moves()
+            .into_iter()
+            .find(|m| match m {
+                Move::Castle(c) => {
+                    let to_matches = c.rook_from == to || (!self.chess960 && c.king_to == to);
+                    c.king_from == from && to_matches && promotion.is_none()
+                },
+                Move::Promotion(p) => {
+                    p.from == from && p.to == to && Some(p.new_piece.get_type()) == promotion
+                },
+                _ => m.get_from() == from && m.get_to() == to && promotion.is_none()
+            })
+            .ok_or(UciMoveError::IllegalMove)
     }
 }
 
 pub struct GameIter<'a> {
     game: &'a Game,
-    i: usize
+    squares: BitIter
 }
 
 impl Game {
     pub fn iter(&self) -> GameIter<'_> {
         GameIter {
             game: self,
-            i: 0
+            squares: BitIter(self.get_occupied_bb())
         }
     }
 }
@@ -243,86 +898,375 @@ impl<'a> Iterator for GameIter<'a> {
     type Item = Piece;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.i += 1;
-
-        if self.i <= self.game.board.len() {
-            match self.game.board[self.i-1] {
-                Some(item) => Some(item),
-                None => self.next()
-            }
-        } else {
-           None
-        }
+        self.squares.next().and_then(|coord| self.game.get_piece_at(coord))
     }
 }
 
-/// Implimentation for I/O of games
+// Implimentation for I/O of games
+
+/// FEN string for the standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The six space separated fields of a FEN string
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FenField {
+    PiecePlacement,
+    SideToMove,
+    CastlingRights,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber
+}
 
-/// Error types from parsing strings representing the board
-#[derive(Debug)]
-pub enum BoardStringParseError {
-    UnexpectedCharacter,
+/// Error types from parsing FEN strings and strings representing the board
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FenParseError {
+    /// The string did not have 4 or 6 space separated fields
+    WrongFieldCount(usize),
+    /// A character that is not allowed in `field`. The `index` is the
+    /// position of the character within the field
+    UnexpectedCharacter { field: FenField, index: usize, character: char },
+    /// The piece placement did not have 8 ranks
+    WrongRankCount(usize),
+    /// A rank in the piece placement did not describe 8 squares. Ranks are
+    /// numbered 1 to 8
+    BadRankLength(usize),
+    /// A field was empty or its number was out of range
+    InvalidField(FenField),
+    /// A castling right was given but the king or rook are not on their
+    /// starting squares
+    InvalidCastlingRight(char),
+    /// The en passant square does not sit behind a pawn that could have
+    /// just double moved
+    InvalidEnPassantSquare,
     NoWhiteKing,
     NoBlackKing
 }
 
+impl std::fmt::Display for FenParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::WrongFieldCount(n) => 
+                write!(f, "expected 4 or 6 fields but found {}", n),
+            Self::UnexpectedCharacter { field, index, character } => 
+                write!(f, "unexpected character '{}' at index {} of {:?}", character, index, field),
+            Self::WrongRankCount(n) => 
+                write!(f, "expected 8 ranks but found {}", n),
+            Self::BadRankLength(rank) => 
+                write!(f, "rank {} does not have 8 squares", rank),
+            Self::InvalidField(field) => 
+                write!(f, "invalid {:?} field", field),
+            Self::InvalidCastlingRight(c) => 
+                write!(f, "castling right '{}' does not match the king and rook positions", c),
+            Self::InvalidEnPassantSquare => 
+                write!(f, "en passant square does not follow a double pawn move"),
+            Self::NoWhiteKing => write!(f, "there is no white king"),
+            Self::NoBlackKing => write!(f, "there is no black king")
+        }
+    }
+}
+
+impl std::error::Error for FenParseError {}
+
 impl Game {
-    pub fn from_string(code: &str, start_side: Side) -> Result<Self, BoardStringParseError>{
+    /// Creates a game from a string of 64 piece characters, `.` for empty
+    /// squares, starting from a8. Every piece is unmoved and each king on
+    /// its back rank may castle with the outermost rook on either side of
+    /// it, which can be changed with `set_castling_rights`.
+    pub fn from_string(code: &str, start_side: Side) -> Result<Self, FenParseError>{
         let board = board_from_string(code)?;
 
-        let mut white_king_loc: Option<Coord> = None;
-        let mut black_king_loc: Option<Coord> = None;
+        let mut castling_rights = CastlingRights::NONE;
+        for c in "KQkq".chars() {
+            if let Ok((side, castling_side, rook_x)) = parse_castling_right(&board, c, 0) {
+                castling_rights.set(side, castling_side, rook_x);
+            }
+        }
+
+        Game::from_board(board, start_side, castling_rights, None, 0, 1)
+    }
+
+    /// Creates a game from a FEN string. The halfmove clock and fullmove
+    /// number may be left off, in which case they default to `0` and `1`.
+    /// 
+    /// Pieces are unmoved if they are on their starting squares, and for
+    /// kings and rooks only if they have a castling right.
+    pub fn from_fen(fen: &str) -> Result<Self, FenParseError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenParseError::WrongFieldCount(fields.len()))
+        }
 
-        for space in board {
-            // If its an empty space then continue
-            if space.is_none() {continue}
+        let mut board = board_from_fen_placement(fields[0])?;
+
+        let side = match fields[1] {
+            "w" => Side::White,
+            "b" => Side::Black,
+            "" => return Err(FenParseError::InvalidField(FenField::SideToMove)),
+            other => {
+                let index = if other.starts_with(['w', 'b']) {1} else {0};
+                return Err(FenParseError::UnexpectedCharacter {
+                    field: FenField::SideToMove,
+                    index,
+                    character: other.chars().nth(index).unwrap()
+                })
+            }
+        };
 
-            let piece = space.unwrap();
+        // Only pieces on their starting squares can be unmoved. Kings and
+        // rooks also need the matching castling right
+        for (i, square) in board.iter_mut().enumerate() {
+            if let Some(piece) = square {
+                let unmoved = START_BOARD[i].is_some_and(|p| 
+                    p.get_side() == piece.get_side() && p.get_type() == piece.get_type()
+                ) && !piece.is_type(PieceType::King) && !piece.is_type(PieceType::Rook);
 
-            // Check if the piece is a king to get their locations
-            if piece.is_type(PieceType::King) {
-                match piece.get_side() {
-                    Side::White => white_king_loc = Some(piece.get_loc()),
-                    Side::Black => black_king_loc = Some(piece.get_loc())
+                *piece = Piece::new(piece.get_side(), !unmoved, piece.get_type(), piece.get_loc());
+            }
+        }
+
+        let castling_rights = parse_castling_rights(&board, fields[2])?;
+
+        // Kings and rooks that can castle haven't moved
+        for side in [Side::White, Side::Black] {
+            for castling_side in ALL_CASTLING_SIDES {
+                if let Some(rook) = castling_rights.get_rook_coord(side, castling_side) {
+                    let king = board.iter().flatten()
+                        .find(|p| p.is_type(PieceType::King) && p.is_side(side))
+                        .map(|p| p.get_loc())
+                        .unwrap();
+
+                    for loc in [king, rook] {
+                        if let Some(piece) = &mut board[loc.get_index()] {
+                            *piece = Piece::new(side, false, piece.get_type(), loc);
+                        }
+                    }
                 }
             }
         }
 
-        // Ensure there are kings
-        if white_king_loc.is_none() 
-            {return Err(BoardStringParseError::NoWhiteKing)}
-        if black_king_loc.is_none()
-            {return Err(BoardStringParseError::NoBlackKing)}
-
-        Ok(Self{
-            board, 
-            white_king_loc: white_king_loc.unwrap(), 
-            black_king_loc: black_king_loc.unwrap(),
-            current_turn: start_side,
+        let ep_square = parse_en_passant_square(side, fields[3])?;
+
+        let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+            (
+                parse_fen_number(fields[4], FenField::HalfmoveClock)?,
+                parse_fen_number(fields[5], FenField::FullmoveNumber)?
+            )
+        } else {
+            (0, 1)
+        };
+
+        if fullmove_number == 0 {
+            return Err(FenParseError::InvalidField(FenField::FullmoveNumber))
+        }
+
+        Game::from_board(board, side, castling_rights, ep_square, halfmove_clock, fullmove_number)
+    }
+
+    /// Returns the FEN string representing the current position
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for y in (0..8).rev() {
+            let mut empty = 0;
+
+            for x in 0..8 {
+                match self.get_piece_at(Coord::from_x_and_y(x, y)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(get_piece_char(&piece));
+                    },
+                    None => empty += 1
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+
+            if y > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.current_turn {
+            Side::White => " w ",
+            Side::Black => " b "
+        });
+
+        let mut castling = String::new();
+        for side in [Side::White, Side::Black] {
+            castling.push_str(&self.get_castling_field(side));
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        fen.push(' ');
+        match self.ep_square {
+            Some(coord) => fen.push_str(&coord.to_string()),
+            None => fen.push('-')
+        }
+
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+
+        fen
+    }
+
+    /// Writes a side's castling rights in X-FEN, kingside first. Rights are
+    /// written as `K` or `Q` when the rook is the outermost one on that side
+    /// of the king, otherwise by the rook's file as in Shredder-FEN.
+    fn get_castling_field(&self, side: Side) -> String {
+        let king_x = self.get_king_loc(side).get_x_and_y()[0];
+        let y = side.get_back_rank_y();
+
+        let rooks_x: Vec<i8> = BitIter(self.get_piece_bb(side, PieceType::Rook))
+            .map(|c| c.get_x_and_y())
+            .filter(|[_, rook_y]| *rook_y == y)
+            .map(|[x, _]| x)
+            .collect();
+
+        ALL_CASTLING_SIDES.into_iter()
+            .filter_map(|castling_side| self.castling_rights.get_rook_file(side, castling_side))
+            .map(|x| {
+                let c = if x > king_x && rooks_x.iter().all(|&other| other <= x) {
+                    'K'
+                } else if x < king_x && rooks_x.iter().all(|&other| other >= x) {
+                    'Q'
+                } else {
+                    (b'A' + x as u8) as char
+                };
+
+                match side {
+                    Side::White => c,
+                    Side::Black => c.to_ascii_lowercase()
+                }
+            })
+            .collect()
+    }
+
+    fn from_board(board: [Option<Piece>; 64],
+                  current_turn: Side,
+                  castling_rights: CastlingRights,
+                  ep_square: Option<Coord>,
+                  halfmove_clock: u32,
+                  fullmove_number: u32)
+                  -> Result<Self, FenParseError> {
+        let mut game = Self{
+            piece_bbs: [bitboard::EMPTY; 6],
+            side_bbs: [bitboard::EMPTY; 2],
+            unmoved_bb: bitboard::EMPTY,
+            castling_rights,
+            ep_square,
+            current_turn,
             previous_move: None,
-            winner: None
-        })
+            status: GameStatus::Ongoing,
+            halfmove_clock,
+            fullmove_number,
+            chess960: false,
+            hash: 0,
+            position_history: Vec::new(),
+            move_history: Vec::new()
+        };
+
+        for piece in board.into_iter().flatten() {
+            game.put_piece(piece);
+        }
+
+        // Ensure there are kings
+        if game.get_piece_bb(Side::White, PieceType::King) == bitboard::EMPTY
+            {return Err(FenParseError::NoWhiteKing)}
+        if game.get_piece_bb(Side::Black, PieceType::King) == bitboard::EMPTY
+            {return Err(FenParseError::NoBlackKing)}
+
+        if ep_square.is_some_and(|coord| !game.is_valid_en_passant_square(coord)) {
+            return Err(FenParseError::InvalidEnPassantSquare)
+        }
+
+        // Castling with a king or rook that isn't on its usual square can
+        // only happen in Chess960
+        for side in [Side::White, Side::Black] {
+            let king_x = game.get_king_loc(side).get_x_and_y()[0];
+
+            for castling_side in ALL_CASTLING_SIDES {
+                if castling_rights.get_rook_file(side, castling_side)
+                    .is_some_and(|x| king_x != 4 || (x != 0 && x != 7)) {
+                    game.chess960 = true;
+                }
+            }
+        }
+
+        // The pieces were hashed as they were put on the board
+        game.hash ^= game.get_state_key();
+        game.position_history.push(game.hash);
+        game.status = game.compute_status();
+
+        Ok(game)
+    }
+}
+
+/// Pieces of the standard starting position, used to work out which pieces
+/// can still be unmoved when loading a FEN string
+const START_BOARD: [Option<Piece>; 64] = {
+    let back_rank = [
+        PieceType::Rook, PieceType::Knight, PieceType::Bishop, PieceType::Queen,
+        PieceType::King, PieceType::Bishop, PieceType::Knight, PieceType::Rook
+    ];
+    let mut board: [Option<Piece>; 64] = [None; 64];
+
+    let mut x = 0;
+    while x < 8 {
+        board[x] = Some(Piece::new(Side::Black, false, back_rank[x], Coord::from_index(x)));
+        board[8 + x] = Some(Piece::new(Side::Black, false, PieceType::Pawn, Coord::from_index(8 + x)));
+        board[48 + x] = Some(Piece::new(Side::White, false, PieceType::Pawn, Coord::from_index(48 + x)));
+        board[56 + x] = Some(Piece::new(Side::White, false, back_rank[x], Coord::from_index(56 + x)));
+        x += 1;
     }
+
+    board
+};
+
+/// Zobrist key for a piece on its square
+fn piece_key(piece: Piece) -> u64 {
+    KEYS.pieces[piece.get_side() as usize][piece.get_type() as usize][piece.get_loc().get_index()]
 }
 
-fn board_from_string(code: &str) -> Result<[Option<Piece>; 64], BoardStringParseError> {
+fn piece_type_from_char(c: char) -> Option<PieceType> {
+    match c.to_ascii_uppercase() {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'B' => Some(PieceType::Bishop),
+        'N' => Some(PieceType::Knight),
+        'R' => Some(PieceType::Rook),
+        'P' => Some(PieceType::Pawn),
+        _ => None
+    }
+}
+
+fn board_from_string(code: &str) -> Result<[Option<Piece>; 64], FenParseError> {
     let mut board:[Option<Piece>; 64] = [None; 64];
 
     // Loop through all the chars in the input string with an index
     for (i, c) in code.chars().enumerate() {
+        // If its an empty square then set it in the board and continue
+        if c == '.' {
+            board[i] = None;
+            continue
+        }
+
         // Work out the piece type
-        let piece_type = match c.to_ascii_uppercase() {
-            'K' => PieceType::King,
-            'Q' => PieceType::Queen,
-            'B' => PieceType::Bishop,
-            'N' => PieceType::Knight,
-            'R' => PieceType::Rook,
-            'P' => PieceType::Pawn,
-            // If its an empty square then set it in the board and continue
-            '.' => {board[i] = None; continue},
-            // Error handling
-            _ => {return Err(BoardStringParseError::UnexpectedCharacter)}
-        };
+        let piece_type = piece_type_from_char(c).ok_or(
+            FenParseError::UnexpectedCharacter { 
+                field: FenField::PiecePlacement, 
+                index: i, 
+                character: c 
+            }
+        )?;
     
         // Work out the side of the piece
         let side = if c.is_ascii_uppercase() { Side::White } 
@@ -335,4 +1279,158 @@ fn board_from_string(code: &str) -> Result<[Option<Piece>; 64], BoardStringParse
     }
 
     Ok(board)
-}
\ No newline at end of file
+}
+
+fn board_from_fen_placement(placement: &str) -> Result<[Option<Piece>; 64], FenParseError> {
+    let mut board:[Option<Piece>; 64] = [None; 64];
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenParseError::WrongRankCount(ranks.len()))
+    }
+
+    // Index of the character within the whole field for error reporting
+    let mut index = 0;
+
+    for (rank_i, rank) in ranks.iter().enumerate() {
+        let mut x = 0;
+
+        for c in rank.chars() {
+            if x >= 8 {
+                return Err(FenParseError::BadRankLength(8 - rank_i))
+            }
+
+            match c {
+                '1'..='8' => x += c.to_digit(10).unwrap() as usize,
+                _ => {
+                    let piece_type = piece_type_from_char(c).ok_or(
+                        FenParseError::UnexpectedCharacter { 
+                            field: FenField::PiecePlacement, 
+                            index, 
+                            character: c 
+                        }
+                    )?;
+
+                    let side = if c.is_ascii_uppercase() { Side::White } 
+                                     else { Side::Black };
+
+                    let loc = Coord::from_index(rank_i * 8 + x);
+                    board[loc.get_index()] = Some(Piece::new(side, false, piece_type, loc));
+                    x += 1;
+                }
+            }
+
+            index += 1;
+        }
+
+        if x != 8 {
+            return Err(FenParseError::BadRankLength(8 - rank_i))
+        }
+
+        // Account for the '/'
+        index += 1;
+    }
+
+    Ok(board)
+}
+
+/// Reads the castling rights field. Rights can be given as `KQkq`, meaning
+/// the outermost rook on that side of the king, or by the file of the rook
+/// as in Shredder-FEN and X-FEN.
+fn parse_castling_rights(board: &[Option<Piece>; 64], field: &str) -> Result<CastlingRights, FenParseError> {
+    let mut castling_rights = CastlingRights::NONE;
+
+    if field == "-" {
+        return Ok(castling_rights)
+    }
+
+    if field.is_empty() {
+        return Err(FenParseError::InvalidField(FenField::CastlingRights))
+    }
+
+    for (i, c) in field.chars().enumerate() {
+        let (side, castling_side, rook_x) = parse_castling_right(board, c, i)?;
+        castling_rights.set(side, castling_side, rook_x);
+    }
+
+    Ok(castling_rights)
+}
+
+/// Finds the rook a single castling right character refers to. The `index`
+/// is the position of the character within the field for error reporting
+fn parse_castling_right(board: &[Option<Piece>; 64], c: char, index: usize) -> Result<(Side, CastlingSide, i8), FenParseError> {
+    let side = if c.is_ascii_uppercase() { Side::White } else { Side::Black };
+    let y = side.get_back_rank_y();
+
+    let is_piece = |x: i8, piece_type: PieceType| {
+        board[Coord::from_x_and_y(x, y).get_index()]
+            .is_some_and(|p| p.is_type(piece_type) && p.is_side(side))
+    };
+
+    let king_x = (0..8).find(|&x| is_piece(x, PieceType::King))
+        .ok_or(FenParseError::InvalidCastlingRight(c))?;
+
+    let rook_x = match c.to_ascii_uppercase() {
+        'K' => (king_x + 1..8).rev().find(|&x| is_piece(x, PieceType::Rook)),
+        'Q' => (0..king_x).find(|&x| is_piece(x, PieceType::Rook)),
+        file @ 'A'..='H' => Some((file as u8 - b'A') as i8).filter(|&x| is_piece(x, PieceType::Rook)),
+        _ => return Err(FenParseError::UnexpectedCharacter { 
+            field: FenField::CastlingRights, 
+            index, 
+            character: c 
+        })
+    }.ok_or(FenParseError::InvalidCastlingRight(c))?;
+
+    let castling_side = if rook_x > king_x { CastlingSide::KingSide } else { CastlingSide::QueenSide };
+
+    Ok((side, castling_side, rook_x))
+}
+
+/// Reads the en passant field. Whether a pawn could have just double moved
+/// past the square is checked once the board is set up
+fn parse_en_passant_square(side: Side, field: &str) -> Result<Option<Coord>, FenParseError> {
+    if field == "-" {
+        return Ok(None)
+    }
+
+    let chars: Vec<char> = field.chars().collect();
+
+    if chars.len() != 2 {
+        return Err(FenParseError::InvalidField(FenField::EnPassant))
+    }
+
+    if !('a'..='h').contains(&chars[0]) {
+        return Err(FenParseError::UnexpectedCharacter { 
+            field: FenField::EnPassant, 
+            index: 0, 
+            character: chars[0] 
+        })
+    }
+
+    // The pawn that double moved belongs to the side that is not to play
+    let expected_rank = match side {
+        Side::White => '6',
+        Side::Black => '3'
+    };
+    if chars[1] != expected_rank {
+        return Err(FenParseError::UnexpectedCharacter { 
+            field: FenField::EnPassant, 
+            index: 1, 
+            character: chars[1] 
+        })
+    }
+
+    Ok(field.parse().ok())
+}
+
+fn parse_fen_number(field: &str, field_type: FenField) -> Result<u32, FenParseError> {
+    if let Some((i, c)) = field.chars().enumerate().find(|(_, c)| !c.is_ascii_digit()) {
+        return Err(FenParseError::UnexpectedCharacter { 
+            field: field_type, 
+            index: i, 
+            character: c 
+        })
+    }
+
+    field.parse::<u32>().map_err(|_| FenParseError::InvalidField(field_type))
+}
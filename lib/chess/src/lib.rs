@@ -1,10 +1,35 @@
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod perft_tests;
+#[cfg(test)]
+mod rules_tests;
+#[cfg(test)]
+mod tb_tests;
 
+pub mod book;
 pub mod coord;
+pub mod epd;
+pub mod eval;
+pub mod pgn;
+pub mod render;
+pub mod tb;
 mod pieces;
+mod bitboard;
+mod castling;
 mod game;
+mod movegen;
 mod moves;
+mod packed;
+mod perft;
+mod san;
+mod zobrist;
 
-pub use game::Game;
-pub use pieces::Side;
+pub use castling::{CastlingRights, CastlingSide};
+pub use game::{Game, GameStatus, GameErrors, Undo, FenParseError, FenField, START_FEN};
+pub use pieces::{Side, Piece, PieceType};
+pub use movegen::{MoveList, GenMode, CheckInfo, MAX_MOVES};
+pub use moves::{Move, MoveError, UciMoveError};
+pub use packed::PackedMove;
+pub use perft::{perft, perft_divide};
+pub use san::SanError;
@@ -0,0 +1,392 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::bitboard::{self, Bitboard, BitIter};
+use crate::coord::Coord;
+use crate::game::Game;
+use crate::moves::{Move, StandardMove, Promotion, EnPassant, get_attackers, get_castle_moves, is_in_check_after_move, is_square_attacked};
+use crate::pieces::{Piece, Side, PieceType, PROMOTABLE_PIECES};
+
+/// Most moves a `MoveList` can hold. No legal position has more than 218
+/// moves.
+pub const MAX_MOVES: usize = 256;
+
+/// Fills the unused part of a `MoveList`
+const NO_MOVE: Move = Move::Standard(StandardMove {
+    piece: Piece::new(Side::White, false, PieceType::Pawn, Coord::from_index(0)),
+    from: Coord::from_index(0),
+    to: Coord::from_index(0)
+});
+
+/// Fixed capacity list of moves that lives on the stack, so generating
+/// moves doesn't allocate. Derefs to a slice of the moves in the list.
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self { moves: [NO_MOVE; MAX_MOVES], len: 0 }
+    }
+
+    /// Adds a move to the end of the list
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list already holds `MAX_MOVES` moves
+    pub fn push(&mut self, move_: Move) {
+        self.moves[self.len] = move_;
+        self.len += 1;
+    }
+
+    /// Removes the move at `index`, replacing it with the last move
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn swap_remove(&mut self, index: usize) -> Move {
+        let move_ = self[index];
+        self.moves[index] = self.moves[self.len - 1];
+        self.len -= 1;
+
+        move_
+    }
+
+    /// Removes every move
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Keeps only the moves that `f` returns `true` for, in the same order
+    pub fn retain(&mut self, mut f: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+
+        for i in 0..self.len {
+            if f(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Which moves to generate
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GenMode {
+    All,
+    /// Moves that take a piece, including en passant and promotions that
+    /// capture
+    Captures,
+    /// Moves that don't take a piece, including castling and promotions
+    /// that don't capture
+    Quiets,
+    /// Moves that might get the side to move out of check: king moves, and
+    /// with a single checker, taking it or blocking its line to the king.
+    /// When the side to move isn't in check this is the same as `All`.
+    Evasions,
+}
+
+/// Pieces giving check to the side to move and pieces pinned to its king,
+/// worked out once per position so each move can be checked cheaply
+#[derive(Clone, Debug)]
+pub struct CheckInfo {
+    king: Coord,
+    checkers: Bitboard,
+    /// For each square holding a pinned piece, the squares it can move to
+    /// without leaving its king in check: those between the king and the
+    /// pinning piece, and the pinning piece itself. Empty for pieces that
+    /// aren't pinned.
+    pin_rays: [Bitboard; 64],
+}
+
+impl CheckInfo {
+    fn new(game: &Game) -> Self {
+        let side = game.get_side_to_play();
+        let other = side.other();
+        let king = game.get_king_loc(side);
+        let occupied = game.get_occupied_bb();
+
+        let checkers = get_attackers(game, king, other, occupied);
+
+        // Sliding pieces that would attack the king if nothing was in the way
+        let queens = game.get_piece_bb(other, PieceType::Queen);
+        let snipers =
+            (bitboard::bishop_attacks(king, bitboard::EMPTY) & (game.get_piece_bb(other, PieceType::Bishop) | queens)) |
+            (bitboard::rook_attacks(king, bitboard::EMPTY) & (game.get_piece_bb(other, PieceType::Rook) | queens));
+
+        let mut pin_rays = [bitboard::EMPTY; 64];
+
+        for sniper in BitIter(snipers) {
+            let blockers = bitboard::between(king, sniper) & occupied;
+
+            // Only a lone piece of the side to move in the way is pinned
+            if blockers.count_ones() == 1 && blockers & game.get_side_bb(side) != bitboard::EMPTY {
+                pin_rays[blockers.trailing_zeros() as usize] =
+                    bitboard::between(king, sniper) | bitboard::coord_bb(sniper);
+            }
+        }
+
+        Self { king, checkers, pin_rays }
+    }
+
+    /// Whether the side to move is in check
+    pub fn is_check(&self) -> bool {
+        self.checkers != bitboard::EMPTY
+    }
+
+    /// Whether the side to move is in check from two pieces at once, so only
+    /// a king move can get out of it
+    pub fn is_double_check(&self) -> bool {
+        self.checkers.count_ones() > 1
+    }
+
+    /// Gets the squares of the pieces giving check
+    pub fn get_checkers(&self) -> Vec<Coord> {
+        BitIter(self.checkers).collect()
+    }
+
+    /// Whether the piece on the square is pinned to its king
+    pub fn is_pinned(&self, coord: Coord) -> bool {
+        self.pin_rays[coord.get_index()] != bitboard::EMPTY
+    }
+
+    /// Gets the squares a pinned piece can move to without exposing its
+    /// king, or `None` if the piece on the square isn't pinned
+    pub fn get_pin_ray(&self, coord: Coord) -> Option<Vec<Coord>> {
+        Some(self.pin_rays[coord.get_index()])
+            .filter(|&ray| ray != bitboard::EMPTY)
+            .map(|ray| BitIter(ray).collect())
+    }
+
+    /// Squares a piece other than the king must move to when in check
+    fn get_evasion_targets(&self) -> Bitboard {
+        match self.checkers.count_ones() {
+            0 => !bitboard::EMPTY,
+            1 => self.checkers | bitboard::between(self.king, Coord::from_index(self.checkers.trailing_zeros() as usize)),
+            _ => bitboard::EMPTY
+        }
+    }
+}
+
+impl Game {
+    /// Works out which pieces give check and which are pinned for the side
+    /// to move, for use with `is_legal_move`
+    pub fn get_check_info(&self) -> CheckInfo {
+        CheckInfo::new(self)
+    }
+
+    /// Adds the pseudo-legal moves of the side to move to `moves`. These
+    /// follow how the pieces move but may leave the king in check, which
+    /// `is_legal_move` can filter out. Castling is only generated when it
+    /// is fully legal.
+    pub fn generate_pseudo_legal_moves(&self, mode: GenMode, moves: &mut MoveList) {
+        let evasion_targets = match mode {
+            GenMode::Evasions => CheckInfo::new(self).get_evasion_targets(),
+            _ => !bitboard::EMPTY
+        };
+
+        generate(self, mode, evasion_targets, moves);
+    }
+
+    /// Adds the legal moves of the side to move to `moves`
+    pub fn generate_legal_moves(&self, mode: GenMode, moves: &mut MoveList) {
+        let check_info = CheckInfo::new(self);
+        let start = moves.len();
+
+        generate(self, mode, check_info.get_evasion_targets(), moves);
+
+        let mut i = start;
+        while i < moves.len() {
+            if self.is_legal_move(&moves[i], &check_info) {
+                i += 1;
+            } else {
+                moves.swap_remove(i);
+            }
+        }
+    }
+
+    /// Checks whether a pseudo-legal move of the side to move leaves its
+    /// king safe, using the checkers and pins in `check_info`
+    pub fn is_legal_move(&self, move_: &Move, check_info: &CheckInfo) -> bool {
+        let from = move_.get_from();
+        let to = move_.get_to();
+
+        match move_ {
+            // Both are rare and can uncover attacks in unusual ways, so are
+            // checked in full
+            Move::Castle(_) | Move::EnPassant(_) => !is_in_check_after_move(self, *move_),
+            _ if move_.get_piece().is_type(PieceType::King) => {
+                // The king can't hide from a slider on the line it is
+                // moving along
+                let occupied = self.get_occupied_bb() & !bitboard::coord_bb(from);
+                !is_square_attacked(self, to, move_.get_side().other(), occupied)
+            },
+            _ => {
+                let pin_ray = check_info.pin_rays[from.get_index()];
+
+                bitboard::contains(check_info.get_evasion_targets(), to) &&
+                    (pin_ray == bitboard::EMPTY || bitboard::contains(pin_ray, to))
+            }
+        }
+    }
+}
+
+/// Adds the moves of the side to move, with pieces other than the king only
+/// moving to squares in `evasion_targets`
+fn generate(game: &Game, mode: GenMode, evasion_targets: Bitboard, moves: &mut MoveList) {
+    let side = game.get_side_to_play();
+    let ours = game.get_side_bb(side);
+    let theirs = game.get_side_bb(side.other());
+    let occupied = game.get_occupied_bb();
+
+    let king_targets = match mode {
+        GenMode::All | GenMode::Evasions => !ours,
+        GenMode::Captures => theirs,
+        GenMode::Quiets => !occupied
+    };
+    let targets = king_targets & evasion_targets;
+
+    for from in BitIter(ours) {
+        let piece = game.get_piece_at(from).unwrap();
+
+        let attacks = match piece.get_type() {
+            PieceType::King => bitboard::king_attacks(from) & king_targets,
+            PieceType::Queen => bitboard::queen_attacks(from, occupied) & targets,
+            PieceType::Bishop => bitboard::bishop_attacks(from, occupied) & targets,
+            PieceType::Rook => bitboard::rook_attacks(from, occupied) & targets,
+            PieceType::Knight => bitboard::knight_attacks(from) & targets,
+            PieceType::Pawn => {
+                add_pawn_moves(game, piece, mode, targets, moves);
+                continue
+            }
+        };
+
+        for to in BitIter(attacks) {
+            moves.push(Move::Standard(StandardMove::new(piece, from, to)));
+        }
+    }
+
+    // Castling is never a capture and is never possible while in check
+    let can_castle = match mode {
+        GenMode::All | GenMode::Quiets => true,
+        GenMode::Evasions => evasion_targets == !bitboard::EMPTY,
+        GenMode::Captures => false
+    };
+
+    if can_castle && game.get_castling_rights().has_any(side) {
+        get_castle_moves(game, game.get_piece_at(game.get_king_loc(side)).unwrap(), moves);
+    }
+}
+
+fn add_pawn_moves(game: &Game, piece: Piece, mode: GenMode, targets: Bitboard, moves: &mut MoveList) {
+    let side = piece.get_side();
+    let from = piece.get_loc();
+    let [x, y] = from.get_x_and_y();
+    let dir = side.get_dir();
+
+    // Pawns never stand on their last rank
+    let one_step = Coord::from_x_and_y(x, y + dir);
+    let occupied = game.get_occupied_bb();
+
+    let mut add = |to: Coord| {
+        if to.get_x_and_y()[1] == side.get_promotion_y() {
+            for promote_to in PROMOTABLE_PIECES {
+                moves.push(Move::Promotion(Promotion::new(
+                    piece,
+                    Piece::new(side, true, promote_to, to),
+                    from,
+                    to
+                )));
+            }
+        } else {
+            moves.push(Move::Standard(StandardMove::new(piece, from, to)));
+        }
+    };
+
+    for to in BitIter(bitboard::pawn_attacks(from, side) & game.get_side_bb(side.other()) & targets) {
+        add(to);
+    }
+
+    if !bitboard::contains(occupied, one_step) {
+        if bitboard::contains(targets & !occupied, one_step) {
+            add(one_step);
+        }
+
+        if !piece.has_moved() && (0..=7).contains(&(y + 2*dir)) {
+            let two_step = Coord::from_x_and_y(x, y + 2*dir);
+
+            if bitboard::contains(targets & !occupied, two_step) {
+                add(two_step);
+            }
+        }
+    }
+
+    if mode == GenMode::Quiets {
+        return
+    }
+
+    if let Some(ep_square) = game.get_en_passant_square() {
+        if bitboard::contains(bitboard::pawn_attacks(from, side), ep_square) {
+            let coord_taken = Coord::from_x_and_y(ep_square.get_x_and_y()[0], y);
+
+            // When in check, taking en passant either takes the checking
+            // pawn or blocks on the en passant square
+            if targets & (bitboard::coord_bb(coord_taken) | bitboard::coord_bb(ep_square)) != bitboard::EMPTY {
+                moves.push(Move::EnPassant(EnPassant {
+                    piece,
+                    from,
+                    to: ep_square,
+                    piece_taken: game.get_piece_at(coord_taken).unwrap(),
+                    coord_taken
+                }));
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashSet;
-
+use crate::bitboard::{self, Bitboard, BitIter};
 use crate::pieces::{Piece, Side, PieceType, PROMOTABLE_PIECES};
+use crate::castling::ALL_CASTLING_SIDES;
 use crate::coord::Coord;
 use crate::game::Game;
+use crate::movegen::MoveList;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Move {
@@ -43,6 +44,24 @@ impl Move {
     pub fn get_side(&self) -> Side {
         self.get_piece().get_side()
     }
+
+    /// Writes the move in the long algebraic form used by UCI, the start and
+    /// end squares followed by the promotion piece if there is one, e.g.
+    /// `e2e4` or `e7e8q`. Castling is written as the king's move.
+    pub fn to_uci(&self) -> String {
+        let mut out = format!("{}{}", self.get_from(), self.get_to());
+
+        if let Self::Promotion(m) = self {
+            out.push(match m.new_piece.get_type() {
+                PieceType::Queen => 'q',
+                PieceType::Rook => 'r',
+                PieceType::Bishop => 'b',
+                _ => 'n'
+            });
+        }
+
+        out
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -112,313 +131,161 @@ pub enum MoveError{
     InvalidMove
 }
 
-/// Returns target squares for moves in a given direction from a source square.
-/// The searching checks for other pieces on the board to prevent a target
-/// being behind another piece.
-/// 
-/// The `king_limit` is to limit the search to one square as the king usually
-/// can only move one square.
-fn get_target_locs_in_dir(game: &Game,
-                          source: Coord, 
-                          dir: [i8; 2], 
-                          king_limit: bool,
-                          cur_side: Side) 
-                          -> Vec<Coord> {
-    let [mut x, mut y] = source.get_x_and_y();
-
-    let mut targets: Vec<Coord> = Vec::new();
-
-    #[allow(while_true)]
-    'searcher: while true {
-        x += dir[0];
-        y += dir[1];
-
-        // If the new pos is off the board then break out
-        if x < 0 || x > 7 || y < 0 || y > 7 {
-            break 'searcher
-        }
-
-        match game.get_piece_at(Coord::from_x_and_y(x, y)) {
-            Some(piece) => {
-                if piece.get_side() != cur_side {
-                    targets.push(Coord::from_x_and_y(x, y))
-                };
-
-                break 'searcher;
-            },
-            None => targets.push(Coord::from_x_and_y(x, y))
-        }
-
-        // If its a king then they only need to search through once
-        if king_limit { break 'searcher }
-    }
-
-    targets
-}
-
-fn get_king_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-    let dirs = [
-        [1,1], [-1,1], [1,-1], [-1,-1], 
-        [1,0], [0,1], [-1,0], [0,-1]
-    ];
-
-    for dir in dirs {
-        targets.append(&mut get_target_locs_in_dir(
-            game, 
-            piece.get_loc(), 
-            dir, 
-            true, 
-            piece.get_side()
-        ));
-    }
-
-    targets
+/// Reasons a move in UCI notation can't be read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UciMoveError {
+    /// The string isn't two squares and an optional promotion piece
+    InvalidSyntax,
+    /// No legal move matches the string
+    IllegalMove,
 }
 
-fn get_queen_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-    let dirs = [
-        [1,1], [-1,1], [1,-1], [-1,-1], 
-        [1,0], [0,1], [-1,0], [0,-1]
-    ];
-
-    for dir in dirs {
-        targets.append(&mut get_target_locs_in_dir(
-            game, 
-            piece.get_loc(), 
-            dir, 
-            false, 
-            piece.get_side()
-        ));
-    }
-
-    targets
-}
-
-
-pub fn get_bishop_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-    let dirs = [[1,1], [-1,1], [1,-1], [-1,-1]];
-
-    for dir in dirs {
-        targets.append(&mut get_target_locs_in_dir(
-            game, 
-            piece.get_loc(), 
-            dir, 
-            false, 
-            piece.get_side()
-        ));
-    }
-
-    targets
-}
-
-
-pub fn get_rook_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-    let dirs = [[1,0], [0,1], [-1,0], [0,-1]];
-
-    for dir in dirs {
-        targets.append(&mut get_target_locs_in_dir(
-            game, 
-            piece.get_loc(), 
-            dir, 
-            false, 
-            piece.get_side()
-        ));
-    }
-
-    targets
-}
-
-
-pub fn get_knight_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-    let dirs = [
-        [2,1], [2,-1], [1,2], [1,-2], 
-        [-2, 1], [-2,-1], [-1,2], [-1,-2]
-    ];
-
-    for dir in dirs {
-        let [mut x, mut y] = piece.get_loc().get_x_and_y();
-
-        x += dir[0];
-        y += dir[1];
-
-        // If the new pos is off the board then continue to next square
-        if x < 0 || x > 7 || y < 0 || y > 7 {continue}
-
-        match game.get_piece_at(Coord::from_x_and_y(x, y)) {
-            Some(other_piece) => {
-                if other_piece.get_side() != piece.get_side() {
-                    targets.push(Coord::from_x_and_y(x, y))
-                }
-            },
-            None => targets.push(Coord::from_x_and_y(x, y))
+impl std::fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSyntax => write!(f, "move is not in UCI notation"),
+            Self::IllegalMove => write!(f, "no legal move matches")
         }
     }
-
-    targets
 }
 
-fn get_pawn_standard_targets(game: &Game, piece: Piece) -> Vec<Coord> {
-    let mut targets: Vec<Coord> = Vec::new();
-
-    let dir = match piece.get_side() {
-        Side::White => 1,
-        Side::Black => -1
-    };
-
-    for dx in [-1, 1] {
-        let x = piece.get_loc().get_x_and_y()[0] + dx;
-        let y = piece.get_loc().get_x_and_y()[1] + dir;
+impl std::error::Error for UciMoveError {}
 
-        // If the new pos is off the board then continue
-        if x < 0 || x > 7 || y < 0 || y > 7 {continue}
+/// Returns the pieces of side `by` that attack the square, given the
+/// squares in `occupied` block sliding pieces
+pub fn get_attackers(game: &Game, coord: Coord, by: Side, occupied: Bitboard) -> Bitboard {
+    let queens = game.get_piece_bb(by, PieceType::Queen);
 
-        match game.get_piece_at(Coord::from_x_and_y(x, y)) {
-            Some(other_piece) => {
-                if other_piece.get_side() != piece.get_side() {
-                    targets.push(Coord::from_x_and_y(x, y))
-                }
-            },
-            None => {}
-        }
-    }
-
-    targets
+    // Attacks are symmetric so look outward from the square for each
+    // type of piece
+    (bitboard::knight_attacks(coord) & game.get_piece_bb(by, PieceType::Knight)) |
+    (bitboard::king_attacks(coord) & game.get_piece_bb(by, PieceType::King)) |
+    (bitboard::pawn_attacks(coord, by.other()) & game.get_piece_bb(by, PieceType::Pawn)) |
+    (bitboard::bishop_attacks(coord, occupied) & (game.get_piece_bb(by, PieceType::Bishop) | queens)) |
+    (bitboard::rook_attacks(coord, occupied) & (game.get_piece_bb(by, PieceType::Rook) | queens))
 }
 
-
-pub fn get_piece_standard_targets(game: &Game, piece: Piece) -> HashSet<Coord> {
-    match piece.get_type() {
-        PieceType::King => HashSet::from_iter(get_king_targets(game, piece)),
-        PieceType::Queen => HashSet::from_iter(get_queen_targets(game, piece)),
-        PieceType::Bishop => HashSet::from_iter(get_bishop_targets(game, piece)),
-        PieceType::Rook => HashSet::from_iter(get_rook_targets(game, piece)),
-        PieceType::Knight => HashSet::from_iter(get_knight_targets(game, piece)),
-        PieceType::Pawn => HashSet::from_iter(get_pawn_standard_targets(game, piece))
-    }
+/// Returns whether any piece of side `by` attacks the square
+pub fn is_square_attacked(game: &Game, coord: Coord, by: Side, occupied: Bitboard) -> bool {
+    get_attackers(game, coord, by, occupied) != bitboard::EMPTY
 }
 
-pub fn get_side_targets(game: &Game, side: Side) -> HashSet<Coord> {
-    let mut targets: HashSet<Coord> = HashSet::new();
-
-    for piece in game.iter() {
-        if piece.is_side(side) {
-            targets.extend(get_piece_standard_targets(game, piece))
-        }
-    }
+/// Returns the squares a piece attacks that are empty or hold an opponent's
+/// piece. Pawns only target squares they can take on.
+fn get_piece_standard_targets(game: &Game, piece: Piece) -> Bitboard {
+    let loc = piece.get_loc();
+    let occupied = game.get_occupied_bb();
+
+    let attacks = match piece.get_type() {
+        PieceType::King => bitboard::king_attacks(loc),
+        PieceType::Queen => bitboard::queen_attacks(loc, occupied),
+        PieceType::Bishop => bitboard::bishop_attacks(loc, occupied),
+        PieceType::Rook => bitboard::rook_attacks(loc, occupied),
+        PieceType::Knight => bitboard::knight_attacks(loc),
+        PieceType::Pawn => bitboard::pawn_attacks(loc, piece.get_side()) & 
+                           game.get_side_bb(piece.get_side().other())
+    };
 
-    targets
+    attacks & !game.get_side_bb(piece.get_side())
 }
 
 fn get_pawn_moves(game: &Game, piece: Piece) -> Vec<Move> {
     let mut moves: Vec<Move> = Vec::new();
 
-    let dir = match piece.get_side() {
-        Side::White => 1,
-        Side::Black => -1
-    };
+    let dir = piece.get_side().get_dir();
+    let occupied = game.get_occupied_bb();
 
     let [x, y] = piece.get_loc().get_x_and_y();
-    if game.get_piece_at(Coord::from_x_and_y(x, y + dir)).is_none() {
+    if !(0..=7).contains(&(y + dir)) {
+        return moves
+    }
+
+    let one_step = Coord::from_x_and_y(x, y + dir);
+    if !bitboard::contains(occupied, one_step) {
         moves.push(Move::Standard(StandardMove { 
             piece, 
             from: piece.get_loc(), 
-            to: Coord::from_x_and_y(x, y + dir) 
+            to: one_step
         }));
 
         // If the square is empty and the pawn hasn't move then it can double move
-        if !piece.has_moved() && 
-           game.get_piece_at(Coord::from_x_and_y(x, y + 2*dir)).is_none() {
-            moves.push(Move::Standard(StandardMove { 
-                piece, 
-                from: piece.get_loc(), 
-                to: Coord::from_x_and_y(x, y + 2*dir) 
-            }));
+        if !piece.has_moved() && (0..=7).contains(&(y + 2*dir)) {
+            let two_step = Coord::from_x_and_y(x, y + 2*dir);
+
+            if !bitboard::contains(occupied, two_step) {
+                moves.push(Move::Standard(StandardMove { 
+                    piece, 
+                    from: piece.get_loc(), 
+                    to: two_step
+                }));
+            }
         }
-
-        // If the square is at the end of the board then it can promote
     }
 
     moves
 }
 
-fn get_castle_moves(game: &Game, piece: Piece) -> Vec<Move> {
-    let mut moves: Vec<Move> = Vec::new();
+/// Squares on rank `y` between files `from_x` and `to_x`, including both
+fn rank_span(y: i8, from_x: i8, to_x: i8) -> Bitboard {
+    (from_x.min(to_x)..=from_x.max(to_x))
+        .fold(bitboard::EMPTY, |bb, x| bb | bitboard::coord_bb(Coord::from_x_and_y(x, y)))
+}
 
-    let y = match piece.get_side() {
-        Side::White => 0,
-        Side::Black => 7
-    };
+/// Castling moves with each rook the side has the right to castle with. The
+/// king always ends on the c or g file with the rook next to it on the
+/// inside, so this works for Chess960 as well as standard chess.
+pub(crate) fn get_castle_moves(game: &Game, piece: Piece, moves: &mut MoveList) {
+    let side = piece.get_side();
+    let king_from = piece.get_loc();
+    let [king_x, y] = king_from.get_x_and_y();
+
+    let occupied = game.get_occupied_bb();
+    let is_safe = |coord: Coord| !is_square_attacked(game, coord, side.other(), occupied);
+
+    let castling_rights = game.get_castling_rights();
+
+    for castling_side in ALL_CASTLING_SIDES {
+        let Some(rook_from) = castling_rights.get_rook_coord(side, castling_side) else {
+            continue
+        };
+        let rook_x = rook_from.get_x_and_y()[0];
+
+        let (king_to_x, rook_to_x) = castling_side.get_king_and_rook_to_x();
+
+        // Every square the king and rook cross or land on must be empty,
+        // apart from the king and rook themselves
+        let needs_empty = (rank_span(y, king_x, king_to_x) | rank_span(y, rook_x, rook_to_x)) & 
+            !bitboard::coord_bb(king_from) & !bitboard::coord_bb(rook_from);
+        if needs_empty & occupied != bitboard::EMPTY {
+            continue
+        }
 
-    let targets = get_side_targets(game, piece.get_side().other());
-
-    // Short castle
-    // Check Rook hasn't moved
-    if game.get_piece_at(Coord::from_x_and_y(7, y))
-           .map(|p| p.is_type(PieceType::Rook) && 
-                           !p.has_moved() && 
-                           // Strictly speaking this last check is not 
-                           // neccessary as a piece that hasn't moved in the 
-                           // correct square should be of the same type
-                           p.is_side(piece.get_side())) 
-           .unwrap_or(false) &&
-    // Check the spaces are empty
-    [5, 6].map(|x| game.get_piece_at(Coord::from_x_and_y(x, y)).is_none())
-          .into_iter()
-          .fold(true, |acc, x| acc && x) &&
-    // Check the spaces the king move aren't in check            
-    [5, 6].map(|x| !targets.contains(&Coord::from_x_and_y(x, y)))
-          .into_iter()
-          .fold(true, |acc, x| acc && x) {
-        moves.push(Move::Castle(Castle::new(
-            piece, 
-            game.get_piece_at(Coord::from_x_and_y(7, y)).unwrap(),
-            piece.get_loc(),
-            Coord::from_x_and_y(7, y),
-            Coord::from_x_and_y(6, y),
-            Coord::from_x_and_y(5, y)
-        )))
-    }
+        // The king can't castle out of, through or into check
+        let king_path = rank_span(y, king_x, king_to_x);
+        if !BitIter(king_path).all(is_safe) {
+            continue
+        }
 
+        let move_ = Move::Castle(Castle::new(
+            piece,
+            game.get_piece_at(rook_from).unwrap(),
+            king_from,
+            rook_from,
+            Coord::from_x_and_y(king_to_x, y),
+            Coord::from_x_and_y(rook_to_x, y)
+        ));
 
-    // Check long castle
-    if game.get_piece_at(Coord::from_x_and_y(0, y))
-           .map(|p| p.is_type(PieceType::Rook) && 
-                           !p.has_moved() && 
-                           // Strictly speaking this last check is not 
-                           // neccessary as a piece that hasn't moved in the 
-                           // correct square should be of the same type
-                           p.is_side(piece.get_side())) 
-           .unwrap_or(false) &&
-    // Check the spaces are empty
-    [1, 2, 3].map(|x| game.get_piece_at(Coord::from_x_and_y(x, y)).is_none())
-          .into_iter()
-          .fold(true, |acc, x| acc && x) &&
-    // Check the spaces the king move aren't in check            
-    [2, 3].map(|x| !targets.contains(&Coord::from_x_and_y(x, y)))
-          .into_iter()
-          .fold(true, |acc, x| acc && x) {
-        moves.push(Move::Castle(Castle::new(
-            piece, 
-            game.get_piece_at(Coord::from_x_and_y(0, y)).unwrap(),
-            piece.get_loc(),
-            Coord::from_x_and_y(0, y),
-            Coord::from_x_and_y(2, y),
-            Coord::from_x_and_y(3, y)
-        )))
+        // In Chess960 moving the rook can uncover an attack on the king
+        if !is_in_check_after_move(game, move_) {
+            moves.push(move_);
+        }
     }
-
-    moves
 }
 
 pub fn get_piece_moves(game: &Game, piece: Piece) -> Vec<Move> {
     let mut moves: Vec<Move> = Vec::new();
 
-    for target in get_piece_standard_targets(game, piece) {
+    for target in BitIter(get_piece_standard_targets(game, piece)) {
         let move_: Move = Move::Standard(StandardMove::new(piece, piece.get_loc(), target));
 
         // Move is only valid if not in check after
@@ -445,34 +312,37 @@ pub fn get_piece_moves(game: &Game, piece: Piece) -> Vec<Move> {
             }
         }
 
-        if game.get_previous_move()
-        .map(
-            |m| m.get_piece().is_type(PieceType::Pawn) && // If the piece in the previous move was a pawn
-            ((m.get_from().get_x_and_y()[1] - m.get_to().get_x_and_y()[1]).abs() == 2) && // If the previous move was a double move
-            ((piece.get_loc().get_x_and_y()[0] - m.get_to().get_x_and_y()[0]).abs() == 1) && // If pawn is in adjacent column
-            (m.get_to().get_x_and_y()[1] == piece.get_loc().get_x_and_y()[1]) // If piece is in the same row
-        ).unwrap_or(false) {
-            moves.push(Move::EnPassant(EnPassant { 
-                piece, 
-                from: piece.get_loc(), 
-                to: Coord::from_x_and_y(
-                    game.get_previous_move().unwrap().get_piece().get_loc().get_x_and_y()[0], 
-                    piece.get_loc().get_x_and_y()[1] + piece.get_side().get_dir()), 
-                piece_taken: game.get_piece_at(game.get_previous_move().unwrap().get_to()).unwrap(), 
-                coord_taken: game.get_previous_move().unwrap().get_to()
-            }))
+        // Only the side to move can take en passant, with a pawn diagonally
+        // behind the en passant square
+        if let Some(ep_square) = game.get_en_passant_square().filter(|_| piece.is_side(game.get_side_to_play())) {
+            let [x, y] = piece.get_loc().get_x_and_y();
+            let [ep_x, ep_y] = ep_square.get_x_and_y();
+
+            if (x - ep_x).abs() == 1 && y + piece.get_side().get_dir() == ep_y {
+                let coord_taken = Coord::from_x_and_y(ep_x, y);
+                let move_ = Move::EnPassant(EnPassant { 
+                    piece, 
+                    from: piece.get_loc(), 
+                    to: ep_square, 
+                    piece_taken: game.get_piece_at(coord_taken).unwrap(), 
+                    coord_taken
+                });
+
+                // Taking both pawns off the rank can uncover an attack
+                if !is_in_check_after_move(game, move_) {
+                    moves.push(move_);
+                }
+            }
         }
     }
 
     // Check for castling
-    // Making assumption that the unmoved Rooks are in the corner
-    // This will need to be changed for chess 960
-    if piece.is_type(PieceType::King) && !piece.has_moved() {
-        moves.append(&mut get_castle_moves(game, piece))
+    if piece.is_type(PieceType::King) && game.get_castling_rights().has_any(piece.get_side()) {
+        let mut castles = MoveList::new();
+        get_castle_moves(game, piece, &mut castles);
+        moves.extend(castles)
     }
 
-    // Check for enPassent
-    
     moves
 }
 
@@ -493,6 +363,35 @@ fn add_promotions(old_move: Move, moves: &mut Vec<Move>) {
     }
 }
 
-fn is_in_check_after_move(game: &Game, move_: Move) -> bool {
-    (*game).clone().apply_unchecked_move(move_, false).is_side_in_check(move_.get_side())
+/// Checks whether making the move leaves its side in check. Rather than
+/// making the move, the squares that would be occupied afterwards are
+/// worked out and the king's square is checked for attackers.
+pub(crate) fn is_in_check_after_move(game: &Game, move_: Move) -> bool {
+    let side = move_.get_side();
+
+    let (vacated, filled, taken) = match move_ {
+        Move::Standard(m) => 
+            (bitboard::coord_bb(m.from), bitboard::coord_bb(m.to), bitboard::coord_bb(m.to)),
+        Move::Promotion(m) => 
+            (bitboard::coord_bb(m.from), bitboard::coord_bb(m.to), bitboard::coord_bb(m.to)),
+        Move::EnPassant(m) => 
+            (bitboard::coord_bb(m.from) | bitboard::coord_bb(m.coord_taken), 
+             bitboard::coord_bb(m.to), 
+             bitboard::coord_bb(m.coord_taken)),
+        Move::Castle(m) => 
+            (bitboard::coord_bb(m.king_from) | bitboard::coord_bb(m.rook_from),
+             bitboard::coord_bb(m.king_to) | bitboard::coord_bb(m.rook_to),
+             bitboard::EMPTY)
+    };
+
+    let king_loc = if move_.get_piece().is_type(PieceType::King) {
+        move_.get_to()
+    } else {
+        game.get_king_loc(side)
+    };
+
+    let occupied = (game.get_occupied_bb() & !vacated) | filled;
+
+    // Taken pieces are still on the board in `game` so can't be counted
+    get_attackers(game, king_loc, side.other(), occupied) & !(taken | filled) != bitboard::EMPTY
 }
@@ -0,0 +1,187 @@
+use crate::castling::CastlingSide;
+use crate::coord::Coord;
+use crate::game::Game;
+use crate::moves::{Move, StandardMove, Castle, Promotion, EnPassant};
+use crate::pieces::{Piece, PieceType};
+
+const FROM_SHIFT: u16 = 0;
+const TO_SHIFT: u16 = 6;
+const FLAGS_SHIFT: u16 = 12;
+const SQUARE_MASK: u16 = 0x3F;
+
+const NORMAL: u16 = 0;
+const DOUBLE_PUSH: u16 = 1;
+const CASTLE: u16 = 2;
+const EN_PASSANT: u16 = 3;
+/// Promotions are this plus the index of the piece in `PROMOTION_TYPES`
+const PROMOTION: u16 = 4;
+
+const PROMOTION_TYPES: [PieceType; 4] = [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen];
+
+/// A move packed into 16 bits: 6 bits for the square moved from, 6 bits for
+/// the square moved to and 4 bits of flags for double pawn moves, castling,
+/// en passant and the promotion piece.
+///
+/// Castling is stored as the king moving to its rook's square so Chess960
+/// castles can be told apart. Unlike `Move` no pieces are stored, so the
+/// game the move was made in is needed to turn it back into a `Move`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    /// Doesn't represent any move, as the squares are the same
+    pub const NONE: Self = Self(0);
+
+    fn new(from: Coord, to: Coord, flags: u16) -> Self {
+        Self((from.get_index() as u16) << FROM_SHIFT | (to.get_index() as u16) << TO_SHIFT | flags << FLAGS_SHIFT)
+    }
+
+    /// Makes a packed move from its 16 bit form, as returned by `to_u16`.
+    /// Returns `None` if the flags aren't ones a move can have
+    pub fn from_u16(bits: u16) -> Option<Self> {
+        let packed = Self(bits);
+
+        match packed.get_flags() < PROMOTION + PROMOTION_TYPES.len() as u16 {
+            true => Some(packed),
+            false => None
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        self.0
+    }
+
+    pub fn get_from(&self) -> Coord {
+        Coord::from_index((self.0 >> FROM_SHIFT & SQUARE_MASK) as usize)
+    }
+
+    /// Gets the square moved to, which for castling is the rook's square
+    pub fn get_to(&self) -> Coord {
+        Coord::from_index((self.0 >> TO_SHIFT & SQUARE_MASK) as usize)
+    }
+
+    fn get_flags(&self) -> u16 {
+        self.0 >> FLAGS_SHIFT
+    }
+
+    pub fn is_double_push(&self) -> bool {
+        self.get_flags() == DOUBLE_PUSH
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.get_flags() == CASTLE
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.get_flags() == EN_PASSANT
+    }
+
+    /// Gets the type of piece a pawn is promoted to, if the move is a
+    /// promotion
+    pub fn get_promotion(&self) -> Option<PieceType> {
+        self.get_flags()
+            .checked_sub(PROMOTION)
+            .map(|i| PROMOTION_TYPES[i as usize])
+    }
+}
+
+impl From<Move> for PackedMove {
+    fn from(move_: Move) -> Self {
+        match move_ {
+            Move::Standard(m) => {
+                let is_double_push = m.piece.is_type(PieceType::Pawn) &&
+                    (m.to.get_x_and_y()[1] - m.from.get_x_and_y()[1]).abs() == 2;
+
+                Self::new(m.from, m.to, if is_double_push { DOUBLE_PUSH } else { NORMAL })
+            },
+            Move::Castle(m) => Self::new(m.king_from, m.rook_from, CASTLE),
+            Move::Promotion(m) => {
+                let index = PROMOTION_TYPES.iter()
+                    .position(|&t| t == m.new_piece.get_type())
+                    .unwrap();
+
+                Self::new(m.from, m.to, PROMOTION + index as u16)
+            },
+            Move::EnPassant(m) => Self::new(m.from, m.to, EN_PASSANT)
+        }
+    }
+}
+
+/// Written like a UCI move, with castling as the king taking its rook
+impl std::fmt::Display for PackedMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.get_from(), self.get_to())?;
+
+        match self.get_promotion() {
+            Some(PieceType::Queen) => write!(f, "q"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(_) => write!(f, "n"),
+            None => Ok(())
+        }
+    }
+}
+
+impl Game {
+    /// Turns a packed move back into a `Move` using the pieces on the board.
+    /// Returns `None` for `PackedMove::NONE` or if the pieces the move needs
+    /// aren't there, but otherwise doesn't check the move is legal.
+    pub fn unpack_move(&self, packed: PackedMove) -> Option<Move> {
+        let from = packed.get_from();
+        let to = packed.get_to();
+        if from == to {
+            return None
+        }
+
+        let piece = self.get_piece_at(from)?;
+
+        if packed.is_castle() {
+            let rook = self.get_piece_at(to)
+                .filter(|r| r.is_type(PieceType::Rook) && r.is_side(piece.get_side()))?;
+
+            if !piece.is_type(PieceType::King) {
+                return None
+            }
+
+            let [king_x, y] = from.get_x_and_y();
+            let castling_side = if to.get_x_and_y()[0] > king_x {
+                CastlingSide::KingSide
+            } else {
+                CastlingSide::QueenSide
+            };
+            let (king_to_x, rook_to_x) = castling_side.get_king_and_rook_to_x();
+
+            return Some(Move::Castle(Castle::new(
+                piece,
+                rook,
+                from,
+                to,
+                Coord::from_x_and_y(king_to_x, y),
+                Coord::from_x_and_y(rook_to_x, y)
+            )))
+        }
+
+        if packed.is_en_passant() {
+            let coord_taken = Coord::from_x_and_y(to.get_x_and_y()[0], from.get_x_and_y()[1]);
+
+            return Some(Move::EnPassant(EnPassant {
+                piece,
+                from,
+                to,
+                piece_taken: self.get_piece_at(coord_taken)?,
+                coord_taken
+            }))
+        }
+
+        if let Some(promotion) = packed.get_promotion() {
+            return Some(Move::Promotion(Promotion::new(
+                piece,
+                Piece::new(piece.get_side(), true, promotion, to),
+                from,
+                to
+            )))
+        }
+
+        Some(Move::Standard(StandardMove::new(piece, from, to)))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::game::Game;
+use crate::movegen::{GenMode, MoveList};
+use crate::moves::Move;
+
+/// Counts the number of positions reachable from `game` in exactly `depth`
+/// moves. Comparing the counts against known values is a good way to find
+/// bugs in move generation.
+pub fn perft(game: &Game, depth: u32) -> u64 {
+    perft_inner(&mut game.clone(), depth)
+}
+
+/// Runs perft for each move from `game`, returning each move with the
+/// number of positions reachable after it. Useful for narrowing down which
+/// move a wrong perft count comes from.
+pub fn perft_divide(game: &Game, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new()
+    }
+
+    let mut game = game.clone();
+
+    game.get_valid_moves()
+        .into_iter()
+        .map(|move_| {
+            let undo = game.make_move(move_);
+            let nodes = perft_inner(&mut game, depth - 1);
+            game.unmake_move(undo);
+
+            (move_, nodes)
+        })
+        .collect()
+}
+
+fn perft_inner(game: &mut Game, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1
+    }
+
+    let mut moves = MoveList::new();
+    game.generate_legal_moves(GenMode::All, &mut moves);
+
+    // No need to make the moves to count the leaves
+    if depth == 1 {
+        return moves.len() as u64
+    }
+
+    let mut nodes = 0;
+    for move_ in moves {
+        let undo = game.make_move(move_);
+        nodes += perft_inner(game, depth - 1);
+        game.unmake_move(undo);
+    }
+
+    nodes
+}
@@ -0,0 +1,103 @@
+//! Perft node counts for the standard test positions from
+//! <https://www.chessprogramming.org/Perft_Results>. Depths are kept low
+//! enough to run quickly in debug builds.
+
+use crate::game::*;
+use crate::perft::*;
+
+fn check_perft(fen: &str, expected: &[u64]) {
+    let game = Game::from_fen(fen).unwrap();
+
+    for (depth, &nodes) in expected.iter().enumerate() {
+        assert_eq!(perft(&game, depth as u32 + 1), nodes, "depth {} of {}", depth + 1, fen);
+    }
+}
+
+#[test]
+fn perft_start_position() {
+    check_perft(START_FEN, &[20, 400, 8902, 197281]);
+}
+
+#[test]
+fn perft_kiwipete() {
+    check_perft(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862]
+    );
+}
+
+#[test]
+fn perft_position_3() {
+    check_perft(
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238]
+    );
+}
+
+#[test]
+fn perft_position_4() {
+    check_perft(
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467]
+    );
+}
+
+#[test]
+fn perft_position_4_mirrored() {
+    check_perft(
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        &[6, 264, 9467]
+    );
+}
+
+#[test]
+fn perft_position_5() {
+    check_perft(
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379]
+    );
+}
+
+#[test]
+fn perft_position_6() {
+    check_perft(
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890]
+    );
+}
+
+#[test]
+fn perft_divide_sums_to_perft() {
+    let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let divide = perft_divide(&game, 2);
+
+    assert_eq!(divide.len(), 48);
+    assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), perft(&game, 2));
+}
+
+// Chess960 positions from https://www.chessprogramming.org/Chess960_Perft_Results
+
+#[test]
+fn perft_chess960_1() {
+    check_perft("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189]);
+}
+
+#[test]
+fn perft_chess960_2() {
+    check_perft("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002]);
+}
+
+#[test]
+fn perft_chess960_3() {
+    check_perft("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471]);
+}
+
+#[test]
+fn perft_chess960_4() {
+    check_perft("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440]);
+}
+
+#[test]
+fn perft_chess960_5() {
+    check_perft("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", &[28, 1120, 31058]);
+}
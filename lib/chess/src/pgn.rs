@@ -0,0 +1,582 @@
+//! Reading and writing games in Portable Game Notation.
+//!
+//! A PGN file holds any number of games, each made of tag pairs such as
+//! `[White "Kasparov"]` followed by the moves in SAN. Moves can have
+//! comments, numeric annotation glyphs (NAGs) and variations, which are
+//! alternatives to the move they follow and can contain variations of
+//! their own.
+
+use crate::game::{Game, GameStatus, FenParseError, START_FEN};
+use crate::moves::Move;
+use crate::pieces::Side;
+use crate::san::SanError;
+
+/// Tags every PGN game should have, in the order they are written
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Longest line written in the movetext
+const MAX_LINE_LENGTH: usize = 79;
+
+/// How a game finished
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PgnResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// The game is still going or the result isn't known
+    Unknown,
+}
+
+impl PgnResult {
+    pub fn from_status(status: GameStatus) -> Self {
+        match status {
+            GameStatus::Ongoing => Self::Unknown,
+            GameStatus::Checkmate(Side::White) => Self::WhiteWins,
+            GameStatus::Checkmate(Side::Black) => Self::BlackWins,
+            _ => Self::Draw
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(Self::WhiteWins),
+            "0-1" => Some(Self::BlackWins),
+            "1/2-1/2" => Some(Self::Draw),
+            "*" => Some(Self::Unknown),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for PgnResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WhiteWins => write!(f, "1-0"),
+            Self::BlackWins => write!(f, "0-1"),
+            Self::Draw => write!(f, "1/2-1/2"),
+            Self::Unknown => write!(f, "*")
+        }
+    }
+}
+
+/// Reasons a PGN file can't be read. Lines are counted from 1
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    UnexpectedCharacter { line: usize, character: char },
+    /// A tag pair wasn't of the form `[Name "value"]`
+    InvalidTag(usize),
+    UnterminatedComment(usize),
+    /// A `(` without a matching `)` or the other way around
+    UnbalancedVariation(usize),
+    /// A variation was started before any move it could replace
+    VariationWithoutMove(usize),
+    /// A NAG came before any move it could annotate
+    NagWithoutMove(usize),
+    InvalidFen(FenParseError),
+    IllegalMove { line: usize, san: String, error: SanError },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedCharacter { line, character } =>
+                write!(f, "unexpected character '{}' on line {}", character, line),
+            Self::InvalidTag(line) => write!(f, "invalid tag on line {}", line),
+            Self::UnterminatedComment(line) => write!(f, "comment on line {} is never closed", line),
+            Self::UnbalancedVariation(line) => write!(f, "unbalanced variation on line {}", line),
+            Self::VariationWithoutMove(line) => write!(f, "variation on line {} has no move to replace", line),
+            Self::NagWithoutMove(line) => write!(f, "annotation on line {} has no move to annotate", line),
+            Self::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            Self::IllegalMove { line, san, error } => write!(f, "{} on line {}: {}", san, line, error)
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<FenParseError> for PgnError {
+    fn from(e: FenParseError) -> Self {
+        Self::InvalidFen(e)
+    }
+}
+
+/// A move in a game along with its annotations
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnMove {
+    pub move_: Move,
+    /// The move in SAN as it is written out
+    pub san: String,
+    /// Numeric annotation glyphs, e.g. `1` for a good move (`!`)
+    pub nags: Vec<u8>,
+    /// Comments before the move, only used at the start of a line
+    pub comments_before: Vec<String>,
+    /// Comments after the move
+    pub comments: Vec<String>,
+    /// Lines that could have been played instead of this move
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(game: &Game, move_: Move) -> Self {
+        Self {
+            move_,
+            san: game.move_to_san(&move_),
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+/// A game read from or to be written to PGN
+#[derive(Clone, Debug)]
+pub struct PgnGame {
+    /// Tag pairs in the order they were read
+    pub tags: Vec<(String, String)>,
+    /// Position the game started from
+    pub start: Game,
+    /// The moves that were played, with variations hanging off them
+    pub moves: Vec<PgnMove>,
+    pub result: PgnResult,
+}
+
+impl PgnGame {
+    /// Makes a record of the moves played in a game so far. The seven tag
+    /// roster is filled in with unknown values apart from the result.
+    pub fn from_game(game: &Game) -> Self {
+        let mut start = game.clone();
+        while start.undo_move().is_some() {}
+
+        let mut position = start.clone();
+        let moves = game.get_move_history()
+            .iter()
+            .map(|undo| {
+                let pgn_move = PgnMove::new(&position, undo.get_move());
+                position.make_move(undo.get_move());
+                pgn_move
+            })
+            .collect();
+
+        let result = PgnResult::from_status(game.get_status());
+
+        let mut pgn = Self {
+            tags: Vec::new(),
+            start,
+            moves,
+            result
+        };
+
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Date" => "????.??.??".to_string(),
+                "Result" => result.to_string(),
+                _ => "?".to_string()
+            };
+            pgn.set_tag(name, &value);
+        }
+
+        if game.is_chess960() {
+            pgn.set_tag("Variant", "Chess960");
+        }
+
+        let fen = pgn.start.to_fen();
+        if fen != START_FEN || game.is_chess960() {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", &fen);
+        }
+
+        pgn
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets the value of a tag, adding it to the end if it isn't there
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    /// Plays the moves of the game, not including variations, from the start
+    /// position
+    pub fn to_game(&self) -> Game {
+        let mut game = self.start.clone();
+
+        for pgn_move in &self.moves {
+            game.apply_unchecked_move(pgn_move.move_, true);
+        }
+
+        game
+    }
+}
+
+/// Writes the tags in the order they are stored followed by the movetext,
+/// wrapped to fit in 80 columns
+impl std::fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        line_tokens(
+            &mut tokens,
+            &self.moves,
+            self.start.get_fullmove_number(),
+            self.start.get_side_to_play()
+        );
+        tokens.push(self.result.to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f)?;
+                line_length = 0;
+            }
+
+            if line_length > 0 {
+                write!(f, " ")?;
+                line_length += 1;
+            }
+
+            write!(f, "{}", token)?;
+            line_length += token.len();
+        }
+
+        writeln!(f)
+    }
+}
+
+/// Adds the tokens for a line of moves, starting with the move numbered
+/// `fullmove` played by `side`
+fn line_tokens(tokens: &mut Vec<String>, moves: &[PgnMove], mut fullmove: u32, mut side: Side) {
+    // Black's moves need a number when they don't follow White's move
+    let mut needs_number = true;
+
+    for pgn_move in moves {
+        for comment in &pgn_move.comments_before {
+            tokens.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+
+        match side {
+            Side::White => tokens.push(format!("{}.", fullmove)),
+            Side::Black if needs_number => tokens.push(format!("{}...", fullmove)),
+            Side::Black => ()
+        }
+
+        tokens.push(pgn_move.san.clone());
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        tokens.extend(pgn_move.comments.iter().map(|comment| format!("{{{}}}", comment)));
+
+        for variation in pgn_move.variations.iter().filter(|v| !v.is_empty()) {
+            let start = tokens.len();
+            line_tokens(tokens, variation, fullmove, side);
+
+            tokens[start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+        }
+
+        needs_number = !pgn_move.comments.is_empty() || !pgn_move.variations.is_empty();
+
+        if side == Side::Black {
+            fullmove += 1;
+        }
+        side = side.other();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    /// A move or result
+    Symbol(String),
+    OpenVariation,
+    CloseVariation,
+}
+
+/// Splits PGN text into tokens along with the line each one starts on
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        let token_line = line;
+
+        match c {
+            '\n' => {
+                line += 1;
+                line_start = true;
+                continue
+            },
+            // Lines starting with % are escaped and ignored
+            '%' if line_start => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            },
+            c if c.is_whitespace() => continue,
+            '[' => {
+                // A `]` inside the quoted value doesn't end the tag
+                let mut inside = String::new();
+                let mut in_value = false;
+                loop {
+                    match chars.next() {
+                        Some(']') if !in_value => break,
+                        Some('\n') | None => return Err(PgnError::InvalidTag(token_line)),
+                        Some('\\') if in_value => {
+                            inside.push('\\');
+                            match chars.next() {
+                                Some('\n') | None => return Err(PgnError::InvalidTag(token_line)),
+                                Some(c) => inside.push(c)
+                            }
+                        },
+                        Some(c) => {
+                            if c == '"' {
+                                in_value = !in_value;
+                            }
+                            inside.push(c);
+                        }
+                    }
+                }
+                tokens.push((parse_tag(&inside).ok_or(PgnError::InvalidTag(token_line))?, token_line));
+            },
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            comment.push(c);
+                        },
+                        None => return Err(PgnError::UnterminatedComment(token_line))
+                    }
+                }
+                tokens.push((Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")), token_line));
+            },
+            ';' => {
+                let mut comment = String::new();
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    comment.push(c);
+                }
+                tokens.push((Token::Comment(comment.trim().to_string()), token_line));
+            },
+            '$' => {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                let nag = digits.parse()
+                    .map_err(|_| PgnError::UnexpectedCharacter { line, character: '$' })?;
+                tokens.push((Token::Nag(nag), token_line));
+            },
+            '(' => tokens.push((Token::OpenVariation, token_line)),
+            ')' => tokens.push((Token::CloseVariation, token_line)),
+            '*' => tokens.push((Token::Symbol("*".to_string()), token_line)),
+            c if c.is_ascii_alphanumeric() => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)) {
+                    symbol.push(c);
+                }
+
+                // Move numbers are skipped
+                if symbol.chars().all(|c| c.is_ascii_digit()) && chars.peek() == Some(&'.') {
+                    while chars.next_if_eq(&'.').is_some() {}
+                } else {
+                    tokens.push((Token::Symbol(symbol), token_line));
+                }
+            },
+            // Move suffixes are short for the first six NAGs
+            '!' | '?' => {
+                let mut suffix = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c == '!' || c == '?') {
+                    suffix.push(c);
+                }
+
+                let nag = match suffix.as_str() {
+                    "!" => 1,
+                    "?" => 2,
+                    "!!" => 3,
+                    "??" => 4,
+                    "!?" => 5,
+                    "?!" => 6,
+                    _ => return Err(PgnError::UnexpectedCharacter { line, character: c })
+                };
+                tokens.push((Token::Nag(nag), token_line));
+            },
+            // Stray dots after a move number that had a space before them
+            '.' => (),
+            _ => return Err(PgnError::UnexpectedCharacter { line, character: c })
+        }
+
+        line_start = false;
+    }
+
+    Ok(tokens)
+}
+
+/// Reads the inside of a tag pair, `Name "value"`
+fn parse_tag(inside: &str) -> Option<Token> {
+    let (name, rest) = inside.trim().split_once(char::is_whitespace)?;
+    let rest = rest.trim();
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None
+    }
+
+    let quoted = rest.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            '"' => return None,
+            c => value.push(c)
+        }
+    }
+
+    Some(Token::Tag(name.to_string(), value))
+}
+
+/// Reads the games from PGN text in a stream of tokens
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(t, _)| t)
+    }
+
+    fn get_line(&self) -> usize {
+        self.tokens.get(self.index)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn parse_game(&mut self) -> Result<PgnGame, PgnError> {
+        let mut tags = Vec::new();
+
+        while let Some(Token::Tag(name, value)) = self.peek() {
+            tags.push((name.clone(), value.clone()));
+            self.index += 1;
+        }
+
+        let get_tag = |tag: &str| tags.iter().find(|(name, _)| name == tag).map(|(_, value)| value.as_str());
+
+        let mut start = match get_tag("FEN") {
+            Some(fen) => Game::from_fen(fen)?,
+            None => Game::new()
+        };
+
+        if get_tag("Variant").is_some_and(|v| v.to_ascii_lowercase().contains("960")) {
+            start.set_chess960(true);
+        }
+
+        let (moves, result) = self.parse_line(start.clone(), 0)?;
+
+        // Games without a result at the end take it from the tags
+        let result = result
+            .or_else(|| get_tag("Result").and_then(PgnResult::from_token))
+            .unwrap_or(PgnResult::Unknown);
+
+        Ok(PgnGame { tags, start, moves, result })
+    }
+
+    /// Reads moves played from `game` until the end of the variation at
+    /// `depth`, or the end of the game for the main line
+    fn parse_line(&mut self, mut game: Game, depth: usize) -> Result<(Vec<PgnMove>, Option<PgnResult>), PgnError> {
+        let mut line: Vec<PgnMove> = Vec::new();
+        // Position before the last move, which variations are played from
+        let mut before_last: Option<Game> = None;
+        let mut comments_before = Vec::new();
+
+        loop {
+            let line_number = self.get_line();
+
+            let Some(token) = self.peek().cloned() else {
+                if depth > 0 {
+                    return Err(PgnError::UnbalancedVariation(line_number))
+                }
+                break
+            };
+
+            match token {
+                Token::Tag(..) if depth == 0 => break,
+                Token::Tag(..) => return Err(PgnError::UnbalancedVariation(line_number)),
+                Token::Comment(comment) => match line.last_mut() {
+                    Some(last) => last.comments.push(comment),
+                    None => comments_before.push(comment)
+                },
+                Token::Nag(nag) => match line.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(PgnError::NagWithoutMove(line_number))
+                },
+                Token::OpenVariation => {
+                    let Some(position) = before_last.clone() else {
+                        return Err(PgnError::VariationWithoutMove(line_number))
+                    };
+
+                    self.index += 1;
+                    let (variation, _) = self.parse_line(position, depth + 1)?;
+                    line.last_mut().unwrap().variations.push(variation);
+                    continue
+                },
+                Token::CloseVariation if depth > 0 => {
+                    self.index += 1;
+                    return Ok((line, None))
+                },
+                Token::CloseVariation => return Err(PgnError::UnbalancedVariation(line_number)),
+                Token::Symbol(symbol) => {
+                    if let Some(result) = PgnResult::from_token(&symbol) {
+                        if depth > 0 {
+                            return Err(PgnError::UnbalancedVariation(line_number))
+                        }
+                        self.index += 1;
+                        return Ok((line, Some(result)))
+                    }
+
+                    let move_ = game.parse_san(&symbol)
+                        .map_err(|error| PgnError::IllegalMove { line: line_number, san: symbol.clone(), error })?;
+
+                    let mut pgn_move = PgnMove::new(&game, move_);
+                    pgn_move.comments_before = std::mem::take(&mut comments_before);
+
+                    before_last = Some(game.clone());
+                    game.make_move(move_);
+                    line.push(pgn_move);
+                }
+            }
+
+            self.index += 1;
+        }
+
+        Ok((line, None))
+    }
+}
+
+/// Reads every game in PGN text
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut parser = Parser { tokens: tokenize(text)?, index: 0 };
+    let mut games = Vec::new();
+
+    while parser.peek().is_some() {
+        games.push(parser.parse_game()?);
+    }
+
+    Ok(games)
+}
@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Side {
     Black,
     White
@@ -19,6 +19,11 @@ impl Side{
         }
     }
 
+    /// Rank the side's pieces start on
+    pub fn get_back_rank_y(&self) -> i8 {
+        self.other().get_promotion_y()
+    }
+
     pub fn get_dir(&self) -> i8 {
         match *self {
             White => 1,
@@ -27,7 +32,7 @@ impl Side{
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PieceType {
     King,
     Queen,
@@ -39,6 +44,8 @@ pub enum PieceType {
 
 pub const PROMOTABLE_PIECES: [PieceType; 4] = [Queen, Bishop, Rook, Knight];
 
+pub const ALL_PIECE_TYPES: [PieceType; 6] = [King, Queen, Bishop, Rook, Knight, Pawn];
+
 use Side::*;
 use PieceType::*;
 use crate::coord::*;
@@ -64,7 +71,7 @@ impl std::fmt::Debug for Piece {
 }
 
 impl Piece {
-    pub fn new(side: Side, 
+    pub const fn new(side: Side, 
                has_moved: bool, 
                piece_type: PieceType, 
                loc: Coord)
@@ -0,0 +1,159 @@
+//! Drawing the board as text for terminals.
+//!
+//! Boards are drawn with White at the bottom unless flipped, with optional
+//! rank and file labels, Unicode piece symbols and ANSI colours. Coloured
+//! boards can also show the last move and a king in check.
+
+use std::fmt::Write;
+
+use crate::coord::Coord;
+use crate::game::Game;
+use crate::pieces::{get_piece_char, Piece, PieceType, Side};
+
+const RESET: &str = "\x1b[0m";
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LIGHT_LAST_MOVE: &str = "\x1b[48;5;186m";
+const DARK_LAST_MOVE: &str = "\x1b[48;5;143m";
+const CHECK_SQUARE: &str = "\x1b[48;5;167m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+
+/// How to draw a board
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Show rank numbers down the side and file letters along the bottom
+    pub labels: bool,
+    /// Use chess symbols like ♘ instead of letters
+    pub unicode: bool,
+    /// Colour the squares and pieces with ANSI escape codes
+    pub colour: bool,
+    /// Draw the board from Black's side
+    pub flipped: bool,
+    /// Highlight the squares the last move was from and to, only shown when
+    /// coloured
+    pub highlight_last_move: bool,
+    /// Highlight the king of the side to move when it is in check, only
+    /// shown when coloured
+    pub highlight_check: bool,
+}
+
+/// Plain letters with labels, which works anywhere
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            labels: true,
+            unicode: false,
+            colour: false,
+            flipped: false,
+            highlight_last_move: true,
+            highlight_check: true,
+        }
+    }
+}
+
+fn get_piece_symbol(piece: &Piece, options: &RenderOptions) -> char {
+    if !options.unicode {
+        return get_piece_char(piece)
+    }
+
+    // Coloured boards set the colour of the piece themselves, so the solid
+    // symbols are used for both sides as they are easier to see
+    let side = if options.colour { Side::Black } else { piece.get_side() };
+
+    match (side, piece.get_type()) {
+        (Side::White, PieceType::King) => '♔',
+        (Side::White, PieceType::Queen) => '♕',
+        (Side::White, PieceType::Rook) => '♖',
+        (Side::White, PieceType::Bishop) => '♗',
+        (Side::White, PieceType::Knight) => '♘',
+        (Side::White, PieceType::Pawn) => '♙',
+        (Side::Black, PieceType::King) => '♚',
+        (Side::Black, PieceType::Queen) => '♛',
+        (Side::Black, PieceType::Rook) => '♜',
+        (Side::Black, PieceType::Bishop) => '♝',
+        (Side::Black, PieceType::Knight) => '♞',
+        (Side::Black, PieceType::Pawn) => '♟'
+    }
+}
+
+/// Draws the board followed by a line saying whose move it is
+pub fn render(game: &Game, options: &RenderOptions) -> String {
+    let last_move = game.get_move_history().last().map(|undo| undo.get_move());
+    let side = game.get_side_to_play();
+
+    let checked_king = game.iter()
+        .find(|p| p.is_type(PieceType::King) && p.is_side(side))
+        .map(|king| king.get_loc())
+        .filter(|_| game.is_side_in_check(side));
+
+    let (ranks, files): (Vec<i8>, Vec<i8>) = match options.flipped {
+        false => ((0..8).rev().collect(), (0..8).collect()),
+        true => ((0..8).collect(), (0..8).rev().collect())
+    };
+
+    let mut out = String::new();
+
+    for &y in &ranks {
+        if options.labels {
+            let _ = write!(out, "{} ", y + 1);
+        }
+
+        for &x in &files {
+            let coord = Coord::from_x_and_y(x, y);
+            let piece = game.get_piece_at(coord);
+            let symbol = piece.map_or('.', |p| get_piece_symbol(&p, options));
+
+            if !options.colour {
+                out.push(symbol);
+                out.push(' ');
+                continue
+            }
+
+            let dark = (x + y) % 2 == 0;
+            let moved = options.highlight_last_move
+                && last_move.is_some_and(|m| m.get_from() == coord || m.get_to() == coord);
+
+            let background = match (dark, moved) {
+                _ if options.highlight_check && checked_king == Some(coord) => CHECK_SQUARE,
+                (true, true) => DARK_LAST_MOVE,
+                (false, true) => LIGHT_LAST_MOVE,
+                (true, false) => DARK_SQUARE,
+                (false, false) => LIGHT_SQUARE
+            };
+
+            let (foreground, symbol) = match piece {
+                Some(p) if p.is_side(Side::White) => (WHITE_PIECE, symbol),
+                Some(_) => (BLACK_PIECE, symbol),
+                None => ("", ' ')
+            };
+
+            let _ = write!(out, "{}{} {} ", background, foreground, symbol);
+            out.push_str(RESET);
+        }
+
+        // Plain squares leave a trailing space
+        out.truncate(out.trim_end_matches(' ').len());
+        out.push('\n');
+    }
+
+    if options.labels {
+        out.push_str("  ");
+        for &x in &files {
+            let file = (b'a' + x as u8) as char;
+            let _ = match options.colour {
+                true => write!(out, " {} ", file),
+                false => write!(out, "{} ", file)
+            };
+        }
+        out.truncate(out.trim_end_matches(' ').len());
+        out.push('\n');
+    }
+
+    let _ = write!(out, "{:?} to move", side);
+    if checked_king.is_some() {
+        out.push_str(", in check");
+    }
+
+    out
+}
@@ -0,0 +1,104 @@
+//! Conformance tests for the rules that are easy to get wrong when
+//! generating legal moves. Moves are compared in SAN so each test reads
+//! like the position it describes.
+
+use crate::game::*;
+use crate::moves::*;
+
+/// Gets the legal moves in the position, sorted so they can be compared
+fn san_moves(fen: &str) -> Vec<String> {
+    let game = Game::from_fen(fen).unwrap();
+    let mut moves: Vec<String> = game.get_valid_moves().iter()
+        .map(|m| game.move_to_san(m))
+        .collect();
+    moves.sort();
+
+    moves
+}
+
+fn castles(fen: &str) -> Vec<String> {
+    san_moves(fen).into_iter().filter(|m| m.starts_with("O-O")).collect()
+}
+
+#[test]
+fn castling_out_of_check() {
+    assert!(castles("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1").is_empty());
+
+    // The same in Chess960, where the king doesn't have to move far
+    assert!(castles("4k3/6r1/8/8/8/8/8/R5KR w HA - 0 1").is_empty());
+}
+
+#[test]
+fn castling_through_check() {
+    assert_eq!(castles("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O-O"]);
+    assert_eq!(castles("3rk3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O"]);
+}
+
+#[test]
+fn castling_into_check() {
+    assert_eq!(castles("4k1r1/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O-O"]);
+    assert_eq!(castles("2r1k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O"]);
+}
+
+#[test]
+fn castling_past_attacked_rook_square() {
+    // Only the king's squares matter, so the rook may cross b1
+    assert_eq!(castles("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O", "O-O-O"]);
+}
+
+#[test]
+fn en_passant_pinned_along_rank() {
+    // Taking removes both pawns from the rank, exposing the king to the rook
+    let moves = san_moves("4k3/8/8/K2pP2r/8/8/8/8 w - d6 0 1");
+    assert!(!moves.contains(&"exd6".to_string()));
+    assert!(moves.contains(&"e6".to_string()));
+}
+
+#[test]
+fn en_passant_pinned_along_diagonal() {
+    // The pawn is pinned by the bishop and taking moves it off the diagonal
+    let moves = san_moves("7K/8/8/3pP3/8/8/1b6/4k3 w - d6 0 1");
+    assert!(!moves.contains(&"exd6".to_string()));
+
+    // Taking along the pin is still allowed
+    let moves = san_moves("4k3/2b5/8/3pP3/8/8/7K/8 w - d6 0 1");
+    assert!(moves.contains(&"exd6".to_string()));
+}
+
+#[test]
+fn en_passant_removes_checking_pawn() {
+    // The pawn that just double moved gives check and can be taken en passant
+    let moves = san_moves("4k3/8/8/3pP3/4K3/8/8/8 w - d6 0 1");
+    assert!(moves.contains(&"exd6".to_string()));
+    assert!(!moves.contains(&"e6".to_string()));
+}
+
+#[test]
+fn promotion_with_capture() {
+    let moves = san_moves("rn2k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+    let promotions: Vec<&String> = moves.iter().filter(|m| m.starts_with('b')).collect();
+
+    // Capturing the rook but not pushing into the knight
+    assert_eq!(promotions, ["bxa8=B", "bxa8=N", "bxa8=Q", "bxa8=R"]);
+
+    let game = Game::from_fen("rn2k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let move_ = game.parse_san("bxa8=N").unwrap();
+    assert!(matches!(move_, Move::Promotion(_)));
+
+    let mut after = game.clone();
+    after.apply_move(move_).unwrap();
+    assert_eq!(after.to_fen(), "Nn2k3/8/8/8/8/8/8/4K3 b - - 0 1");
+
+    after.undo_move();
+    assert_eq!(after, game);
+}
+
+#[test]
+fn double_check_evasions() {
+    // Checked by the rook and knight, so taking the knight doesn't help and
+    // only the king can move
+    assert_eq!(
+        san_moves("k3r3/8/8/1B6/8/3n4/8/4K2R w K - 0 1"),
+        ["Kd1", "Kd2", "Kf1"]
+    );
+}
@@ -0,0 +1,255 @@
+use crate::coord::Coord;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::pieces::{PieceType, PROMOTABLE_PIECES};
+
+/// Reasons a move in Standard Algebraic Notation can't be read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    /// The string isn't written in SAN
+    InvalidSyntax,
+    /// No legal move matches the string
+    IllegalMove,
+    /// More than one legal move matches the string
+    AmbiguousMove,
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no move given"),
+            Self::InvalidSyntax => write!(f, "move is not valid SAN"),
+            Self::IllegalMove => write!(f, "no legal move matches"),
+            Self::AmbiguousMove => write!(f, "more than one legal move matches")
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn piece_letter(piece_type: PieceType) -> Option<char> {
+    match piece_type {
+        PieceType::King => Some('K'),
+        PieceType::Queen => Some('Q'),
+        PieceType::Bishop => Some('B'),
+        PieceType::Rook => Some('R'),
+        PieceType::Knight => Some('N'),
+        PieceType::Pawn => None
+    }
+}
+
+fn piece_type_from_letter(c: char) -> Option<PieceType> {
+    match c {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'N' => Some(PieceType::Knight),
+        _ => None
+    }
+}
+
+fn file_char(x: i8) -> char {
+    (b'a' + x as u8) as char
+}
+
+fn rank_char(y: i8) -> char {
+    (b'1' + y as u8) as char
+}
+
+/// Whether the move is castling towards the h file
+fn is_kingside(move_: &Move) -> bool {
+    match move_ {
+        Move::Castle(m) => m.rook_from.get_x_and_y()[0] > m.king_from.get_x_and_y()[0],
+        _ => false
+    }
+}
+
+fn get_promotion_type(move_: &Move) -> Option<PieceType> {
+    match move_ {
+        Move::Promotion(m) => Some(m.new_piece.get_type()),
+        _ => None
+    }
+}
+
+/// The parts of a SAN move other than castling
+struct SanParts {
+    piece_type: PieceType,
+    from_x: Option<i8>,
+    from_y: Option<i8>,
+    to: Coord,
+    promotion: Option<PieceType>,
+}
+
+fn parse_parts(san: &str) -> Result<SanParts, SanError> {
+    let mut chars: Vec<char> = san.chars().collect();
+
+    let piece_type = match chars.first().and_then(|&c| piece_type_from_letter(c)) {
+        Some(piece_type) => {
+            chars.remove(0);
+            piece_type
+        },
+        None => PieceType::Pawn
+    };
+
+    // Promotions are usually written `e8=Q` but `e8Q` is also seen
+    let promotion = match chars.last().and_then(|&c| piece_type_from_letter(c)) {
+        Some(promotion) => {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            Some(promotion)
+        },
+        None => None
+    };
+
+    if promotion.is_some_and(|p| !PROMOTABLE_PIECES.contains(&p)) {
+        return Err(SanError::InvalidSyntax)
+    }
+
+    if chars.len() < 2 {
+        return Err(SanError::InvalidSyntax)
+    }
+
+    let to: Coord = chars.split_off(chars.len() - 2)
+        .into_iter()
+        .collect::<String>()
+        .parse()
+        .map_err(|_| SanError::InvalidSyntax)?;
+
+    if chars.last() == Some(&'x') {
+        chars.pop();
+    }
+
+    // Whatever is left is the file and/or rank the piece moves from
+    let (mut from_x, mut from_y) = (None, None);
+    for c in chars {
+        match c {
+            'a'..='h' if from_x.is_none() && from_y.is_none() => from_x = Some((c as u8 - b'a') as i8),
+            '1'..='8' if from_y.is_none() => from_y = Some((c as u8 - b'1') as i8),
+            _ => return Err(SanError::InvalidSyntax)
+        }
+    }
+
+    Ok(SanParts { piece_type, from_x, from_y, to, promotion })
+}
+
+fn is_capture(game: &Game, move_: &Move) -> bool {
+    matches!(move_, Move::EnPassant(_)) ||
+        (!matches!(move_, Move::Castle(_)) && game.get_piece_at(move_.get_to()).is_some())
+}
+
+impl Game {
+    /// Writes a legal move in Standard Algebraic Notation, e.g. `Nbd7`,
+    /// `exd5`, `O-O` or `e8=Q+`
+    pub fn move_to_san(&self, move_: &Move) -> String {
+        let mut san = if let Move::Castle(_) = move_ {
+            if is_kingside(move_) { "O-O".to_string() } else { "O-O-O".to_string() }
+        } else {
+            let piece_type = move_.get_piece().get_type();
+            let [from_x, from_y] = move_.get_from().get_x_and_y();
+            let mut san = String::new();
+
+            match piece_letter(piece_type) {
+                Some(letter) => {
+                    san.push(letter);
+
+                    // Other pieces of the same type that could move to the
+                    // same square
+                    let others: Vec<Move> = self.get_valid_moves()
+                        .into_iter()
+                        .filter(|m| m.get_piece().is_type(piece_type) &&
+                                    m.get_to() == move_.get_to() &&
+                                    m.get_from() != move_.get_from() &&
+                                    !matches!(m, Move::Castle(_)))
+                        .collect();
+
+                    if !others.is_empty() {
+                        let same_file = others.iter().any(|m| m.get_from().get_x_and_y()[0] == from_x);
+                        let same_rank = others.iter().any(|m| m.get_from().get_x_and_y()[1] == from_y);
+
+                        if !same_file {
+                            san.push(file_char(from_x));
+                        } else if !same_rank {
+                            san.push(rank_char(from_y));
+                        } else {
+                            san.push(file_char(from_x));
+                            san.push(rank_char(from_y));
+                        }
+                    }
+                },
+                // Pawns are only named by their file when capturing
+                None if is_capture(self, move_) => san.push(file_char(from_x)),
+                None => ()
+            }
+
+            if is_capture(self, move_) {
+                san.push('x');
+            }
+
+            san.push_str(&move_.get_to().to_string());
+
+            if let Some(promotion) = get_promotion_type(move_).and_then(piece_letter) {
+                san.push('=');
+                san.push(promotion);
+            }
+
+            san
+        };
+
+        let mut after = self.clone();
+        after.make_move(*move_);
+
+        if after.is_side_in_check(after.get_side_to_play()) {
+            san.push(if after.get_valid_moves().is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    /// Finds the legal move written in Standard Algebraic Notation. Check
+    /// and annotation symbols such as `+`, `#`, `!` and `?` are ignored.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let san = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        if san.is_empty() {
+            return Err(SanError::Empty)
+        }
+
+        let moves = self.get_valid_moves();
+
+        let matches: Vec<Move> = match san {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let kingside = san.len() == 3;
+
+                moves.into_iter()
+                    .filter(|m| matches!(m, Move::Castle(_)) && is_kingside(m) == kingside)
+                    .collect()
+            },
+            _ => {
+                let parts = parse_parts(san)?;
+
+                moves.into_iter()
+                    .filter(|m| {
+                        let [x, y] = m.get_from().get_x_and_y();
+
+                        !matches!(m, Move::Castle(_)) &&
+                        m.get_piece().is_type(parts.piece_type) &&
+                        m.get_to() == parts.to &&
+                        get_promotion_type(m) == parts.promotion &&
+                        parts.from_x.is_none_or(|from_x| from_x == x) &&
+                        parts.from_y.is_none_or(|from_y| from_y == y)
+                    })
+                    .collect()
+            }
+        };
+
+        match matches[..] {
+            [move_] => Ok(move_),
+            [] => Err(SanError::IllegalMove),
+            _ => Err(SanError::AmbiguousMove)
+        }
+    }
+}
@@ -0,0 +1,1259 @@
+//! Probing Syzygy endgame tablebases. The WDL tables (`.rtbw`) give whether
+//! a position is won, drawn or lost, and the DTZ tables (`.rtbz`) how many
+//! plies it is until a capture or pawn move that keeps the result.
+//!
+//! Tables are found with `init`, which reads their headers, and the blocks
+//! of values are read from disk as positions need them. Positions with
+//! castling rights are never in the tables.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::game::Game;
+use crate::movegen::{GenMode, MoveList};
+use crate::moves::Move;
+use crate::pieces::{Side, PieceType};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+const WDL_SUFFIX: &str = ".rtbw";
+const DTZ_SUFFIX: &str = ".rtbz";
+
+/// Most pieces a table can have, including the kings
+const MAX_PIECES: usize = 7;
+
+// Flags stored for each part of a table
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Bytes first read from a table file for its header, which is read again
+/// with more when it doesn't fit
+const HEADER_READ: u64 = 1 << 16;
+/// Longest header a table can have, with the largest Huffman codes and
+/// DTZ maps
+const MAX_HEADER: u64 = 1 << 22;
+
+/// Number of ways to place the leading group when it is three unique pieces
+const UNIQUE_PIECES_SIZE: u64 = 31332;
+/// Number of ways to place the two kings when they are the leading group
+const KINGS_SIZE: u64 = 462;
+
+/// The tables found by `init`. Probing happens from whichever thread is
+/// searching, so they're shared behind a lock.
+static TABLEBASES: Mutex<Option<Tablebases>> = Mutex::new(None);
+
+/// Result of a position with perfect play. Cursed wins and blessed losses
+/// are wins and losses that are drawn by the fifty move rule.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Self {
+        match value {
+            i32::MIN..=-2 => Self::Loss,
+            -1 => Self::BlessedLoss,
+            0 => Self::Draw,
+            1 => Self::CursedWin,
+            _ => Self::Win
+        }
+    }
+
+    /// The DTZ of the move before a capture or pawn move with this result,
+    /// as DTZ tables don't store it
+    pub fn dtz_before_zeroing(&self) -> i32 {
+        match self {
+            Self::Loss => -1,
+            Self::BlessedLoss => -101,
+            Self::Draw => 0,
+            Self::CursedWin => 101,
+            Self::Win => 1
+        }
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_i32(-(self as i32))
+    }
+}
+
+/// Looks for tables in the directories in `paths`, separated by `:` (or `;`
+/// on Windows), replacing any found before. An empty string or `<empty>`
+/// removes all tables. Returns the number of WDL tables that could be read.
+pub fn init(paths: &str) -> usize {
+    let tablebases = Tablebases::find(paths);
+    let count = tablebases.tables.len();
+
+    *lock() = Some(tablebases);
+
+    count
+}
+
+/// Most pieces, including kings, in any table found by `init`. Positions
+/// with more pieces can't be probed.
+pub fn max_pieces() -> usize {
+    lock().as_ref().map_or(0, |tb| tb.max_pieces)
+}
+
+/// Probes the WDL tables for the result of the game with the side to move
+/// playing perfectly. Returns `None` if the tables for the position and the
+/// positions after each capture haven't been found. Captures are made and
+/// unmade on `game`, which is left as it was.
+pub fn probe_wdl(game: &mut Game) -> Option<Wdl> {
+    let lock = lock();
+    let tablebases = lock.as_ref().filter(|tb| tb.can_probe(game))?;
+
+    tablebases.search(game, false).map(|(wdl, _)| wdl)
+}
+
+/// Probes the DTZ tables for the number of plies until the next capture or
+/// pawn move with perfect play, positive if the side to move wins and
+/// negative if it loses. Cursed wins and blessed losses are 100 plies
+/// further from zero, and draws are 0. Like `probe_wdl`, `game` is left as
+/// it was.
+pub fn probe_dtz(game: &mut Game) -> Option<i32> {
+    let lock = lock();
+    let tablebases = lock.as_ref().filter(|tb| tb.can_probe(game))?;
+
+    tablebases.probe_dtz(game)
+}
+
+/// The tables don't change while being probed, so they're still fine to use
+/// if a thread panicked while holding the lock
+fn lock() -> MutexGuard<'static, Option<Tablebases>> {
+    TABLEBASES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Squares here count from a1 to h8 as the table files do
+type Square = usize;
+
+fn square_of(x: i8, y: i8) -> Square {
+    (x + 8 * y) as Square
+}
+
+fn file_of(sq: Square) -> usize {
+    sq & 7
+}
+
+fn rank_of(sq: Square) -> usize {
+    sq >> 3
+}
+
+/// How far above the a1-h8 diagonal a square is, negative below it
+fn off_diagonal(sq: Square) -> i32 {
+    rank_of(sq) as i32 - file_of(sq) as i32
+}
+
+/// Pieces are coded as in the table files, from 1 for pawns to 6 for kings
+/// with 8 added for black
+fn piece_code(side: Side, piece_type: PieceType) -> u8 {
+    let code = match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6
+    };
+
+    match side {
+        Side::White => code,
+        Side::Black => code + 8
+    }
+}
+
+/// Tables used to turn the squares of groups of pieces into indices
+struct Maps {
+    /// Squares a2 to h7 coded so the pawn furthest to the edges and then
+    /// furthest back is highest
+    pawns: [usize; 64],
+    /// Squares below the a1-h8 diagonal coded from 0 to 27
+    b1_h1_h7: [usize; 64],
+    /// Squares in the a1-d1-d4 triangle coded from 0 to 9, with squares on
+    /// the diagonal last
+    a1_d1_d4: [usize; 64],
+    /// Index of two kings, the first in the a1-d1-d4 triangle
+    kk: [[u64; 64]; 10],
+    /// `binomial[k][n]` is the number of ways to choose k from n
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Index of the leading pawn on each square by the number of leading pawns
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    /// Number of ways to place the leading pawns by their number and the
+    /// file of the leading pawn
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+fn maps() -> &'static Maps {
+    static MAPS: OnceLock<Maps> = OnceLock::new();
+    MAPS.get_or_init(Maps::new)
+}
+
+impl Maps {
+    fn new() -> Self {
+        let mut b1_h1_h7 = [0; 64];
+        let mut code = 0;
+        for (sq, entry) in b1_h1_h7.iter_mut().enumerate() {
+            if off_diagonal(sq) < 0 {
+                *entry = code;
+                code += 1;
+            }
+        }
+
+        let mut a1_d1_d4 = [0; 64];
+        let mut diagonal = Vec::new();
+        code = 0;
+        for sq in (0..=27).filter(|&sq| file_of(sq) <= 3) {
+            if off_diagonal(sq) < 0 {
+                a1_d1_d4[sq] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            a1_d1_d4[sq] = code;
+            code += 1;
+        }
+
+        // Kings can't be next to each other, and if the first is on the
+        // diagonal the second can't be above it. Positions with both kings
+        // on the diagonal come last.
+        let mut kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (idx, row) in kk.iter_mut().enumerate() {
+            for s1 in (0..=27).filter(|&s1| file_of(s1) <= 3 && off_diagonal(s1) <= 0) {
+                // b1 is the only square coded 0
+                if a1_d1_d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue
+                }
+
+                for (s2, entry) in row.iter_mut().enumerate() {
+                    let touching = file_of(s1).abs_diff(file_of(s2)) <= 1 && rank_of(s1).abs_diff(rank_of(s2)) <= 1;
+
+                    if touching || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue
+                    }
+
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        *entry = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            kk[idx][s2] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; 64]; MAX_PIECES];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 } +
+                    if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; MAX_PIECES];
+        let mut lead_pawns_size = [[0; 4]; MAX_PIECES];
+        let mut numbered = 0;
+        for lead_pawns in 1..MAX_PIECES - 1 {
+            for (file, size) in lead_pawns_size[lead_pawns].iter_mut().enumerate() {
+                let mut idx = 0;
+
+                for rank in 1..7 {
+                    let sq = file + 8 * rank;
+
+                    // Pawns further up the board have fewer squares left for
+                    // the other pawns, as they can't be further back
+                    if lead_pawns == 1 {
+                        pawns[sq] = 47 - numbered;
+                        pawns[sq ^ 7] = 46 - numbered;
+                        numbered += 2;
+                    }
+
+                    lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += binomial[lead_pawns - 1][pawns[sq]];
+                }
+
+                *size = idx;
+            }
+        }
+
+        Self { pawns, b1_h1_h7, a1_d1_d4, kk, binomial, lead_pawn_idx, lead_pawns_size }
+    }
+}
+
+/// Reads numbers from table data, or `None` past the end
+fn read_bytes<const N: usize>(data: &[u8], pos: usize) -> Option<[u8; N]> {
+    data.get(pos..pos.checked_add(N)?)?.try_into().ok()
+}
+
+fn read_u8(data: &[u8], pos: usize) -> Option<u8> {
+    data.get(pos).copied()
+}
+
+fn read_u16_le(data: &[u8], pos: usize) -> Option<u16> {
+    read_bytes(data, pos).map(u16::from_le_bytes)
+}
+
+fn read_u32_le(data: &[u8], pos: usize) -> Option<u32> {
+    read_bytes(data, pos).map(u32::from_le_bytes)
+}
+
+fn read_u32_be(data: &[u8], pos: usize) -> Option<u32> {
+    read_bytes(data, pos).map(u32::from_be_bytes)
+}
+
+fn read_u64_be(data: &[u8], pos: usize) -> Option<u64> {
+    read_bytes(data, pos).map(u64::from_be_bytes)
+}
+
+/// Reads `len` bytes from `pos` in a table file
+fn read_file(mut file: &File, pos: u64, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    file.seek(SeekFrom::Start(pos)).ok()?;
+    file.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// Everything needed to decompress one part of a table. Tables have a part
+/// for each side to move and, with pawns, each file of the leading pawn.
+/// The Huffman code is found by its position in the header, and the sparse
+/// index, block lengths and blocks by their position in the table file.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    /// Number of values between each sparse index entry
+    span: usize,
+    num_blocks: usize,
+    min_sym_len: usize,
+    /// Lowest symbol of each length
+    lowest_sym: usize,
+    /// Pairs of symbols each symbol expands to
+    btree: usize,
+    /// Number of values minus one in each block
+    block_lengths: u64,
+    block_lengths_size: usize,
+    /// Entries pointing into `block_lengths` every `span` values
+    sparse_index: u64,
+    sparse_index_size: usize,
+    data: u64,
+    /// Lowest symbol of each length padded to 64 bits
+    base64: Vec<u64>,
+    /// Number of values minus one each symbol expands to
+    symlen: Vec<u32>,
+    /// The pieces in the order they are indexed
+    pieces: [u8; MAX_PIECES],
+    /// Multiplier for the index of each group of pieces
+    group_idx: [u64; MAX_PIECES + 1],
+    /// Number of pieces in each group, ending with 0
+    group_len: [usize; MAX_PIECES + 1],
+    /// Where the DTZ values for each result start in the map
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn get_left(&self, data: &[u8], sym: usize) -> Option<usize> {
+        let lr = read_bytes::<3>(data, self.btree + 3 * sym)?;
+        Some(((lr[1] as usize & 0xF) << 8) | lr[0] as usize)
+    }
+
+    fn get_right(&self, data: &[u8], sym: usize) -> Option<usize> {
+        let lr = read_bytes::<3>(data, self.btree + 3 * sym)?;
+        Some(((lr[2] as usize) << 4) | (lr[1] as usize >> 4))
+    }
+
+    /// Splits the pieces into groups and works out the multiplier for each.
+    /// `order` gives where the leading group and the other side's pawns
+    /// come in the index.
+    fn set_groups(&mut self, table: &Table, order: [u8; 2], file: usize) {
+        let maps = maps();
+        let mut first_len = if table.has_pawns { 0 } else if table.has_unique_pieces { 3 } else { 2 };
+        let mut n = 0;
+
+        self.group_len[0] = 1;
+        for i in 1..table.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        let pawns_both_sides = table.has_pawns && table.pawn_count[1] > 0;
+        let mut next = if pawns_both_sides { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_len[0] - if pawns_both_sides { self.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = idx;
+                idx *= if table.has_pawns {
+                    maps.lead_pawns_size[self.group_len[0]][file]
+                } else if table.has_unique_pieces {
+                    UNIQUE_PIECES_SIZE
+                } else {
+                    KINGS_SIZE
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = idx;
+                idx *= maps.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= maps.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+
+            k += 1;
+        }
+
+        self.group_idx[n] = idx;
+    }
+
+    /// Reads the sizes and Huffman code of this part, returning where the
+    /// next part starts
+    fn set_sizes(&mut self, data: &[u8], mut pos: usize) -> Option<usize> {
+        self.flags = read_u8(data, pos)?;
+        pos += 1;
+
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            // The single value is stored in place of the symbol length
+            self.min_sym_len = read_u8(data, pos)? as usize;
+            return Some(pos + 1)
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0)?;
+        let table_size = self.group_idx[groups];
+
+        self.block_size = 1usize.checked_shl(read_u8(data, pos)? as u32)?;
+        self.span = 1usize.checked_shl(read_u8(data, pos + 1)? as u32)?;
+        self.sparse_index_size = usize::try_from(table_size.div_ceil(self.span as u64)).ok()?;
+        let padding = read_u8(data, pos + 2)? as usize;
+        self.num_blocks = read_u32_le(data, pos + 3)? as usize;
+        self.block_lengths_size = self.num_blocks + padding;
+        let max_sym_len = read_u8(data, pos + 7)? as usize;
+        self.min_sym_len = read_u8(data, pos + 8)? as usize;
+        self.lowest_sym = pos + 9;
+        pos += 9;
+
+        // Blocks start with a 64 bit buffer, and codes longer than 32 bits
+        // would run it dry between refills
+        if self.block_size < 8 || self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 32 {
+            return None
+        }
+
+        // Canonical Huffman codes: longer codes have lower values, so the
+        // length of a code is found by comparing against the lowest code of
+        // each length
+        let lengths = max_sym_len - self.min_sym_len + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16_le(data, self.lowest_sym + 2 * i)? as u64;
+            let next_lowest = read_u16_le(data, self.lowest_sym + 2 * (i + 1))? as u64;
+
+            self.base64[i] = self.base64[i + 1].wrapping_add(lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - self.min_sym_len) as u32).unwrap_or(0);
+        }
+        pos += lengths * 2;
+
+        let symbols = read_u16_le(data, pos)? as usize;
+        pos += 2;
+        self.btree = pos;
+        data.get(pos..pos + symbols * 3)?;
+
+        // Each symbol is either a value or a pair of symbols
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(data, sym, &mut visited)?;
+            }
+        }
+
+        Some(pos + symbols * 3 + (symbols & 1))
+    }
+
+    fn set_symlen(&mut self, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u32> {
+        visited[sym] = true;
+
+        let right = self.get_right(data, sym)?;
+        if right == 0xFFF {
+            return Some(0)
+        }
+
+        let left = self.get_left(data, sym)?;
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                self.symlen[child] = self.set_symlen(data, child, visited)?;
+            }
+        }
+
+        self.symlen[left].checked_add(self.symlen[right])?.checked_add(1)
+    }
+
+    /// Finds the value stored at `idx`, reading the block holding it from
+    /// the table file. `header` is the start of the file read by `open`.
+    fn decompress(&self, header: &[u8], file: &File, idx: u64) -> Option<i32> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as i32)
+        }
+
+        // The sparse index points to the block holding the value in the
+        // middle of each span, so from there step to the right block
+        let k = idx / self.span as u64;
+        if k >= self.sparse_index_size as u64 {
+            return None
+        }
+
+        let entry = read_file(file, self.sparse_index + 6 * k, 6)?;
+        let mut block = read_u32_le(&entry, 0)? as usize;
+        let mut offset = read_u16_le(&entry, 4)? as i64 +
+            (idx % self.span as u64) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| {
+            if block >= self.num_blocks {
+                return None
+            }
+            let bytes = read_file(file, self.block_lengths + 2 * block as u64, 2)?;
+            read_u16_le(&bytes, 0).map(i64::from)
+        };
+
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // Read symbols from the start of the block until reaching the one
+        // that expands to cover the offset
+        let data = read_file(file, self.data + (block * self.block_size) as u64, self.block_size)?;
+        let mut buf = read_u64_be(&data, 0)?;
+        let mut buf_size = 64;
+        let mut pos = 8;
+
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < *self.base64.get(len)? {
+                len += 1;
+            }
+
+            sym = ((buf - self.base64[len]) >> (64 - len - self.min_sym_len)) as usize;
+            sym += read_u16_le(header, self.lowest_sym + 2 * len)? as usize;
+            let sym_len = *self.symlen.get(sym)? as i64;
+
+            if offset < sym_len + 1 {
+                break
+            }
+
+            offset -= sym_len + 1;
+            len += self.min_sym_len;
+            buf <<= len;
+            buf_size -= len;
+
+            // The last symbols of a block can leave the buffer reading past
+            // its end, which is never decoded
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32_be(&data, pos).unwrap_or(0) as u64) << (64 - buf_size);
+                pos += 4;
+            }
+        }
+
+        // Expand the symbol down to the single value at the offset. Each
+        // step goes to a symbol covering fewer values, so a bad table can't
+        // loop forever.
+        while self.symlen[sym] != 0 {
+            let left = self.get_left(header, sym)?;
+            let left_len = *self.symlen.get(left)? as i64;
+
+            let next = if offset < left_len + 1 {
+                left
+            } else {
+                offset -= left_len + 1;
+                self.get_right(header, sym)?
+            };
+
+            if *self.symlen.get(next)? >= self.symlen[sym] {
+                return None
+            }
+            sym = next;
+        }
+
+        self.get_left(header, sym).map(|value| value as i32)
+    }
+}
+
+/// A WDL or DTZ table file. Only the header is kept in memory, and the
+/// blocks of values are read from the file as positions need them.
+#[derive(Debug)]
+struct Table {
+    kind: TableKind,
+    path: PathBuf,
+    /// The start of the file up to the sparse indices
+    header: Vec<u8>,
+    /// Both sides have the same pieces, so only white to move is stored
+    symmetric: bool,
+    has_pawns: bool,
+    /// Some side has a piece other than the king that it has only one of
+    has_unique_pieces: bool,
+    piece_count: usize,
+    /// Pawns of the leading side then the other side
+    pawn_count: [usize; 2],
+    /// Indexed by the file of the leading pawn then the side to move
+    pairs: [[PairsData; 2]; 4],
+    /// Where the DTZ value maps start in the header
+    map: usize,
+}
+
+impl Table {
+    /// Reads the header of a table, named by its pieces like `KRvKP`, from
+    /// a file. Returns `None` if the file can't be read or doesn't hold
+    /// that table.
+    fn open(path: &Path, name: &str, kind: TableKind) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let file_len = file.metadata().ok()?.len();
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC
+        };
+
+        let (white, black) = name.split_once('v')?;
+        let count = |pieces: &str, c: char| pieces.chars().filter(|&p| p == c).count();
+
+        let white_pawns = count(white, 'P');
+        let black_pawns = count(black, 'P');
+
+        // The side with fewer pawns leads as that compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+
+        // Piece codes are where the letter is in this string
+        let mut codes: Vec<u8> = [(white, 0), (black, 8)].into_iter()
+            .flat_map(|(pieces, colour)| pieces.chars().map(move |c| " PNBRQK".find(c).unwrap_or(0) as u8 + colour))
+            .collect();
+        codes.sort_unstable();
+
+        let mut table = Self {
+            kind,
+            path: path.to_path_buf(),
+            header: Vec::new(),
+            symmetric: white == black,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: "QRBNP".chars().any(|c| count(white, c) == 1 || count(black, c) == 1),
+            piece_count: white.len() + black.len(),
+            pawn_count: if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] },
+            pairs: Default::default(),
+            map: 0,
+        };
+
+        // The header's length is only known once it has been read, so read
+        // more of the file until it fits
+        let mut read_len = HEADER_READ.min(file_len);
+        loop {
+            table.header = read_file(&file, 0, read_len as usize)?;
+            if table.header.get(..4)? != magic {
+                return None
+            }
+
+            if table.read_header(&codes, file_len).is_some() {
+                return Some(table)
+            }
+
+            if read_len == file_len || read_len == MAX_HEADER {
+                return None
+            }
+            read_len = (read_len * 4).min(file_len).min(MAX_HEADER);
+        }
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == TableKind::Wdl && !self.symmetric { 2 } else { 1 }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns { 4 } else { 1 }
+    }
+
+    /// Reads the pieces, groups and offsets of every part of the table.
+    /// `codes` are the sorted piece codes of the table's name. Returns
+    /// `None` if the header doesn't match the name, is cut short or points
+    /// past the end of the file.
+    fn read_header(&mut self, codes: &[u8], file_len: u64) -> Option<()> {
+        const SPLIT: u8 = 1;
+        const HAS_PAWNS: u8 = 2;
+
+        let data = &self.header;
+        let flags = read_u8(data, 4)?;
+        if (flags & HAS_PAWNS != 0) != self.has_pawns || (flags & SPLIT != 0) == self.symmetric {
+            return None
+        }
+
+        let mut pairs: [[PairsData; 2]; 4] = Default::default();
+        let pawns_both_sides = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pos = 5;
+
+        for (file, parts) in pairs.iter_mut().enumerate().take(self.files()) {
+            let first = read_u8(data, pos)?;
+            let second = if pawns_both_sides { read_u8(data, pos + 1)? } else { 0xFF };
+            let order = [
+                [first & 0xF, second & 0xF],
+                [first >> 4, second >> 4]
+            ];
+            pos += 1 + pawns_both_sides as usize;
+
+            for k in 0..self.piece_count {
+                let byte = read_u8(data, pos)?;
+                parts[0].pieces[k] = byte & 0xF;
+                parts[1].pieces[k] = byte >> 4;
+                pos += 1;
+            }
+
+            // Indexing relies on the pieces being the ones in the name, with
+            // a leading pawn first
+            for part in parts.iter().take(self.sides()) {
+                let mut pieces = part.pieces[..self.piece_count].to_vec();
+                pieces.sort_unstable();
+
+                if pieces != codes || (self.has_pawns && part.pieces[0] & 7 != 1) {
+                    return None
+                }
+            }
+
+            for (part, order) in parts.iter_mut().zip(order).take(self.sides()) {
+                part.set_groups(self, order, file);
+            }
+        }
+        pos += pos & 1;
+
+        for parts in pairs.iter_mut().take(self.files()) {
+            for part in parts.iter_mut().take(self.sides()) {
+                pos = part.set_sizes(data, pos)?;
+            }
+        }
+
+        let map = pos;
+        if self.kind == TableKind::Dtz {
+            for parts in pairs.iter_mut().take(self.files()) {
+                let part = &mut parts[0];
+                if part.flags & FLAG_MAPPED == 0 {
+                    continue
+                }
+
+                for map_idx in part.map_idx.iter_mut() {
+                    if part.flags & FLAG_WIDE != 0 {
+                        pos += pos & 1;
+                        *map_idx = (pos - map) / 2 + 1;
+                        pos += 2 * read_u16_le(data, pos)? as usize + 2;
+                    } else {
+                        *map_idx = pos - map + 1;
+                        pos += read_u8(data, pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        // Everything up to here has to be in the header
+        data.get(..pos)?;
+
+        let mut pos = pos as u64;
+        for parts in pairs.iter_mut().take(self.files()) {
+            for part in parts.iter_mut().take(self.sides()) {
+                part.sparse_index = pos;
+                pos = pos.checked_add(part.sparse_index_size as u64 * 6)?;
+            }
+        }
+
+        for parts in pairs.iter_mut().take(self.files()) {
+            for part in parts.iter_mut().take(self.sides()) {
+                part.block_lengths = pos;
+                pos = pos.checked_add(part.block_lengths_size as u64 * 2)?;
+            }
+        }
+
+        for parts in pairs.iter_mut().take(self.files()) {
+            for part in parts.iter_mut().take(self.sides()) {
+                // Blocks are aligned to 64 bytes
+                pos = pos.checked_add(0x3F)? & !0x3F;
+                part.data = pos;
+                pos = pos.checked_add((part.num_blocks as u64).checked_mul(part.block_size as u64)?)?;
+            }
+        }
+
+        if pos > file_len {
+            return None
+        }
+
+        self.pairs = pairs;
+        self.map = map;
+
+        Some(())
+    }
+
+    /// Looks up the position in the table. `flipped` is set when black has
+    /// the pieces the table names first. Returns `Ok(None)` for a DTZ table
+    /// that only stores the other side to move, and `Err` if the value
+    /// can't be read.
+    fn probe(&self, game: &Game, flipped: bool, wdl: Wdl) -> Result<Option<i32>, ()> {
+        let maps = maps();
+        let black_to_move = game.get_side_to_play() == Side::Black;
+
+        // Tables are stored with the side named first as white, and when
+        // both sides are the same only with white to move
+        let flip = flipped || (self.symmetric && black_to_move);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip != black_to_move) as usize;
+
+        let mut board: Vec<(Square, u8)> = game.iter()
+            .map(|p| {
+                let [x, y] = p.get_loc().get_x_and_y();
+                (square_of(x, y), piece_code(p.get_side(), p.get_type()))
+            })
+            .collect();
+        board.sort_unstable_by_key(|&(sq, _)| sq);
+
+        let mut squares = Vec::with_capacity(MAX_PIECES);
+        let mut pieces = Vec::with_capacity(MAX_PIECES);
+        let mut lead_pawns = 0;
+        let mut file = 0;
+
+        // With pawns there is a part of the table for each file the leading
+        // pawn can be on after mirroring, and the leading pawns come first
+        if self.has_pawns {
+            let lead_pawn = self.pairs[0][0].pieces[0] ^ flip_color;
+
+            for &(sq, code) in board.iter().filter(|&&(_, code)| code == lead_pawn) {
+                squares.push(sq ^ flip_squares);
+                pieces.push(code ^ flip_color);
+            }
+            lead_pawns = squares.len();
+
+            let lead = (0..lead_pawns).max_by_key(|&i| maps.pawns[squares[i]]).ok_or(())?;
+            squares.swap(0, lead);
+
+            file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+
+        if self.kind == TableKind::Dtz {
+            let flags = self.pairs[file][0].flags;
+
+            if (flags & FLAG_STM) as usize != stm && (self.has_pawns || !self.symmetric) {
+                return Ok(None)
+            }
+        }
+
+        let lead_pawn = if self.has_pawns { Some(self.pairs[0][0].pieces[0] ^ flip_color) } else { None };
+        for &(sq, code) in board.iter().filter(|&&(_, code)| Some(code) != lead_pawn) {
+            squares.push(sq ^ flip_squares);
+            pieces.push(code ^ flip_color);
+        }
+
+        let part = &self.pairs[file][if self.kind == TableKind::Wdl { stm } else { 0 }];
+        let size = squares.len();
+        if size != self.piece_count {
+            return Err(())
+        }
+
+        // Put the pieces in the order the table indexes them
+        for i in lead_pawns..size - 1 {
+            if let Some(j) = (i + 1..size).find(|&j| pieces[j] == part.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // Mirror so the leading piece is on files a to d
+        if file_of(squares[0]) > 3 {
+            for sq in squares.iter_mut() {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx = if self.has_pawns {
+            let mut idx = maps.lead_pawn_idx[lead_pawns][squares[0]];
+
+            squares[1..lead_pawns].sort_by_key(|&sq| maps.pawns[sq]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += maps.binomial[i][maps.pawns[sq]];
+            }
+
+            idx
+        } else {
+            self.encode_leading_pieces(&mut squares, part.group_len[0])
+        };
+
+        // The rest of the groups are indexed by the squares left after the
+        // groups before them, as combinations of their squares
+        idx *= part.group_idx[0];
+
+        let mut start = part.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+
+        while part.group_len[next] != 0 {
+            let len = part.group_len[next];
+            squares[start..start + len].sort_unstable();
+
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                let pawn_adjust = if remaining_pawns { 8 } else { 0 };
+
+                // Pawns on the first or last rank would be off the table
+                n += maps.binomial[i + 1][sq.checked_sub(adjust + pawn_adjust).ok_or(())?];
+            }
+
+            remaining_pawns = false;
+            idx += n * part.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        let file_handle = File::open(&self.path).map_err(|_| ())?;
+        let value = part.decompress(&self.header, &file_handle, idx).ok_or(())?;
+
+        match self.kind {
+            TableKind::Wdl => Ok(Some(value - 2)),
+            TableKind::Dtz => self.map_dtz(file, value, wdl).map(Some).ok_or(())
+        }
+    }
+
+    /// Indexes the leading group of a table without pawns, mirroring the
+    /// board so the first piece is in the a1-d1-d4 triangle
+    fn encode_leading_pieces(&self, squares: &mut [Square], lead_len: usize) -> u64 {
+        let maps = maps();
+
+        if rank_of(squares[0]) > 3 {
+            for sq in squares.iter_mut() {
+                *sq ^= 56;
+            }
+        }
+
+        // Mirror along the a1-h8 diagonal if the first piece of the group
+        // off it is above it
+        if let Some(i) = (0..lead_len).find(|&i| off_diagonal(squares[i]) != 0) {
+            if off_diagonal(squares[i]) > 0 {
+                for sq in squares[i..].iter_mut() {
+                    *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                }
+            }
+        }
+
+        if !self.has_unique_pieces {
+            return maps.kk[maps.a1_d1_d4[squares[0]]][squares[1]]
+        }
+
+        let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+        let adjust1 = (s1 > s0) as u64;
+        let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+
+        if off_diagonal(s0) != 0 {
+            (maps.a1_d1_d4[s0] as u64 * 63 + s1 as u64 - adjust1) * 62 + s2 as u64 - adjust2
+        } else if off_diagonal(s1) != 0 {
+            (6 * 63 + rank_of(s0) as u64 * 28 + maps.b1_h1_h7[s1] as u64) * 62 + s2 as u64 - adjust2
+        } else if off_diagonal(s2) != 0 {
+            6 * 63 * 62 + 4 * 28 * 62 +
+                rank_of(s0) as u64 * 7 * 28 +
+                (rank_of(s1) as u64 - adjust1) * 28 +
+                maps.b1_h1_h7[s2] as u64
+        } else {
+            6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 +
+                rank_of(s0) as u64 * 7 * 6 +
+                (rank_of(s1) as u64 - adjust1) * 6 +
+                (rank_of(s2) as u64 - adjust2)
+        }
+    }
+
+    /// DTZ values are stored as an index into a list for each result, and
+    /// in moves rather than plies when that makes no difference
+    fn map_dtz(&self, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        let part = &self.pairs[file][0];
+        let mut value = value;
+
+        if part.flags & FLAG_MAPPED != 0 {
+            let list = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3
+            };
+            let i = part.map_idx[list] + usize::try_from(value).ok()?;
+
+            value = if part.flags & FLAG_WIDE != 0 {
+                read_u16_le(&self.header, self.map + 2 * i)? as i32
+            } else {
+                read_u8(&self.header, self.map + i)? as i32
+            };
+        }
+
+        let in_moves = match wdl {
+            Wdl::Win => part.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => part.flags & FLAG_LOSS_PLIES == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false
+        };
+
+        Some(if in_moves { value * 2 + 1 } else { value + 1 })
+    }
+}
+
+#[derive(Debug)]
+struct TableFiles {
+    wdl: Table,
+    dtz: Option<Table>,
+}
+
+#[derive(Debug, Default)]
+struct Tablebases {
+    /// Keyed by the table name, like `KRvKP`
+    tables: HashMap<String, TableFiles>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    fn find(paths: &str) -> Self {
+        let mut tablebases = Self::default();
+
+        if paths.is_empty() || paths == "<empty>" {
+            return tablebases
+        }
+
+        let separator = if cfg!(windows) { ';' } else { ':' };
+
+        for dir in paths.split(separator).filter(|d| !d.is_empty()) {
+            let Ok(entries) = std::fs::read_dir(dir) else { continue };
+
+            for entry in entries.flatten() {
+                let file_name = entry.file_name();
+                let Some(name) = file_name.to_str().and_then(|n| n.strip_suffix(WDL_SUFFIX)) else { continue };
+
+                if !is_table_name(name) || tablebases.tables.contains_key(name) {
+                    continue
+                }
+
+                // Tables that can't be read are left out, so positions
+                // needing them aren't probed
+                let Some(wdl) = Table::open(&entry.path(), name, TableKind::Wdl) else { continue };
+                let dtz_path = Path::new(dir).join(format!("{}{}", name, DTZ_SUFFIX));
+                let dtz = Table::open(&dtz_path, name, TableKind::Dtz);
+
+                tablebases.max_pieces = tablebases.max_pieces.max(name.len() - 1);
+                tablebases.tables.insert(name.to_string(), TableFiles { wdl, dtz });
+            }
+        }
+
+        tablebases
+    }
+
+    fn can_probe(&self, game: &Game) -> bool {
+        game.get_castling_rights().is_empty() && game.iter().count() <= self.max_pieces
+    }
+
+    /// Probes a single table for the position, without looking at captures
+    fn probe_table(&self, game: &Game, kind: TableKind, wdl: Wdl) -> Result<Option<i32>, ()> {
+        let pieces: Vec<_> = game.iter().collect();
+        if pieces.len() == 2 {
+            return Ok(Some(0))
+        }
+
+        let material = |side| {
+            let mut name = String::new();
+            for (piece_type, c) in [
+                (PieceType::King, 'K'), (PieceType::Queen, 'Q'), (PieceType::Rook, 'R'),
+                (PieceType::Bishop, 'B'), (PieceType::Knight, 'N'), (PieceType::Pawn, 'P')
+            ] {
+                let count = pieces.iter().filter(|p| p.is_side(side) && p.is_type(piece_type)).count();
+                name.extend(std::iter::repeat_n(c, count));
+            }
+            name
+        };
+        let white = material(Side::White);
+        let black = material(Side::Black);
+
+        let white_first = format!("{}v{}", white, black);
+        let (name, flipped) = if self.tables.contains_key(&white_first) {
+            (white_first, false)
+        } else {
+            (format!("{}v{}", black, white), true)
+        };
+
+        let files = self.tables.get(&name).ok_or(())?;
+        let table = match kind {
+            TableKind::Wdl => Some(&files.wdl),
+            TableKind::Dtz => files.dtz.as_ref()
+        }.ok_or(())?;
+
+        table.probe(game, flipped, wdl)
+    }
+
+    /// Finds the result of the position, also looking at captures (and pawn
+    /// moves if `zeroing_moves` is set) as the tables don't store the right
+    /// result when one of them is best. Also returns whether the best move
+    /// is a capture or pawn move, in which case the DTZ tables can't be
+    /// trusted either.
+    fn search(&self, game: &mut Game, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let mut moves = MoveList::new();
+        game.generate_legal_moves(GenMode::All, &mut moves);
+
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for &move_ in moves.iter() {
+            let is_pawn = move_.get_piece().is_type(PieceType::Pawn);
+            if !(is_capture(game, &move_) || (zeroing_moves && is_pawn)) {
+                continue
+            }
+
+            searched += 1;
+
+            let undo = game.make_move(move_);
+            let result = self.search(game, false);
+            game.unmake_move(undo);
+
+            let value = -result?.0;
+            if value > best {
+                best = value;
+
+                if value == Wdl::Win {
+                    return Some((value, true))
+                }
+            }
+        }
+
+        // Once every move has been searched the table isn't needed, and
+        // would be wrong if it is there because of an en passant capture
+        let searched_all = searched > 0 && searched == moves.len();
+
+        let value = if searched_all {
+            best
+        } else {
+            Wdl::from_i32(self.probe_table(game, TableKind::Wdl, Wdl::Draw).ok()??)
+        };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || searched_all))
+        }
+
+        Some((value, false))
+    }
+
+    fn probe_dtz(&self, game: &mut Game) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(game, true)?;
+
+        if wdl == Wdl::Draw {
+            return Some(0)
+        }
+
+        if zeroing_best {
+            return Some(wdl.dtz_before_zeroing())
+        }
+
+        if let Some(dtz) = self.probe_table(game, TableKind::Dtz, wdl).ok()? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * (wdl as i32).signum())
+        }
+
+        // The table only stores the other side to move, so search one move
+        // and take the best DTZ that keeps the result
+        let mut moves = MoveList::new();
+        game.generate_legal_moves(GenMode::All, &mut moves);
+
+        let mut min_dtz = i32::MAX;
+
+        for &move_ in moves.iter() {
+            let zeroing = is_capture(game, &move_) || move_.get_piece().is_type(PieceType::Pawn);
+
+            let undo = game.make_move(move_);
+
+            // After a zeroing move take the DTZ from before it, using the
+            // search to get the sign
+            let mut dtz = if zeroing {
+                self.search(game, false).map(|(wdl, _)| -wdl.dtz_before_zeroing())
+            } else {
+                self.probe_dtz(game).map(|dtz| -dtz)
+            };
+
+            let mut replies = MoveList::new();
+            game.generate_legal_moves(GenMode::All, &mut replies);
+            let mates = replies.is_empty() && game.is_side_in_check(game.get_side_to_play());
+
+            game.unmake_move(undo);
+
+            let dtz = dtz.as_mut()?;
+
+            if *dtz == 1 && mates {
+                min_dtz = 1;
+            }
+
+            if !zeroing {
+                *dtz += dtz.signum();
+            }
+
+            if *dtz < min_dtz && dtz.signum() == (wdl as i32).signum() {
+                min_dtz = *dtz;
+            }
+        }
+
+        // With no legal moves the side to move has been mated
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+}
+
+fn is_capture(game: &Game, move_: &Move) -> bool {
+    match move_ {
+        Move::EnPassant(_) => true,
+        Move::Castle(_) => false,
+        _ => game.get_piece_at(move_.get_to()).is_some()
+    }
+}
+
+/// Table names are the pieces of each side from the king down, like `KRvKP`
+fn is_table_name(name: &str) -> bool {
+    let Some((white, black)) = name.split_once('v') else { return false };
+
+    [white, black].iter().all(|side| {
+        side.starts_with('K') && side[1..].chars().all(|c| "QRBNP".contains(c))
+    }) && name.len() - 1 <= MAX_PIECES
+}
@@ -0,0 +1,494 @@
+//! Probing tables written in the same format as the Syzygy files. Each part
+//! of a table is compressed the way the generator does it, with a canonical
+//! Huffman code over the values and pairs of symbols, split into blocks
+//! with a sparse index, so reading them goes through the same code as the
+//! real tables. The values are made from the index of each position, which
+//! is worked out by hand for the positions probed. The tables the
+//! generator made aren't kept here, so the test that probes them is ignored
+//! and run with `--ignored` once `SYZYGY_PATH` points at them.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::game::*;
+use crate::tb::*;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/// Part flags for a DTZ table storing white to move with wins in plies
+const DTZ_WIN_PLIES: u8 = 4;
+
+const BLOCK_SIZE_LOG: u8 = 6;
+const SPAN_LOG: u8 = 6;
+
+/// A symbol of the Huffman code, which is a value or a pair of symbols
+enum Symbol {
+    Value(u16),
+    Pair(usize, usize),
+}
+
+/// Compresses the values of one part of a table, returning its sizes and
+/// Huffman code, sparse index, block lengths and blocks
+fn compress_part(flags: u8, values: &[u16]) -> [Vec<u8>; 4] {
+    if values.iter().all(|&value| value == values[0]) {
+        return [vec![flags | 0x80, values[0] as u8], Vec::new(), Vec::new(), Vec::new()]
+    }
+
+    let mut symbols = Vec::new();
+    let mut expands = Vec::new();
+    let mut leaves = HashMap::new();
+    let mut sequence: Vec<usize> = values.iter()
+        .map(|&value| *leaves.entry(value).or_insert_with(|| {
+            symbols.push(Symbol::Value(value));
+            expands.push(1);
+            symbols.len() - 1
+        }))
+        .collect();
+
+    // Replace the most common pair of symbols a few times. Symbol 0xFFF
+    // can't be used as it marks values in the tree.
+    for _ in 0..8 {
+        if symbols.len() >= 0xFFF {
+            break
+        }
+
+        let mut counts = HashMap::new();
+        for pair in sequence.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+        }
+
+        let Some((&(left, right), &count)) = counts.iter().max_by_key(|&(&pair, &count)| (count, Reverse(pair))) else { break };
+        if count < 4 {
+            break
+        }
+
+        symbols.push(Symbol::Pair(left, right));
+        expands.push(expands[left] + expands[right]);
+        let pair = symbols.len() - 1;
+
+        let mut replaced = Vec::with_capacity(sequence.len());
+        let mut i = 0;
+        while i < sequence.len() {
+            if i + 1 < sequence.len() && sequence[i] == left && sequence[i + 1] == right {
+                replaced.push(pair);
+                i += 2;
+            } else {
+                replaced.push(sequence[i]);
+                i += 1;
+            }
+        }
+        sequence = replaced;
+    }
+
+    // Huffman code lengths, counting every symbol once so the ones only
+    // used inside pairs still get a code
+    let mut frequency = vec![1u64; symbols.len()];
+    for &symbol in &sequence {
+        frequency[symbol] += 1;
+    }
+
+    let mut heap: BinaryHeap<_> = frequency.iter().enumerate().map(|(i, &f)| Reverse((f, i))).collect();
+    let mut parent = vec![usize::MAX; symbols.len()];
+    while heap.len() > 1 {
+        let Reverse((f1, n1)) = heap.pop().unwrap();
+        let Reverse((f2, n2)) = heap.pop().unwrap();
+        parent.push(usize::MAX);
+        parent[n1] = parent.len() - 1;
+        parent[n2] = parent.len() - 1;
+        heap.push(Reverse((f1 + f2, parent.len() - 1)));
+    }
+
+    let code_len: Vec<usize> = (0..symbols.len())
+        .map(|mut node| {
+            let mut len = 0;
+            while parent[node] != usize::MAX {
+                node = parent[node];
+                len += 1;
+            }
+            len
+        })
+        .collect();
+    let min_len = *code_len.iter().min().unwrap();
+    let max_len = *code_len.iter().max().unwrap();
+    assert!(max_len <= 32);
+
+    // Symbols are numbered from the longest code down, and codes of each
+    // length count up from the lowest
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|&s| (Reverse(code_len[s]), s));
+    let mut number = vec![0; symbols.len()];
+    for (n, &s) in order.iter().enumerate() {
+        number[s] = n;
+    }
+
+    let longer = |len: usize| code_len.iter().filter(|&&l| l > len).count();
+    let mut base = vec![0u64; max_len + 2];
+    for len in (min_len..max_len).rev() {
+        base[len] = (base[len + 1] + (longer(len) - longer(len + 1)) as u64) / 2;
+    }
+    let codes: Vec<u64> = (0..symbols.len())
+        .map(|s| base[code_len[s]] + (number[s] - longer(code_len[s])) as u64)
+        .collect();
+
+    // Fill blocks with whole symbols
+    let block_size = 1 << BLOCK_SIZE_LOG;
+    let mut blocks: Vec<Vec<usize>> = vec![Vec::new()];
+    let (mut bits, mut count) = (0, 0);
+    for &symbol in &sequence {
+        if bits + code_len[symbol] > 8 * block_size || count + expands[symbol] > 0x10000 {
+            blocks.push(Vec::new());
+            bits = 0;
+            count = 0;
+        }
+        blocks.last_mut().unwrap().push(symbol);
+        bits += code_len[symbol];
+        count += expands[symbol];
+    }
+
+    let mut data = vec![0u8; blocks.len() * block_size];
+    let mut block_lengths = Vec::new();
+    let mut starts = Vec::new();
+    let mut start = 0;
+    for (i, block) in blocks.iter().enumerate() {
+        let mut bit = i * block_size * 8;
+        for &symbol in block {
+            for shift in (0..code_len[symbol]).rev() {
+                if codes[symbol] >> shift & 1 == 1 {
+                    data[bit / 8] |= 0x80 >> (bit % 8);
+                }
+                bit += 1;
+            }
+        }
+
+        let count: usize = block.iter().map(|&s| expands[s]).sum();
+        block_lengths.extend(((count - 1) as u16).to_le_bytes());
+        starts.push(start);
+        start += count;
+    }
+
+    // Each entry points at the value in the middle of its span, which for
+    // the last span can be past the end of the last block
+    let span = 1 << SPAN_LOG;
+    let mut sparse_index = Vec::new();
+    for k in 0..values.len().div_ceil(span) {
+        let target = k * span + span / 2;
+        let block = starts.partition_point(|&start| start <= target) - 1;
+        sparse_index.extend((block as u32).to_le_bytes());
+        sparse_index.extend(((target - starts[block]) as u16).to_le_bytes());
+    }
+
+    let mut sizes = vec![flags, BLOCK_SIZE_LOG, SPAN_LOG, 0];
+    sizes.extend((blocks.len() as u32).to_le_bytes());
+    sizes.extend([max_len as u8, min_len as u8]);
+    for len in min_len..=max_len {
+        sizes.extend((longer(len) as u16).to_le_bytes());
+    }
+    sizes.extend((symbols.len() as u16).to_le_bytes());
+    for &s in &order {
+        let (left, right) = match symbols[s] {
+            Symbol::Value(value) => (value as usize, 0xFFF),
+            Symbol::Pair(left, right) => (number[left], number[right])
+        };
+        sizes.extend([left as u8, (left >> 8 | (right & 0xF) << 4) as u8, (right >> 4) as u8]);
+    }
+    if symbols.len() & 1 == 1 {
+        sizes.push(0);
+    }
+
+    [sizes, sparse_index, block_lengths, data]
+}
+
+/// Writes a table file from its magic number, the header bytes up to the
+/// parts, and the flags and values of each part in the order they're read
+fn write_table(path: &Path, magic: [u8; 4], header: &[u8], parts: &[(u8, Vec<u16>)]) {
+    let parts: Vec<_> = parts.iter().map(|(flags, values)| compress_part(*flags, values)).collect();
+
+    let mut bytes = magic.to_vec();
+    bytes.extend(header);
+    bytes.resize(bytes.len().next_multiple_of(2), 0);
+
+    for section in 0..3 {
+        for part in &parts {
+            bytes.extend(&part[section]);
+        }
+    }
+    for part in &parts {
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        bytes.extend(&part[3]);
+    }
+
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn values(size: u64, value: impl Fn(u64) -> u16) -> Vec<u16> {
+    (0..size).map(value).collect()
+}
+
+fn probe(fen: &str) -> (Option<Wdl>, Option<i32>) {
+    let mut game = Game::from_fen(fen).unwrap();
+    let result = (probe_wdl(&mut game), probe_dtz(&mut game));
+
+    // Captures tried while probing are taken back
+    assert_eq!(game.to_fen(), fen);
+
+    result
+}
+
+/// The tables found by `init` are shared, so tests that use them take turns
+fn lock_tables() -> MutexGuard<'static, ()> {
+    static TABLES: Mutex<()> = Mutex::new(());
+    TABLES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A directory for tables that is removed along with them when dropped
+struct TableDir(PathBuf);
+
+impl TableDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!("chess_syzygy_{}_{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        Self(dir)
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+
+    fn to_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TableDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+const KQK: u64 = 31332;
+const KQK_PIECES: [u8; 5] = [0x01, 0x00, 0x66, 0x55, 0xEE];
+
+/// The leading group is three unique pieces, each table side listing the
+/// white king, white queen and black king. White to move wins, cursed
+/// every fifth position, and black to move loses the same way. DTZ is the
+/// index in plies.
+fn write_kqk(dir: &TableDir) {
+    write_table(&dir.join("KQvK.rtbw"), WDL_MAGIC, &KQK_PIECES, &[
+        (0, values(KQK, |idx| if idx % 5 == 2 { 3 } else { 4 })),
+        (0, values(KQK, |idx| if idx % 5 == 2 { 1 } else { 0 })),
+    ]);
+    write_table(&dir.join("KQvK.rtbz"), DTZ_MAGIC, &KQK_PIECES, &[
+        (DTZ_WIN_PLIES, values(KQK, |idx| (idx % 4093) as u16)),
+    ]);
+}
+
+/// A part for each file of the pawn, with 6 pawn squares times 63 for the
+/// white king times 62 for the black king
+fn write_kpk(dir: &TableDir) {
+    let kpk = 6 * 63 * 62;
+    let kpk_pieces = [[0x03].as_slice(), &[0x00, 0x11, 0x66, 0xEE].repeat(4)].concat();
+    write_table(&dir.join("KPvK.rtbw"), WDL_MAGIC, &kpk_pieces, &vec![vec![
+        (0, values(kpk, |idx| if idx % 3 == 0 { 2 } else { 4 })),
+        (0, values(kpk, |idx| if idx % 3 == 0 { 2 } else { 0 })),
+    ]; 4].concat());
+    write_table(&dir.join("KPvK.rtbz"), DTZ_MAGIC, &kpk_pieces, &vec![
+        (DTZ_WIN_PLIES, values(kpk, |idx| (idx % 4093) as u16));
+        4
+    ]);
+}
+
+/// The kings lead with 462 placements, then the pair of knights on the 62
+/// squares left. Black to move is a single value, and there is no DTZ
+/// table.
+fn write_knnk(dir: &TableDir) {
+    let knnk = 462 * 62 * 61 / 2;
+    write_table(&dir.join("KNNvK.rtbw"), WDL_MAGIC, &[0x01, 0x00, 0x66, 0xEE, 0x22, 0x22], &[
+        (0, values(knnk, |idx| 4 - (idx % 5) as u16)),
+        (0, vec![2]),
+    ]);
+}
+
+/// Symmetric, so only white to move is stored, with the white pawn leading
+/// and the black pawn on 47 squares after it. Only the d file has more than
+/// one value.
+fn write_kpkp(dir: &TableDir) {
+    let kpkp = 6 * 47 * 62 * 61;
+    let kpkp_pieces = [[0x02].as_slice(), &[0x00, 0x11, 0x11, 0x99, 0x66, 0xEE].repeat(4)].concat();
+    write_table(&dir.join("KPvKP.rtbw"), WDL_MAGIC, &kpkp_pieces, &[
+        (0, vec![2]),
+        (0, vec![2]),
+        (0, vec![2]),
+        (0, values(kpkp, |idx| (idx % 5) as u16)),
+    ]);
+}
+
+#[test]
+fn finding_tables() {
+    let _lock = lock_tables();
+    let dir = TableDir::new();
+    write_kqk(&dir);
+    write_knnk(&dir);
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    std::fs::write(dir.join("KRvK.rtbz"), "").unwrap();
+
+    // DTZ tables without a WDL table and other files are left out
+    assert_eq!(init(dir.to_str()), 2);
+    assert_eq!(max_pieces(), 4);
+
+    assert_eq!(init(""), 0);
+    assert_eq!(max_pieces(), 0);
+    assert_eq!(probe("8/7k/8/8/8/2Q5/8/1K6 w - - 0 1"), (None, None));
+}
+
+#[test]
+fn pawnless_tables() {
+    let _lock = lock_tables();
+    let dir = TableDir::new();
+    write_kqk(&dir);
+    write_knnk(&dir);
+    assert_eq!(init(dir.to_str()), 2);
+
+    // Index (17 * 62 + 53) from the king on b1, queen on c3 and king on h7
+    assert_eq!(probe("8/7k/8/8/8/2Q5/8/1K6 w - - 0 1"), (Some(Wdl::CursedWin), Some(1107 * 2 + 1 + 100)));
+    assert_eq!(probe("8/7k/8/8/8/2Q5/8/1K6 b - - 0 1").0, Some(Wdl::BlessedLoss));
+    // Turned around to put the white king in the a1-d1-d4 triangle
+    assert_eq!(probe("6K1/8/5Q2/8/8/8/k7/8 w - - 0 1"), (Some(Wdl::CursedWin), Some(2315)));
+    // Black having the queen uses the same table
+    assert_eq!(probe("1k6/8/2q5/8/8/8/7K/8 b - - 0 1"), (Some(Wdl::CursedWin), Some(2315)));
+    // The king on the diagonal with the queen above it is mirrored, for
+    // index (6 * 63 + 2 * 28 + 3) * 62 + 59
+    assert_eq!(probe("8/8/7k/Q7/8/2K5/8/8 w - - 0 1"), (Some(Wdl::Win), Some(27153 % 4093 + 1)));
+    // Taking the queen draws
+    assert_eq!(probe("8/8/8/8/8/8/3Qk3/K7 b - - 0 1"), (Some(Wdl::Draw), Some(0)));
+    assert_eq!(probe("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), (Some(Wdl::Draw), Some(0)));
+
+    // Index 37 + (54 + 1830) * 462, with the black king on d6 being the
+    // 37th square the kk map allows. There's no DTZ table.
+    assert_eq!(probe("N6N/8/3k4/8/8/8/8/1K6 w - - 0 1"), (Some(Wdl::Win), None));
+    assert_eq!(probe("N6N/8/3k4/8/8/8/8/1K6 b - - 0 1").0, Some(Wdl::Draw));
+}
+
+#[test]
+fn pawn_tables() {
+    let _lock = lock_tables();
+    let dir = TableDir::new();
+    write_kpk(&dir);
+    assert_eq!(init(dir.to_str()), 1);
+
+    // Index 2 + 33 * 378 from the pawn on d4, king on a1 and king on d5
+    assert_eq!(probe("8/8/8/3k4/3P4/8/8/K7 w - - 0 1"), (Some(Wdl::Win), Some(12476 % 4093 + 1)));
+    // Pawns on the e to h files are mirrored
+    assert_eq!(probe("8/8/8/4k3/4P3/8/8/7K w - - 0 1"), (Some(Wdl::Win), Some(198)));
+    // Index 1 + 62 * 6 + 24 * 378 from the b file part
+    assert_eq!(probe("7K/8/8/8/1k6/1P6/8/8 w - - 0 1"), (Some(Wdl::Win), Some(9445 % 4093 + 1)));
+    // Index 0 + 7 * 6 + 54 * 378 from the c file part with black to move
+    assert_eq!(probe("k7/8/8/8/8/8/2P5/7K b - - 0 1").0, Some(Wdl::Draw));
+    assert_eq!(probe("k7/8/8/8/8/8/2P5/7K w - - 0 1").0, Some(Wdl::Draw));
+}
+
+#[test]
+fn symmetric_tables() {
+    let _lock = lock_tables();
+    let dir = TableDir::new();
+    write_kpk(&dir);
+    write_kpkp(&dir);
+    assert_eq!(init(dir.to_str()), 2);
+
+    // Index 3 + 27 * 6 + 7 * 282 + 53 * 17484 once mirrored onto the d
+    // file, and the same with black to move after flipping the board
+    assert_eq!(probe("7k/8/8/3pP3/8/8/8/K7 w - - 0 1").0, Some(Wdl::BlessedLoss));
+    assert_eq!(probe("k7/8/8/8/3Pp3/8/8/7K b - - 0 1").0, Some(Wdl::BlessedLoss));
+    // Taking en passant reaches a KPvK loss for the other side, index
+    // 4 + 61 * 378, which the table for the position doesn't know about
+    assert_eq!(probe("7k/8/8/3pP3/8/8/8/K7 w - d6 0 1").0, Some(Wdl::Win));
+    assert_eq!(probe("k7/8/8/8/3Pp3/8/8/7K b - d3 0 1").0, Some(Wdl::Win));
+}
+
+#[test]
+fn missing_tables() {
+    let _lock = lock_tables();
+    let dir = TableDir::new();
+    write_kqk(&dir);
+    assert_eq!(init(dir.to_str()), 1);
+
+    // Tables that aren't there or can't hold the position
+    assert_eq!(probe("7R/8/8/8/4k3/8/8/K7 w - - 0 1"), (None, None));
+    assert_eq!(probe("7Q/8/8/8/8/3k4/8/K6R w - - 0 1"), (None, None));
+    assert_eq!(probe(START_FEN), (None, None));
+    assert_eq!(probe("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), (None, None));
+    // Capturing into a table that isn't there
+    assert_eq!(probe("8/8/8/8/8/8/3Qk3/K6N b - - 0 1"), (None, None));
+}
+
+/// Files that are cut short or don't match their name are left out rather
+/// than panicking when they are read
+#[test]
+fn damaged_tables() {
+    let _lock = lock_tables();
+    let dirs = [TableDir::new(), TableDir::new(), TableDir::new()];
+
+    // Values that don't repeat, so most of the file is blocks
+    let path = dirs[2].join("KQvK.rtbw");
+    write_table(&path, WDL_MAGIC, &KQK_PIECES, &[
+        (0, values(KQK, |idx| (idx.wrapping_mul(2654435761) >> 7) as u16 % 5)),
+        (0, values(KQK, |idx| (idx.wrapping_mul(40503) >> 5) as u16 % 5)),
+    ]);
+    let table = std::fs::read(&path).unwrap();
+
+    // Cut short in the header and in the blocks, with pieces that aren't
+    // the ones in the name, and only the magic number
+    std::fs::write(dirs[0].join("KQvK.rtbw"), &table[..40]).unwrap();
+    std::fs::write(dirs[1].join("KQvK.rtbw"), &table[..table.len() - 1]).unwrap();
+    std::fs::write(dirs[2].join("KBvK.rtbw"), &table).unwrap();
+    std::fs::write(dirs[2].join("KNvK.rtbw"), WDL_MAGIC).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let paths = dirs.iter().map(TableDir::to_str).collect::<Vec<_>>().join(":");
+    assert_eq!(init(&paths), 0);
+    assert_eq!(probe("8/7k/8/8/8/2Q5/8/1K6 w - - 0 1"), (None, None));
+
+    // Garbled blocks give wrong values, but reading them mustn't panic
+    let mut garbled = table.clone();
+    let blocks = garbled.len() - 1024;
+    garbled[blocks..].fill(0xFF);
+    std::fs::write(&path, &garbled).unwrap();
+
+    assert_eq!(init(dirs[2].to_str()), 1);
+    for fen in ["8/7k/8/8/8/2Q5/8/1K6 b - - 0 1", "8/8/7k/Q7/8/2K5/8/8 b - - 0 1", "k7/8/2K5/8/8/8/8/7Q b - - 0 1"] {
+        probe(fen);
+    }
+
+    assert_eq!(init(""), 0);
+}
+
+/// Probes the tables the Syzygy generator made, from the directories in
+/// `SYZYGY_PATH`, for positions whose values are known
+#[test]
+#[ignore = "needs the official KQvK, KRvK and KBNvK tables in SYZYGY_PATH"]
+fn official_tables() {
+    let _lock = lock_tables();
+    let paths = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH isn't set");
+    assert!(init(&paths) >= 3);
+
+    assert_eq!(probe("k7/8/1K6/8/8/8/8/7R w - - 0 1").0, Some(Wdl::Win));
+    assert_eq!(probe("k7/8/1K6/8/8/8/8/7R b - - 0 1").0, Some(Wdl::Loss));
+    // Black can take the rook or queen
+    assert_eq!(probe("8/8/8/8/8/8/6kR/K7 b - - 0 1"), (Some(Wdl::Draw), Some(0)));
+    assert_eq!(probe("8/8/8/8/8/8/6kQ/K7 b - - 0 1"), (Some(Wdl::Draw), Some(0)));
+    assert_eq!(probe("8/8/8/8/8/8/6kR/K7 w - - 0 1").0, Some(Wdl::Win));
+    // Stalemate
+    assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), (Some(Wdl::Draw), Some(0)));
+
+    // The example from the python-chess documentation
+    assert_eq!(probe("8/2K5/4B3/3N4/8/8/4k3/8 b - - 0 1"), (Some(Wdl::Loss), Some(-53)));
+
+    assert_eq!(init(""), 0);
+}
@@ -1,7 +1,17 @@
+use crate::bitboard::*;
+use crate::book::*;
+use crate::castling::*;
 use crate::coord::*;
+use crate::epd::*;
+use crate::eval::*;
 use crate::game::*;
+use crate::movegen::*;
 use crate::moves::*;
+use crate::packed::*;
+use crate::pgn::*;
 use crate::pieces::*;
+use crate::render::*;
+use crate::san::*;
 
 #[test]
 fn coord_test() {
@@ -482,4 +492,1001 @@ fn checkmate() {
 
     assert_eq!(game.get_valid_moves().len(), 0);
     assert_eq!(game.get_winner(), Some(Side::White));
+    assert_eq!(game.get_status(), GameStatus::Checkmate(Side::White));
+}
+
+#[test]
+fn fen_round_trip() {
+    let fens = [
+        START_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+        "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w Kq - 13 37",
+    ];
+
+    for fen in fens {
+        assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+    }
+
+    assert_eq!(Game::new().to_fen(), START_FEN);
+}
+
+#[test]
+fn fen_derived_state() {
+    let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 3 20").unwrap();
+
+    assert!(!game.get_white_king().has_moved());
+    assert!(game.get_piece_at(Coord::from_x_and_y(0, 0)).unwrap().has_moved());
+    assert!(!game.get_piece_at(Coord::from_x_and_y(7, 0)).unwrap().has_moved());
+    assert!(!game.get_piece_at(Coord::from_x_and_y(0, 7)).unwrap().has_moved());
+    assert!(game.get_piece_at(Coord::from_x_and_y(7, 7)).unwrap().has_moved());
+    assert_eq!(game.get_halfmove_clock(), 3);
+    assert_eq!(game.get_fullmove_number(), 20);
+
+    // The en passant square should allow the capture
+    let game = Game::from_fen("4k3/8/8/1pP5/8/8/8/4K3 w - b6 0 1").unwrap();
+    assert!(game.get_valid_moves().iter().any(|m| matches!(m, Move::EnPassant(_))));
+}
+
+#[test]
+fn fen_clocks_update() {
+    let mut game = Game::new();
+    let knight = game.get_piece_at(Coord::from_x_and_y(6, 0)).unwrap();
+
+    game.apply_move(Move::Standard(StandardMove::new(
+        knight,
+        Coord::from_x_and_y(6, 0),
+        Coord::from_x_and_y(5, 2)
+    ))).unwrap();
+
+    let pawn = game.get_piece_at(Coord::from_x_and_y(4, 6)).unwrap();
+    game.apply_move(Move::Standard(StandardMove::new(
+        pawn,
+        Coord::from_x_and_y(4, 6),
+        Coord::from_x_and_y(4, 4)
+    ))).unwrap();
+
+    assert_eq!(game.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/8/5N2/PPPPPPPP/RNBQKB1R w KQkq e6 0 2");
+}
+
+#[test]
+fn fen_errors() {
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1").unwrap_err(),
+        FenParseError::WrongRankCount(7)
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap_err(),
+        FenParseError::UnexpectedCharacter { 
+            field: FenField::PiecePlacement, 
+            index: 13, 
+            character: 'x' 
+        }
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap_err(),
+        FenParseError::BadRankLength(6)
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1").unwrap_err(),
+        FenParseError::UnexpectedCharacter { 
+            field: FenField::CastlingRights, 
+            index: 2, 
+            character: 'x' 
+        }
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap_err(),
+        FenParseError::InvalidCastlingRight('k')
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1").unwrap_err(),
+        FenParseError::UnexpectedCharacter { 
+            field: FenField::EnPassant, 
+            index: 1, 
+            character: '3' 
+        }
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1").unwrap_err(),
+        FenParseError::InvalidEnPassantSquare
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0").unwrap_err(),
+        FenParseError::WrongFieldCount(5)
+    );
+    assert_eq!(
+        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1").unwrap_err(),
+        FenParseError::UnexpectedCharacter { 
+            field: FenField::HalfmoveClock, 
+            index: 0, 
+            character: 'a' 
+        }
+    );
+    assert_eq!(
+        Game::from_fen("8/8/8/8/8/8/8/4K3 w - - 0 1").unwrap_err(),
+        FenParseError::NoBlackKing
+    );
+}
+
+#[test]
+fn stalemate() {
+    let mut game = Game::from_fen("7k/8/8/6Q1/8/8/8/K7 w - - 0 1").unwrap();
+    let queen = game.get_piece_at(Coord::from_x_and_y(6, 4)).unwrap();
+
+    game.apply_move(Move::Standard(StandardMove::new(
+        queen,
+        Coord::from_x_and_y(6, 4),
+        Coord::from_x_and_y(6, 5)
+    ))).unwrap();
+
+    assert_eq!(game.get_status(), GameStatus::Stalemate);
+    assert_eq!(game.get_winner(), None);
+}
+
+#[test]
+fn threefold_repetition() {
+    let mut game = Game::new();
+    let knight_moves = [[6, 0, 5, 2], [6, 7, 5, 5], [5, 2, 6, 0], [5, 5, 6, 7]];
+
+    for i in 0..8 {
+        let [from_x, from_y, to_x, to_y] = knight_moves[i % 4];
+        let from = Coord::from_x_and_y(from_x, from_y);
+
+        assert_eq!(game.get_status(), GameStatus::Ongoing);
+        game.apply_move(Move::Standard(StandardMove::new(
+            game.get_piece_at(from).unwrap(),
+            from,
+            Coord::from_x_and_y(to_x, to_y)
+        ))).unwrap();
+    }
+
+    assert_eq!(game.get_repetition_count(), 3);
+    assert_eq!(game.get_status(), GameStatus::ThreefoldRepetition);
+}
+
+#[test]
+fn move_rule_draws() {
+    let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+    let rook = game.get_piece_at(Coord::from_x_and_y(0, 0)).unwrap();
+    game.apply_move(Move::Standard(StandardMove::new(
+        rook,
+        Coord::from_x_and_y(0, 0),
+        Coord::from_x_and_y(0, 1)
+    ))).unwrap();
+
+    assert_eq!(game.get_status(), GameStatus::FiftyMoveRule);
+
+    let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 100").unwrap();
+    assert_eq!(game.get_status(), GameStatus::SeventyFiveMove);
+}
+
+#[test]
+fn material_draws() {
+    let statuses = [
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", GameStatus::InsufficientMaterial),
+        ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", GameStatus::InsufficientMaterial),
+        ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", GameStatus::DeadPosition),
+        ("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", GameStatus::Ongoing),
+        ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", GameStatus::Ongoing),
+        ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", GameStatus::Ongoing),
+    ];
+
+    for (fen, status) in statuses {
+        assert_eq!(Game::from_fen(fen).unwrap().get_status(), status, "{}", fen);
+    }
+}
+
+#[test]
+fn mating_material() {
+    let cases = [
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", false, false),
+        ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", false, false),
+        ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", true, false),
+        ("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1", false, false),
+        ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", true, false),
+        ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", true, false),
+        ("4k3/8/8/8/8/8/8/4K2R w - - 0 1", true, false),
+        ("4k3/4p3/8/8/8/8/8/4KN2 w - - 0 1", true, true),
+        ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", false, false),
+        ("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", true, true),
+        ("4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1", true, true),
+    ];
+
+    for (fen, white_can_mate, black_can_mate) in cases {
+        let game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.has_mating_material(Side::White), white_can_mate, "{}", fen);
+        assert_eq!(game.has_mating_material(Side::Black), black_can_mate, "{}", fen);
+    }
+}
+
+#[test]
+fn make_unmake_move() {
+    // Position with castling, en passant and promotions available
+    let fen = "r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 4 30";
+    let mut game = Game::from_fen(fen).unwrap();
+
+    let moves = game.get_valid_moves();
+    assert!(moves.iter().any(|m| matches!(m, Move::Castle(_))));
+    assert!(moves.iter().any(|m| matches!(m, Move::EnPassant(_))));
+    assert!(moves.iter().any(|m| matches!(m, Move::Promotion(_))));
+
+    for move_ in moves {
+        let undo = game.make_move(move_);
+
+        // Also try every reply to check nested undos
+        for reply in game.get_valid_moves() {
+            let reply_undo = game.make_move(reply);
+            game.unmake_move(reply_undo);
+        }
+
+        game.unmake_move(undo);
+        assert_eq!(game.to_fen(), fen, "{:?}", move_);
+        assert_eq!(game.get_move_history().len(), 0);
+    }
+}
+
+#[test]
+fn undo_move() {
+    let mut game = Game::from_fen("r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1").unwrap();
+    let pawn = game.get_piece_at(Coord::from_x_and_y(1, 6)).unwrap();
+    let rook = game.get_piece_at(Coord::from_x_and_y(0, 7)).unwrap();
+
+    let promotion = Move::Promotion(Promotion::new(
+        pawn,
+        Piece::new(Side::White, true, PieceType::Queen, Coord::from_x_and_y(0, 7)),
+        Coord::from_x_and_y(1, 6),
+        Coord::from_x_and_y(0, 7)
+    ));
+    game.apply_move(promotion).unwrap();
+
+    assert_eq!(game.get_move_history().len(), 1);
+    assert_eq!(game.get_move_history()[0].get_captured(), Some(rook));
+    assert_eq!(game.get_status(), GameStatus::Ongoing);
+
+    assert_eq!(game.undo_move(), Some(promotion));
+    assert_eq!(game.to_fen(), "r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1");
+    assert_eq!(game.undo_move(), None);
+}
+
+#[test]
+fn bitboard_attacks() {
+    let a1 = Coord::from_x_and_y(0, 0);
+    let d4 = Coord::from_x_and_y(3, 3);
+
+    assert_eq!(BitIter(knight_attacks(a1)).count(), 2);
+    assert_eq!(BitIter(king_attacks(d4)).count(), 8);
+    assert_eq!(BitIter(pawn_attacks(a1, Side::White)).collect::<Vec<Coord>>(), vec![Coord::from_x_and_y(1, 1)]);
+    assert_eq!(pawn_attacks(a1, Side::Black), EMPTY);
+
+    assert_eq!(BitIter(rook_attacks(d4, EMPTY)).count(), 14);
+    assert_eq!(BitIter(queen_attacks(d4, EMPTY)).count(), 27);
+
+    // Blockers are included in the attacks but nothing behind them
+    let blockers = coord_bb(Coord::from_x_and_y(3, 5)) | coord_bb(Coord::from_x_and_y(5, 5));
+    let rook = rook_attacks(d4, blockers);
+    assert!(contains(rook, Coord::from_x_and_y(3, 5)));
+    assert!(!contains(rook, Coord::from_x_and_y(3, 6)));
+
+    let bishop = bishop_attacks(d4, blockers);
+    assert!(contains(bishop, Coord::from_x_and_y(5, 5)));
+    assert!(!contains(bishop, Coord::from_x_and_y(6, 6)));
+    assert!(contains(bishop, a1));
+}
+
+#[test]
+fn eval_symmetry() {
+    assert_eq!(evaluate(&Game::new()), 0);
+
+    // The same position with the colours swapped scores the same for the
+    // side to move
+    let white = Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+    let black = Game::from_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4").unwrap();
+    assert_eq!(evaluate(&white), evaluate(&black));
+    assert_eq!(Evaluation::new(&white).white_score(), -Evaluation::new(&black).white_score());
+}
+
+#[test]
+fn eval_terms() {
+    let game = Game::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+    assert!(bishop_pair(&game).mg > 0);
+    assert_eq!(material(&game), PIECE_VALUES[PieceType::Bishop as usize] * 2);
+    assert_eq!(phase(&game), 2);
+
+    // Doubled and isolated pawns score worse than connected ones
+    let doubled = pawn_structure(&Game::from_fen("4k3/8/8/8/8/2P5/2P5/4K3 w - - 0 1").unwrap());
+    let connected = pawn_structure(&Game::from_fen("4k3/8/8/8/8/3P4/2P5/4K3 w - - 0 1").unwrap());
+    assert!(doubled.mg < connected.mg && doubled.eg < connected.eg);
+
+    // A passed pawn is a bonus until an enemy pawn can stop it
+    let passed = Game::from_fen("4k3/8/7P/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert!(pawn_structure(&passed).mg > 0 && pawn_structure(&passed).eg > 0);
+    let blocked = Game::from_fen("4k3/6p1/7P/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(pawn_structure(&blocked), Score::ZERO);
+
+    // The breakdown adds up to the score the search uses
+    let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1").unwrap();
+    let evaluation = Evaluation::new(&game);
+    let total = evaluation.material + evaluation.piece_squares + evaluation.mobility
+        + evaluation.pawn_structure + evaluation.king_safety + evaluation.bishop_pair;
+    assert_eq!(total, evaluation.total());
+    assert_eq!(evaluation.score(), evaluate(&game));
+    assert_eq!(evaluation.score(), -evaluation.white_score());
+}
+
+#[test]
+fn zobrist_hash() {
+    let play = |game: &mut Game, moves: &[[i8; 4]]| {
+        for [from_x, from_y, to_x, to_y] in moves {
+            let from = Coord::from_x_and_y(*from_x, *from_y);
+            let to = Coord::from_x_and_y(*to_x, *to_y);
+            game.apply_move(Move::Standard(StandardMove::new(game.get_piece_at(from).unwrap(), from, to))).unwrap();
+        }
+    };
+
+    // The same position reached by different move orders
+    let mut first = Game::new();
+    play(&mut first, &[[6, 0, 5, 2], [6, 7, 5, 5], [1, 0, 2, 2]]);
+    let mut second = Game::new();
+    play(&mut second, &[[1, 0, 2, 2], [6, 7, 5, 5], [6, 0, 5, 2]]);
+
+    assert_eq!(first.hash(), second.hash());
+    assert_eq!(first, second);
+    assert_eq!(first.hash(), Game::from_fen(&first.to_fen()).unwrap().hash());
+    assert_ne!(first.hash(), Game::new().hash());
+
+    let mut positions = std::collections::HashSet::new();
+    positions.insert(first.clone());
+    assert!(positions.contains(&second));
+
+    // Taking moves back restores the hash
+    first.undo_move();
+    assert_ne!(first.hash(), second.hash());
+    play(&mut first, &[[1, 0, 2, 2]]);
+    assert_eq!(first.hash(), second.hash());
+
+    // Side to move, castling rights and en passant are all part of the hash
+    let hash = |fen: &str| Game::from_fen(fen).unwrap().hash();
+    let fen = "r3k2r/8/8/8/4P3/8/8/R3K2R b KQkq - 0 1";
+    assert_ne!(hash(fen), hash("r3k2r/8/8/8/4P3/8/8/R3K2R w KQkq - 0 1"));
+    assert_ne!(hash(fen), hash("r3k2r/8/8/8/4P3/8/8/R3K2R b Kkq - 0 1"));
+    assert_ne!(hash(fen), hash("r3k2r/8/8/8/4P3/8/8/R3K2R b KQkq e3 0 1"));
+    assert_eq!(hash(fen), hash("r3k2r/8/8/8/4P3/8/8/R3K2R b KQkq - 15 40"));
+}
+
+#[test]
+fn san_formatting() {
+    let san = |fen: &str, from: [i8; 2], to: [i8; 2]| {
+        let game = Game::from_fen(fen).unwrap();
+        let from = Coord::from_x_and_y(from[0], from[1]);
+        let to = Coord::from_x_and_y(to[0], to[1]);
+
+        let moves = game.get_valid_moves();
+        let move_ = moves.iter()
+            .find(|m| m.get_from() == from && m.get_to() == to && 
+                      !matches!(m, Move::Promotion(p) if !p.new_piece.is_type(PieceType::Queen)))
+            .unwrap();
+        game.move_to_san(move_)
+    };
+
+    assert_eq!(san(START_FEN, [6, 0], [5, 2]), "Nf3");
+    assert_eq!(san(START_FEN, [4, 1], [4, 3]), "e4");
+
+    // Disambiguation by file, then rank, then both
+    assert_eq!(san("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", [0, 0], [3, 0]), "Rad1");
+    assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", [0, 0], [0, 2]), "R1a3");
+    assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", [0, 0], [1, 1]), "Qa1b2");
+
+    let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    assert_eq!(san(castling, [4, 0], [6, 0]), "O-O");
+    assert_eq!(san(castling, [4, 0], [2, 0]), "O-O-O");
+
+    assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", [4, 4], [3, 5]), "exd6");
+    assert_eq!(san("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", [1, 6], [1, 7]), "b8=Q+");
+    assert_eq!(san("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", [1, 6], [0, 7]), "bxa8=Q+");
+    assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", [0, 0], [0, 7]), "Ra8#");
+}
+
+#[test]
+fn san_parsing() {
+    // Every legal move can be written and read back
+    let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    for move_ in game.get_valid_moves() {
+        assert_eq!(game.parse_san(&game.move_to_san(&move_)), Ok(move_));
+    }
+
+    let game = Game::from_fen("r3k3/1P6/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+    let rook = game.parse_san("Rad1").unwrap();
+    assert_eq!(rook.get_from(), Coord::from_x_and_y(0, 0));
+    assert_eq!(game.parse_san("Rad1+!?"), Ok(rook));
+
+    let promotion = game.parse_san("bxa8=N").unwrap();
+    assert!(matches!(promotion, Move::Promotion(p) if p.new_piece.is_type(PieceType::Knight)));
+    assert_eq!(game.parse_san("bxa8N"), Ok(promotion));
+
+    let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+    assert!(matches!(game.parse_san("O-O-O"), Ok(Move::Castle(c)) if c.king_to == Coord::from_x_and_y(2, 7)));
+    assert!(matches!(game.parse_san("0-0"), Ok(Move::Castle(c)) if c.king_to == Coord::from_x_and_y(6, 7)));
+
+    let game = Game::from_fen("r3k3/1P6/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+    assert_eq!(game.parse_san(" "), Err(SanError::Empty));
+    assert_eq!(game.parse_san("Rd1"), Err(SanError::AmbiguousMove));
+    assert_eq!(game.parse_san("Nf3"), Err(SanError::IllegalMove));
+    assert_eq!(game.parse_san("b8"), Err(SanError::IllegalMove));
+    assert_eq!(game.parse_san("b8=K"), Err(SanError::InvalidSyntax));
+    assert_eq!(game.parse_san("Rz1"), Err(SanError::InvalidSyntax));
+}
+
+#[test]
+fn coord_algebraic() {
+    let e4 = Coord::from_x_and_y(4, 3);
+    assert_eq!(e4.to_string(), "e4");
+    assert_eq!("e4".parse(), Ok(e4));
+    assert_eq!(Coord::from_index(0).to_string(), "a8");
+    assert_eq!("h1".parse(), Ok(Coord::from_index(63)));
+
+    for s in ["", "e", "e9", "i4", "E4", "e44"] {
+        assert_eq!(s.parse::<Coord>(), Err(CoordParseError), "{}", s);
+    }
+}
+
+#[test]
+fn uci_moves() {
+    let game = Game::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+
+    // Every legal move can be written and read back
+    for move_ in game.get_valid_moves() {
+        assert_eq!(game.parse_uci_move(&move_.to_uci()), Ok(move_));
+    }
+
+    assert!(matches!(game.parse_uci_move("e1g1"), Ok(Move::Castle(_))));
+    assert!(matches!(game.parse_uci_move("e5d6"), Ok(Move::EnPassant(_))));
+    assert!(matches!(game.parse_uci_move("e5e6"), Ok(Move::Standard(_))));
+
+    let promotion = game.parse_uci_move("b7a8n").unwrap();
+    assert!(matches!(promotion, Move::Promotion(p) if p.new_piece.is_type(PieceType::Knight)));
+    assert_eq!(promotion.to_uci(), "b7a8n");
+
+    assert_eq!(game.parse_uci_move("b7a8"), Err(UciMoveError::IllegalMove));
+    assert_eq!(game.parse_uci_move("e1e3"), Err(UciMoveError::IllegalMove));
+    assert_eq!(game.parse_uci_move("e1"), Err(UciMoveError::InvalidSyntax));
+    assert_eq!(game.parse_uci_move("b7a8k"), Err(UciMoveError::InvalidSyntax));
+    assert_eq!(game.parse_uci_move("z1e2"), Err(UciMoveError::InvalidSyntax));
+}
+
+#[test]
+fn pgn_round_trip() {
+    let mut game = Game::new();
+    for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+        game.apply_move(game.parse_san(san).unwrap()).unwrap();
+    }
+
+    let pgn = PgnGame::from_game(&game);
+    assert_eq!(pgn.result, PgnResult::WhiteWins);
+    assert_eq!(pgn.get_tag("Result"), Some("1-0"));
+    assert_eq!(pgn.get_tag("FEN"), None);
+
+    let text = pgn.to_string();
+    assert!(text.starts_with("[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n"));
+    assert!(text.ends_with("\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n"));
+
+    let games = parse_pgn(&text).unwrap();
+    assert_eq!(games.len(), 1);
+    assert_eq!(games[0].tags, pgn.tags);
+    assert_eq!(games[0].moves, pgn.moves);
+    assert_eq!(games[0].to_game().get_status(), GameStatus::Checkmate(Side::White));
+
+    // Games that don't start from the normal position keep their FEN
+    let mut game = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+    game.apply_move(game.parse_san("Kd7").unwrap()).unwrap();
+    let pgn = PgnGame::from_game(&game);
+    assert_eq!(pgn.get_tag("SetUp"), Some("1"));
+    assert!(pgn.to_string().ends_with("\n40... Kd7 *\n"));
+    assert_eq!(parse_pgn(&pgn.to_string()).unwrap()[0].to_game(), game);
+}
+
+#[test]
+fn pgn_reading() {
+    let text = r#"
+[Event "Test \"quoted]\""]
+[White "A"]
+[Black "B"]
+[Result "1/2-1/2"]
+[Annotator "C"]
+
+{Opening comment} 1. e4 e5 2.Nf3 $1 Nc6!? ; rest of line comment
+{is fine} (2... d6 3. d4 (3. Bc4 Be7) exd4) (2... Nf6) 3. Bb5 a6 *
+
+% escaped line
+[Event "Second [round 2]"]
+
+1. d4 d5 1-0
+"#;
+
+    let games = parse_pgn(text).unwrap();
+    assert_eq!(games.len(), 2);
+
+    let game = &games[0];
+    assert_eq!(game.get_tag("Event"), Some("Test \"quoted]\""));
+    assert_eq!(game.get_tag("Annotator"), Some("C"));
+    assert_eq!(game.result, PgnResult::Unknown);
+    assert_eq!(game.moves.len(), 6);
+
+    assert_eq!(game.moves[0].comments_before, vec!["Opening comment"]);
+    assert_eq!(game.moves[2].nags, vec![1]);
+    assert_eq!(game.moves[3].nags, vec![5]);
+    assert_eq!(game.moves[3].comments, vec!["rest of line comment", "is fine"]);
+
+    // Variations replace the move they follow and can be nested
+    let variations = &game.moves[3].variations;
+    assert_eq!(variations.len(), 2);
+    assert_eq!(variations[0].iter().map(|m| m.san.as_str()).collect::<Vec<_>>(), ["d6", "d4", "exd4"]);
+    assert_eq!(variations[0][1].variations[0][0].san, "Bc4");
+    assert_eq!(variations[1][0].san, "Nf6");
+
+    assert_eq!(games[1].get_tag("Event"), Some("Second [round 2]"));
+    assert_eq!(games[1].result, PgnResult::WhiteWins);
+    assert_eq!(games[1].moves.len(), 2);
+
+    // Variations and comments survive being written and read again
+    let written = game.to_string();
+    assert!(written.replace('\n', " ").contains("2. Nf3 $1 Nc6 $5 {rest of line comment} {is fine} (2... d6 3. d4 (3. Bc4 Be7) 3... exd4) (2... Nf6) 3. Bb5"));
+    let reread = parse_pgn(&written).unwrap();
+    assert_eq!(reread[0].moves, game.moves);
+}
+
+#[test]
+fn pgn_errors() {
+    assert_eq!(parse_pgn("[Event \"?\"\n1. e4").unwrap_err(), PgnError::InvalidTag(1));
+    assert_eq!(parse_pgn("1. e4 {never closed").unwrap_err(), PgnError::UnterminatedComment(1));
+    assert_eq!(parse_pgn("1. e4 (1. d4").unwrap_err(), PgnError::UnbalancedVariation(1));
+    assert_eq!(parse_pgn("1. e4 e5)").unwrap_err(), PgnError::UnbalancedVariation(1));
+    assert_eq!(parse_pgn("(1. d4) 1. e4").unwrap_err(), PgnError::VariationWithoutMove(1));
+    assert_eq!(parse_pgn("$1 1. e4").unwrap_err(), PgnError::NagWithoutMove(1));
+    assert_eq!(parse_pgn("1. e4 (! 1. d4)").unwrap_err(), PgnError::NagWithoutMove(1));
+    assert_eq!(parse_pgn("[Event \"Open [A]\"\n1. e4").unwrap_err(), PgnError::InvalidTag(1));
+    assert_eq!(parse_pgn("1. e4 &").unwrap_err(), PgnError::UnexpectedCharacter { line: 1, character: '&' });
+    assert!(matches!(parse_pgn("[FEN \"bad\"]\n1. e4").unwrap_err(), PgnError::InvalidFen(_)));
+    assert_eq!(
+        parse_pgn("1. e4 e5\n2. Ke3").unwrap_err(),
+        PgnError::IllegalMove { line: 2, san: "Ke3".to_string(), error: SanError::IllegalMove }
+    );
+}
+
+#[test]
+fn epd_parsing() {
+    let text = "\
+2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";
+
+r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - am Nxe5 Bb5; c0 \"Nxe5=0, Bb5=10\"; hmvc 2; fmvn 3
+";
+
+    let records = parse_epd(text).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let wac = &records[0];
+    assert_eq!(wac.get_id(), Some("WAC.001"));
+    assert_eq!(wac.best_moves, vec![wac.game.parse_san("Qg6").unwrap()]);
+    assert!(wac.avoid_moves.is_empty());
+    assert_eq!(wac.game.to_fen(), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+
+    // Several operands, a quoted comment and clock operations without a
+    // final semicolon
+    let record = &records[1];
+    assert_eq!(record.avoid_moves.len(), 2);
+    assert_eq!(record.get_comment(0), Some("Nxe5=0, Bb5=10"));
+    assert_eq!(record.get_comment(1), None);
+    assert_eq!(record.get_operands("hmvc"), Some(&["2".to_string()][..]));
+    assert_eq!(record.game.get_halfmove_clock(), 2);
+    assert_eq!(record.game.get_fullmove_number(), 3);
+
+    assert_eq!(parse_epd("\n8/8/8/8 w").unwrap_err(), EpdError::MissingFields(2));
+    assert_eq!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - id \"open").unwrap_err(), EpdError::UnterminatedString(1));
+    assert!(matches!(parse_epd("4k3/8/8 w - - bm Kd2;").unwrap_err(), EpdError::InvalidFen { line: 1, .. }));
+    assert_eq!(
+        parse_epd("4k3/8/8/8/8/8/8/4K3 w - - hmvc x;").unwrap_err(),
+        EpdError::InvalidOperand { line: 1, opcode: "hmvc".to_string(), operand: "x".to_string() }
+    );
+    assert_eq!(
+        parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Ke3;").unwrap_err(),
+        EpdError::IllegalMove { line: 1, san: "Ke3".to_string(), error: SanError::IllegalMove }
+    );
+}
+
+#[test]
+fn board_rendering() {
+    let mut game = Game::new();
+    assert_eq!(game.to_string(), "\
+8 r n b q k b n r
+7 p p p p p p p p
+6 . . . . . . . .
+5 . . . . . . . .
+4 . . . . . . . .
+3 . . . . . . . .
+2 P P P P P P P P
+1 R N B Q K B N R
+  a b c d e f g h
+White to move");
+
+    for san in ["f3", "e5", "g4", "Qh4+"] {
+        game.make_move(game.parse_san(san).unwrap());
+    }
+
+    let flipped = RenderOptions { labels: false, unicode: true, flipped: true, ..RenderOptions::default() };
+    let text = render(&game, &flipped);
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖"));
+    assert_eq!(lines.next(), Some("♙ . . ♙ ♙ ♙ ♙ ♙"));
+    assert_eq!(lines.next(), Some(". . ♙ . . . . ."));
+    assert_eq!(lines.next(), Some("♛ ♙ . . . . . ."));
+    assert_eq!(text.lines().last(), Some("White to move, in check"));
+
+    // The squares of the last move and the king in check are highlighted
+    let coloured = RenderOptions { colour: true, ..RenderOptions::default() };
+    let text = render(&game, &coloured);
+    let rank_four = text.lines().nth(4).unwrap();
+    assert!(rank_four.starts_with("4 "));
+    assert_eq!(rank_four.matches("\x1b[48;5;143m").count() + rank_four.matches("\x1b[48;5;186m").count(), 1);
+    let rank_one = text.lines().nth(7).unwrap();
+    assert_eq!(rank_one.matches("\x1b[48;5;167m").count(), 1);
+    assert_eq!(text.lines().nth(8), Some("   a  b  c  d  e  f  g  h"));
+
+    let no_check = RenderOptions { highlight_check: false, ..coloured };
+    assert!(!render(&game, &no_check).contains("\x1b[48;5;167m"));
+}
+
+#[test]
+fn chess960_start_positions() {
+    assert_eq!(Game::new_chess960(518).unwrap().to_fen(), START_FEN);
+    assert_eq!(
+        Game::new_chess960(0).unwrap().to_fen(),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+    );
+    assert!(Game::new_chess960(960).is_none());
+
+    let mut back_ranks = std::collections::HashSet::new();
+    for number in 0..960 {
+        let game = Game::new_chess960(number).unwrap();
+        assert!(game.is_chess960());
+
+        let back_rank: String = game.to_fen().split('/').next_back().unwrap().chars().take(8).collect();
+        let file = |c: char| back_rank.find(c).unwrap();
+
+        // Bishops on opposite colours and the king between the rooks
+        assert_ne!(file('B') % 2, back_rank.rfind('B').unwrap() % 2, "{}", back_rank);
+        assert!(file('R') < file('K') && file('K') < back_rank.rfind('R').unwrap(), "{}", back_rank);
+        back_ranks.insert(back_rank);
+    }
+    assert_eq!(back_ranks.len(), 960);
+}
+
+#[test]
+fn chess960_castling() {
+    // Shredder-FEN rights are written back as X-FEN
+    let game = Game::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+    assert!(game.is_chess960());
+    assert_eq!(game.to_fen(), "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1");
+
+    // A rook that isn't the outermost one is written by its file
+    let game = Game::from_fen("rr2k3/8/8/8/8/8/8/RR2K3 w Bb - 0 1").unwrap();
+    assert_eq!(game.to_fen(), "rr2k3/8/8/8/8/8/8/RR2K3 w Bb - 0 1");
+    assert_eq!(Game::from_fen("rr2k3/8/8/8/8/8/8/RR2K3 w Q - 0 1").unwrap().to_fen(), "rr2k3/8/8/8/8/8/8/RR2K3 w Q - 0 1");
+    assert_eq!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 w C - 0 1").unwrap_err(), FenParseError::InvalidCastlingRight('C'));
+
+    // Standard positions are not Chess960 unless asked for
+    let mut game = Game::new();
+    assert!(!game.is_chess960());
+    assert_eq!(Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap().to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+    // Castling is written as the king taking its rook in Chess960 only
+    let castling = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let castle = castling.parse_uci_move("e1g1").unwrap();
+    assert_eq!(castling.move_to_uci(&castle), "e1g1");
+    assert_eq!(castling.parse_uci_move("e1h1"), Ok(castle));
+    game = castling.clone();
+    game.set_chess960(true);
+    assert_eq!(game.move_to_uci(&castle), "e1h1");
+    assert_eq!(game.parse_uci_move("e1g1"), Err(UciMoveError::IllegalMove));
+
+    // The king doesn't move when castling from g1 with the rook on h1, and
+    // the b1 rook can castle even though the king is already next to it
+    let mut game = Game::from_fen("1r4kr/8/8/8/8/8/8/1R4KR w KQkq - 0 1").unwrap();
+    let short = game.parse_uci_move("g1h1").unwrap();
+    assert!(matches!(short, Move::Castle(c) if c.king_to == c.king_from));
+    assert_eq!(game.move_to_san(&short), "O-O");
+    let long = game.parse_uci_move("g1b1").unwrap();
+    assert_eq!(game.move_to_san(&long), "O-O-O");
+
+    game.apply_move(long).unwrap();
+    assert_eq!(game.to_fen(), "1r4kr/8/8/8/8/8/8/2KR3R b kq - 1 1");
+    game.undo_move();
+    assert_eq!(game.to_fen(), "1r4kr/8/8/8/8/8/8/1R4KR w KQkq - 0 1");
+
+    // Castling is not allowed if moving the rook leaves the king in check
+    let game = Game::from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+    assert!(!game.get_valid_moves().iter().any(|m| matches!(m, Move::Castle(_))));
+}
+
+#[test]
+fn castling_rights_state() {
+    let start_code = "r...k..r\
+                      ........\
+                      ........\
+                      ........\
+                      ........\
+                      ........\
+                      ........\
+                      R...K..R";
+    let mut game = Game::from_string(start_code, Side::White).unwrap();
+    assert_eq!(game.get_castling_rights(), CastlingRights::STANDARD);
+
+    // White may castle only kingside
+    let mut castling_rights = CastlingRights::NONE;
+    castling_rights.set(Side::White, CastlingSide::KingSide, 7);
+    game.set_castling_rights(castling_rights).unwrap();
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w K - 0 1");
+    assert_eq!(game.hash(), Game::from_fen(&game.to_fen()).unwrap().hash());
+
+    let castles: Vec<String> = game.get_valid_moves().iter()
+        .filter(|m| matches!(m, Move::Castle(_)))
+        .map(|m| game.move_to_san(m))
+        .collect();
+    assert_eq!(castles, ["O-O"]);
+
+    // Rights need the rook to be there
+    castling_rights.set(Side::Black, CastlingSide::QueenSide, 1);
+    assert_eq!(game.set_castling_rights(castling_rights), Err(GameErrors::InvalidCastlingRights));
+    assert_eq!(game.get_castling_rights().get_rook_file(Side::White, CastlingSide::KingSide), Some(7));
+
+    // Moving a rook or taking one loses its right, moving the king loses both
+    let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    game.apply_move(game.parse_uci_move("a1a8").unwrap()).unwrap();
+    assert_eq!(game.to_fen(), "R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1");
+    game.apply_move(game.parse_uci_move("e8e7").unwrap()).unwrap();
+    assert!(!game.get_castling_rights().has_any(Side::Black));
+    assert_eq!(game.to_fen(), "R6r/4k3/8/8/8/8/8/4K2R w K - 1 2");
+
+    game.undo_move();
+    game.undo_move();
+    assert_eq!(game.get_castling_rights(), CastlingRights::STANDARD);
+}
+
+#[test]
+fn en_passant_state() {
+    let start_code = "....k...\
+                      ........\
+                      ........\
+                      ...pP...\
+                      ........\
+                      ........\
+                      ........\
+                      ....K...";
+    let mut game = Game::from_string(start_code, Side::White).unwrap();
+    assert_eq!(game.get_en_passant_square(), None);
+    assert!(!game.get_valid_moves().iter().any(|m| matches!(m, Move::EnPassant(_))));
+
+    // En passant on d6 is available
+    let d6 = "d6".parse::<Coord>().unwrap();
+    game.set_en_passant_square(Some(d6)).unwrap();
+    assert_eq!(game.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+    assert_eq!(game.hash(), Game::from_fen(&game.to_fen()).unwrap().hash());
+    assert_eq!(game.move_to_san(&game.parse_uci_move("e5d6").unwrap()), "exd6");
+
+    assert_eq!(game.set_en_passant_square(Some("e6".parse::<Coord>().unwrap())), Err(GameErrors::InvalidEnPassantSquare));
+    assert_eq!(game.get_en_passant_square(), Some(d6));
+
+    // A double move sets the square even when no pawn can take, and any
+    // other move clears it
+    let mut game = Game::new();
+    game.apply_move(game.parse_uci_move("e2e4").unwrap()).unwrap();
+    assert_eq!(game.get_en_passant_square(), Some("e3".parse::<Coord>().unwrap()));
+    game.apply_move(game.parse_uci_move("g8f6").unwrap()).unwrap();
+    assert_eq!(game.get_en_passant_square(), None);
+    game.undo_move();
+    assert_eq!(game.get_en_passant_square(), Some("e3".parse::<Coord>().unwrap()));
+}
+
+#[test]
+fn move_list() {
+    let game = Game::new();
+    let mut moves = MoveList::new();
+    assert!(moves.is_empty());
+
+    game.generate_legal_moves(GenMode::All, &mut moves);
+    assert_eq!(moves.len(), 20);
+    assert_eq!(moves.to_vec(), game.get_valid_moves());
+
+    // Moves are added to the end of the list
+    game.generate_legal_moves(GenMode::All, &mut moves);
+    assert_eq!(moves.len(), 40);
+
+    moves.retain(|m| m.get_piece().is_type(PieceType::Knight));
+    assert_eq!(moves.len(), 8);
+    assert_eq!(moves.into_iter().filter(|m| m.get_to().to_string() == "f3").count(), 2);
+}
+
+#[test]
+fn move_generation_modes() {
+    let fens = [
+        START_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "4k3/8/8/3pP3/4K3/8/8/8 w - d6 0 1",
+        "k3r3/8/8/1B6/8/3n4/8/4K2R w K - 0 1",
+    ];
+
+    for fen in fens {
+        let game = Game::from_fen(fen).unwrap();
+        let legal = game.get_valid_moves();
+
+        let mut captures = MoveList::new();
+        game.generate_legal_moves(GenMode::Captures, &mut captures);
+        let mut quiets = MoveList::new();
+        game.generate_legal_moves(GenMode::Quiets, &mut quiets);
+
+        // Captures and quiets split the legal moves between them
+        assert_eq!(captures.len() + quiets.len(), legal.len(), "{}", fen);
+        assert!(captures.iter().chain(quiets.iter()).all(|m| legal.contains(m)), "{}", fen);
+        assert!(captures.iter().all(|m| matches!(m, Move::EnPassant(_)) || game.get_piece_at(m.get_to()).is_some()), "{}", fen);
+
+        // The legal moves are the pseudo-legal moves that pass the filter
+        let mut pseudo_legal = MoveList::new();
+        game.generate_pseudo_legal_moves(GenMode::All, &mut pseudo_legal);
+        let check_info = game.get_check_info();
+        pseudo_legal.retain(|m| game.is_legal_move(m, &check_info));
+        assert_eq!(pseudo_legal.len(), legal.len(), "{}", fen);
+
+        // Evasions only leave out moves that can't get out of check
+        let mut evasions = MoveList::new();
+        game.generate_legal_moves(GenMode::Evasions, &mut evasions);
+        assert_eq!(evasions.len(), legal.len(), "{}", fen);
+    }
+
+    // Only king moves are generated in double check, even pseudo-legally
+    let game = Game::from_fen("k3r3/8/8/1B6/8/3n4/8/4K2R w K - 0 1").unwrap();
+    let mut evasions = MoveList::new();
+    game.generate_pseudo_legal_moves(GenMode::Evasions, &mut evasions);
+    assert!(evasions.iter().all(|m| m.get_piece().is_type(PieceType::King)));
+}
+
+#[test]
+fn check_info() {
+    let game = Game::from_fen("4k3/8/8/4P3/8/4n3/8/3K4 w - - 0 1").unwrap();
+    let check_info = game.get_check_info();
+    assert!(check_info.is_check());
+    assert!(!check_info.is_double_check());
+    assert_eq!(check_info.get_checkers(), ["e3".parse::<Coord>().unwrap()]);
+    assert!(!check_info.is_pinned("e5".parse().unwrap()));
+
+    let game = Game::from_fen("4k3/8/1b6/8/8/4N3/5K2/8 w - - 0 1").unwrap();
+    let check_info = game.get_check_info();
+    assert!(!check_info.is_check());
+    let knight = "e3".parse::<Coord>().unwrap();
+    assert!(check_info.is_pinned(knight));
+    assert_eq!(check_info.get_pin_ray(knight).unwrap().len(), 4);
+
+    // A pinned knight can never move
+    let mut moves = MoveList::new();
+    game.generate_pseudo_legal_moves(GenMode::All, &mut moves);
+    assert!(moves.iter().any(|m| m.get_from() == knight));
+    assert!(moves.iter().filter(|m| m.get_from() == knight).all(|m| !game.is_legal_move(m, &check_info)));
+}
+
+#[test]
+fn packed_moves() {
+    let fens = [
+        START_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        "1r4kr/8/8/8/8/8/8/1R4KR w KQkq - 0 1",
+    ];
+
+    // Every legal move packs and unpacks to the same move
+    for fen in fens {
+        let game = Game::from_fen(fen).unwrap();
+
+        for move_ in game.get_valid_moves() {
+            let packed = PackedMove::from(move_);
+            assert_eq!(PackedMove::from_u16(packed.to_u16()), Some(packed));
+            assert_eq!(game.unpack_move(packed), Some(move_), "{} in {}", packed, fen);
+        }
+    }
+
+    let game = Game::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1").unwrap();
+
+    let promotion = PackedMove::from(game.parse_san("bxa1=N").unwrap());
+    assert_eq!(promotion.get_promotion(), Some(PieceType::Knight));
+    assert_eq!(promotion.to_string(), "b2a1n");
+    // Flags above the last promotion aren't moves
+    assert_eq!(PackedMove::from_u16(promotion.to_u16() | 0xF000), None);
+    assert_eq!(PackedMove::from_u16(0x8000 | promotion.to_u16() & 0x0FFF), None);
+
+    let castle = PackedMove::from(game.parse_san("O-O-O").unwrap());
+    assert!(castle.is_castle());
+    assert_eq!(castle.to_string(), "e8a8");
+
+    let double_push = PackedMove::from(game.parse_san("c5").unwrap());
+    assert!(double_push.is_double_push());
+    assert!(!double_push.is_en_passant());
+    assert_eq!(double_push.get_promotion(), None);
+
+    // The pieces need to be on the board
+    assert_eq!(game.unpack_move(PackedMove::NONE), None);
+    assert_eq!(game.unpack_move(double_push), game.parse_san("c5").ok());
+    assert_eq!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap().unpack_move(castle), None);
+}
+
+#[test]
+fn polyglot_keys() {
+    // Keys from the Polyglot book format specification
+    let lines: [(&[&str], u64); 9] = [
+        (&[], 0x463B96181691FC9C),
+        (&["e4"], 0x823C9B50FD114196),
+        (&["e4", "d5"], 0x0756B94461C50FB0),
+        (&["e4", "d5", "e5"], 0x662FAFB965DB29D4),
+        (&["e4", "d5", "e5", "f5"], 0x22A48B5A8E47FF78),
+        (&["e4", "d5", "e5", "f5", "Ke2"], 0x652A607CA3F242C1),
+        (&["e4", "d5", "e5", "f5", "Ke2", "Kf7"], 0x00FDD303C946BDD9),
+        (&["a4", "b5", "h4", "b4", "c4"], 0x3C8123EA7B067637),
+        (&["a4", "b5", "h4", "b4", "c4", "bxc3", "Ra3"], 0x5C3F9B829B279560),
+    ];
+
+    for (moves, key) in lines {
+        let mut game = Game::new();
+        for san in moves {
+            game.apply_move(game.parse_san(san).unwrap()).unwrap();
+        }
+
+        assert_eq!(polyglot_key(&game), key, "{:?}", moves);
+    }
+}
+
+#[test]
+fn polyglot_book() {
+    let entry = |key: u64, move_: u16, weight: u16| {
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend(move_.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    };
+
+    // Moves are packed as to file, to rank, from file, from rank, each in
+    // three bits
+    let e2e4 = 4 | 3 << 3 | 4 << 6 | 1 << 9;
+    let d2d4 = 3 | 3 << 3 | 3 << 6 | 1 << 9;
+    let e1h1 = 7 | 4 << 6;
+    let illegal = 4 | 4 << 3 | 4 << 6 | 1 << 9;
+
+    let castling = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+
+    // Out of order, which the book sorts out
+    let bytes: Vec<u8> = [
+        entry(polyglot_key(&castling), e1h1, 1),
+        entry(0x463B96181691FC9C, e2e4, 3),
+        entry(0x463B96181691FC9C, illegal, 10),
+        entry(0x463B96181691FC9C, d2d4, 1),
+    ].concat();
+
+    let book = Book::from_bytes(&bytes).unwrap();
+    assert_eq!(book.len(), 4);
+
+    let game = Game::new();
+    let moves = book.get_moves(&game);
+    assert_eq!(moves.len(), 2);
+    assert_eq!(moves[0], BookMove { move_: game.parse_san("e4").unwrap(), weight: 3 });
+    assert_eq!(moves[1], BookMove { move_: game.parse_san("d4").unwrap(), weight: 1 });
+
+    // Picks are spread by weight
+    let picks: Vec<Move> = (0..4).map(|r| book.choose_move(&game, r).unwrap()).collect();
+    assert_eq!(picks.iter().filter(|&&m| m == moves[0].move_).count(), 3);
+
+    // Castling is written as the king taking its rook
+    assert_eq!(book.choose_move(&castling, 0), castling.parse_san("O-O").ok());
+    assert_eq!(book.choose_move(&Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap(), 0), None);
+
+    assert_eq!(Book::from_bytes(&bytes[1..]).unwrap_err(), BookError::InvalidLength(63));
 }
@@ -0,0 +1,76 @@
+/// Random keys used to hash positions. Each part of the position has its
+/// own key and the hash of a position is all of its keys xored together, so
+/// the hash can be updated as moves are made rather than worked out again.
+pub struct Keys {
+    /// Indexed by `Side`, then `PieceType`, then square index
+    pub pieces: [[[u64; 64]; 6]; 2],
+    /// Used when it is black to move
+    pub black_to_move: u64,
+    /// Used for each rook that can still castle, indexed by `Side` then
+    /// the rook's file
+    pub castling: [[u64; 8]; 2],
+    /// Indexed by the file of the en passant square
+    pub en_passant: [u64; 8],
+}
+
+/// Step of the SplitMix64 generator, returning the new state and output
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    (state, z ^ (z >> 31))
+}
+
+pub const KEYS: Keys = {
+    let mut keys = Keys {
+        pieces: [[[0; 64]; 6]; 2],
+        black_to_move: 0,
+        castling: [[0; 8]; 2],
+        en_passant: [0; 8],
+    };
+
+    // Fixed seed so hashes are the same every run
+    let mut state = 0x2545_F491_4F6C_DD1D;
+    let mut key;
+
+    let mut side = 0;
+    while side < 2 {
+        let mut piece_type = 0;
+        while piece_type < 6 {
+            let mut i = 0;
+            while i < 64 {
+                (state, key) = split_mix(state);
+                keys.pieces[side][piece_type][i] = key;
+                i += 1;
+            }
+            piece_type += 1;
+        }
+        side += 1;
+    }
+
+    (state, key) = split_mix(state);
+    keys.black_to_move = key;
+
+    let mut side = 0;
+    while side < 2 {
+        let mut i = 0;
+        while i < 8 {
+            (state, key) = split_mix(state);
+            keys.castling[side][i] = key;
+            i += 1;
+        }
+        side += 1;
+    }
+
+    let mut i = 0;
+    while i < 8 {
+        (state, key) = split_mix(state);
+        keys.en_passant[i] = key;
+        i += 1;
+    }
+
+    keys
+};
@@ -0,0 +1,302 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chess::Side;
+
+use crate::timeman::TimeLeft;
+
+/// Time given back to a player each move
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bonus {
+    None,
+    /// Added to the clock after every move
+    Increment(Duration),
+    /// Time used on the move is given back after it, up to this much
+    Bronstein(Duration),
+    /// The clock only starts running after this much time each move
+    Delay(Duration),
+}
+
+/// How much time each player gets, such as `5+3` for 5 minutes with 3
+/// seconds added every move
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    /// Time each player starts with, and gets again every period
+    pub base: Duration,
+    /// Moves in each period, or `None` for sudden death
+    pub moves: Option<u32>,
+    pub bonus: Bonus,
+}
+
+/// Parses `[moves/]minutes[(+|b|d)seconds]`, e.g. `5+3` for a 3 second
+/// increment, `40/90` for 40 moves in 90 minutes, `5b2` for a 2 second
+/// Bronstein delay and `5d2` for a 2 second simple delay
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time control {}, expected e.g. 5+3, 40/90 or 5d2", s);
+
+        let (moves, rest) = match s.split_once('/') {
+            Some((moves, rest)) => {
+                let moves = moves.parse::<u32>().ok().filter(|&m| m > 0).ok_or_else(invalid)?;
+                (Some(moves), rest)
+            },
+            None => (None, s)
+        };
+
+        let (base, bonus) = match rest.find(['+', 'b', 'd']) {
+            Some(i) => (&rest[..i], Some((&rest[i..i + 1], &rest[i + 1..]))),
+            None => (rest, None)
+        };
+
+        let minutes = base.parse::<f64>().ok().filter(|m| m.is_finite() && *m > 0.0).ok_or_else(invalid)?;
+
+        let bonus = match bonus {
+            Some((kind, seconds)) => {
+                let seconds = seconds.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0).ok_or_else(invalid)?;
+                let seconds = Duration::from_secs_f64(seconds);
+
+                match kind {
+                    "+" => Bonus::Increment(seconds),
+                    "b" => Bonus::Bronstein(seconds),
+                    _ => Bonus::Delay(seconds)
+                }
+            },
+            None => Bonus::None
+        };
+
+        Ok(Self { base: Duration::from_secs_f64(minutes * 60.0), moves, bonus })
+    }
+}
+
+/// A chess clock keeping the time left for both players
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    /// Indexed by side
+    remaining: [Duration; 2],
+    moves_made: [u32; 2],
+    /// The side whose clock is running and when it started
+    running: Option<(Side, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            remaining: [control.base; 2],
+            moves_made: [0; 2],
+            running: None,
+        }
+    }
+
+    /// Starts the clock of the side about to move
+    pub fn start(&mut self, side: Side) {
+        self.running = Some((side, Instant::now()));
+    }
+
+    /// Stops the running clock once its side has moved, adding any bonus.
+    /// Returns `false` if the side ran out of time before moving.
+    pub fn stop(&mut self) -> bool {
+        let Some((side, started)) = self.running.take() else { return true };
+
+        let used = started.elapsed();
+        let charged = match self.control.bonus {
+            Bonus::Delay(delay) => used.saturating_sub(delay),
+            _ => used
+        };
+
+        let remaining = &mut self.remaining[side as usize];
+        if charged >= *remaining {
+            *remaining = Duration::ZERO;
+            return false
+        }
+        *remaining -= charged;
+
+        match self.control.bonus {
+            Bonus::Increment(increment) => *remaining += increment,
+            Bonus::Bronstein(delay) => *remaining += used.min(delay),
+            _ => {}
+        }
+
+        self.moves_made[side as usize] += 1;
+        if let Some(moves) = self.control.moves {
+            if self.moves_made[side as usize].is_multiple_of(moves) {
+                *remaining += self.control.base;
+            }
+        }
+
+        true
+    }
+
+    /// Gets the time a side has left, counting down if its clock is running
+    pub fn get_remaining(&self, side: Side) -> Duration {
+        let remaining = self.remaining[side as usize];
+
+        match self.running {
+            Some((running, started)) if running == side => {
+                let used = started.elapsed();
+                let charged = match self.control.bonus {
+                    Bonus::Delay(delay) => used.saturating_sub(delay),
+                    _ => used
+                };
+
+                remaining.saturating_sub(charged)
+            },
+            _ => remaining
+        }
+    }
+
+    /// Gets what an engine needs to know to plan its time for the move
+    pub fn get_time_left(&self, side: Side) -> TimeLeft {
+        // Delays are treated like an increment as neither build up
+        let increment = match self.control.bonus {
+            Bonus::None => Duration::ZERO,
+            Bonus::Increment(d) | Bonus::Bronstein(d) | Bonus::Delay(d) => d
+        };
+
+        TimeLeft {
+            remaining: self.get_remaining(side),
+            increment,
+            moves_to_go: self.control.moves.map(|m| m - self.moves_made[side as usize] % m),
+        }
+    }
+}
+
+/// Both players' times, like `White 4:59.2  Black 5:00.0`
+impl std::fmt::Display for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_time = |time: Duration| {
+            let tenths = time.as_millis() / 100;
+            format!("{}:{:02}.{}", tenths / 600, tenths % 600 / 10, tenths % 10)
+        };
+
+        write!(
+            f,
+            "White {}  Black {}",
+            format_time(self.get_remaining(Side::White)),
+            format_time(self.get_remaining(Side::Black))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a move for `side` that took `used`, returning whether it was in
+    /// time
+    fn play(clock: &mut Clock, side: Side, used: Duration) -> bool {
+        clock.running = Some((side, Instant::now() - used));
+        clock.stop()
+    }
+
+    /// The clock keeps running for a moment after the move was made
+    fn assert_close(actual: Duration, expected: Duration) {
+        assert!(actual <= expected && expected - actual < Duration::from_millis(50), "{:?} {:?}", actual, expected);
+    }
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn parse_time_controls() {
+        let control = |base, moves, bonus| TimeControl { base, moves, bonus };
+
+        assert_eq!("5+3".parse(), Ok(control(secs(300), None, Bonus::Increment(secs(3)))));
+        assert_eq!("40/90".parse(), Ok(control(secs(5400), Some(40), Bonus::None)));
+        assert_eq!("5b2".parse(), Ok(control(secs(300), None, Bonus::Bronstein(secs(2)))));
+        assert_eq!("5d2".parse(), Ok(control(secs(300), None, Bonus::Delay(secs(2)))));
+        assert_eq!("40/120+30".parse(), Ok(control(secs(7200), Some(40), Bonus::Increment(secs(30)))));
+        assert_eq!("0.5+0.5".parse(), Ok(control(secs(30), None, Bonus::Increment(Duration::from_millis(500)))));
+        assert_eq!("3+0".parse(), Ok(control(secs(180), None, Bonus::Increment(Duration::ZERO))));
+
+        for invalid in ["", "0", "-5", "five", "5+", "5+x", "5+-1", "5x3", "0/90", "40/", "/90", "NaN", "inf+1"] {
+            assert!(invalid.parse::<TimeControl>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn increment() {
+        let mut clock = Clock::new("5+3".parse().unwrap());
+
+        assert!(play(&mut clock, Side::White, secs(10)));
+        assert_close(clock.get_remaining(Side::White), secs(293));
+        assert_eq!(clock.get_remaining(Side::Black), secs(300));
+
+        // Quick moves build up time
+        assert!(play(&mut clock, Side::Black, Duration::ZERO));
+        assert_close(clock.get_remaining(Side::Black), secs(303));
+    }
+
+    #[test]
+    fn delays() {
+        // Time used within a simple delay isn't taken off
+        let mut clock = Clock::new("5d2".parse().unwrap());
+        assert!(play(&mut clock, Side::White, secs(1)));
+        assert_eq!(clock.get_remaining(Side::White), secs(300));
+        assert!(play(&mut clock, Side::White, secs(5)));
+        assert_close(clock.get_remaining(Side::White), secs(297));
+
+        // A Bronstein delay gives back what was used, up to the delay
+        let mut clock = Clock::new("5b2".parse().unwrap());
+        assert!(play(&mut clock, Side::White, secs(1)));
+        assert_eq!(clock.get_remaining(Side::White), secs(300));
+        assert!(play(&mut clock, Side::White, secs(5)));
+        assert_close(clock.get_remaining(Side::White), secs(297));
+
+        // Neither builds up time like an increment
+        assert!(play(&mut clock, Side::Black, Duration::ZERO));
+        assert_eq!(clock.get_remaining(Side::Black), secs(300));
+    }
+
+    #[test]
+    fn periods() {
+        let mut clock = Clock::new("2/1".parse().unwrap());
+        assert_eq!(clock.get_time_left(Side::White).moves_to_go, Some(2));
+
+        assert!(play(&mut clock, Side::White, secs(20)));
+        assert_close(clock.get_remaining(Side::White), secs(40));
+        assert_eq!(clock.get_time_left(Side::White).moves_to_go, Some(1));
+
+        // The base time is added again once the period's moves are made
+        assert!(play(&mut clock, Side::White, secs(30)));
+        assert_close(clock.get_remaining(Side::White), secs(70));
+        assert_eq!(clock.get_time_left(Side::White).moves_to_go, Some(2));
+
+        assert!(play(&mut clock, Side::White, secs(10)));
+        assert!(play(&mut clock, Side::White, secs(10)));
+        assert_close(clock.get_remaining(Side::White), secs(110));
+
+        assert_eq!(clock.get_time_left(Side::Black).moves_to_go, Some(2));
+        assert_eq!(clock.get_remaining(Side::Black), secs(60));
+    }
+
+    #[test]
+    fn flag_fall() {
+        let mut clock = Clock::new("1+5".parse().unwrap());
+
+        assert!(!play(&mut clock, Side::White, secs(61)));
+        assert_eq!(clock.get_remaining(Side::White), Duration::ZERO);
+        assert_eq!(clock.get_remaining(Side::Black), secs(60));
+
+        // The delay runs before the clock does
+        let mut clock = Clock::new("1d5".parse().unwrap());
+        assert!(play(&mut clock, Side::White, secs(62)));
+        assert!(!play(&mut clock, Side::Black, secs(66)));
+
+        // Stopping a clock that isn't running does nothing
+        assert!(clock.stop());
+    }
+
+    #[test]
+    fn display() {
+        let mut clock = Clock::new("5+0".parse().unwrap());
+        assert_eq!(clock.to_string(), "White 5:00.0  Black 5:00.0");
+
+        clock.remaining[Side::Black as usize] = Duration::from_millis(61_250);
+        assert_eq!(clock.to_string(), "White 5:00.0  Black 1:01.2");
+    }
+}
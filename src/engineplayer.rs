@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chess::book::Book;
+
+use crate::Player;
+use crate::clock::Clock;
+use crate::search::{Search, SearchLimits};
+use crate::tt::TranspositionTable;
+
+/// Computer player that picks moves with an alpha-beta search
+pub struct EnginePlayer {
+    pub limits: SearchLimits,
+    /// Kept between moves so earlier searches help later ones
+    tt: TranspositionTable,
+    /// Opening book to play from before searching
+    book: Option<Arc<Book>>,
+    /// State of the random number generator used to pick book moves
+    random_state: u64,
+    /// Whether to print each move as it is made
+    pub verbose: bool,
+}
+
+impl EnginePlayer {
+    pub fn new(limits: SearchLimits, hash_mb: usize, book: Option<Arc<Book>>) -> Self {
+        // Seeded from the clock so games from the book differ
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+
+        Self {
+            limits,
+            tt: TranspositionTable::new(hash_mb),
+            book,
+            random_state: seed,
+            verbose: true,
+        }
+    }
+
+    /// Gets the next number from a xorshift generator
+    fn next_random(&mut self) -> u64 {
+        // Xorshift gets stuck at zero
+        if self.random_state == 0 {
+            self.random_state = 0x9E37_79B9_7F4A_7C15;
+        }
+
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 7;
+        self.random_state ^= self.random_state << 17;
+
+        self.random_state
+    }
+}
+
+impl Player for EnginePlayer {
+    fn make_move(&mut self, game: &mut chess::Game, clock: Option<&Clock>) {
+        let random = self.next_random();
+        if let Some(book_move) = self.book.as_ref().and_then(|b| b.choose_move(game, random)) {
+            if self.verbose {
+                println!("{:?} plays {} (book)", game.get_side_to_play(), game.move_to_san(&book_move));
+            }
+            game.apply_unchecked_move(book_move, true);
+            return
+        }
+
+        let mut limits = self.limits;
+        if let Some(clock) = clock {
+            limits.time_left = Some(clock.get_time_left(game.get_side_to_play()));
+        }
+
+        let stop = AtomicBool::new(false);
+        let result = Search::new(game, limits, &stop, &mut self.tt).run(|_| {});
+
+        // The game loop only asks for a move when there is one to make
+        let best_move = result.best_move.expect("Engine asked to move with no legal moves");
+
+        if self.verbose {
+            println!(
+                "{:?} plays {} (depth {}, score {}, {} nodes)", 
+                game.get_side_to_play(), 
+                game.move_to_san(&best_move),
+                result.depth,
+                result.score,
+                result.nodes
+            );
+        }
+
+        game.apply_unchecked_move(best_move, true);
+    }
+}
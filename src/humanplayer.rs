@@ -1,18 +1,34 @@
 use std::io::{self, Write};
-use crate::{Player, chess};
 
-pub struct HumanPlayer {}
+use chess::render::{render, RenderOptions};
+
+use crate::Player;
+use crate::clock::Clock;
+
+pub struct HumanPlayer {
+    /// How the board is drawn. It is always drawn from the side of the
+    /// player to move
+    pub board: RenderOptions,
+}
 
 impl Player for HumanPlayer {
-    fn make_move(&self, game: &mut chess::Game) {
+    fn make_move(&mut self, game: &mut chess::Game, clock: Option<&Clock>) {
         let mut piece_selection: Option<usize> = None;
         let mut move_selection: Option<usize> = None;
 
-        let pieces = game.get_pieces();
+        let mut pieces = game.get_pieces();
         let mut moves= Vec::new();
 
+        let board = RenderOptions {
+            flipped: game.get_side_to_play() == chess::Side::Black,
+            ..self.board
+        };
+
         while piece_selection.is_none() || move_selection.is_none() {
-            println!("{:?}\n", game);
+            println!("{}\n", render(game, &board));
+            if let Some(clock) = clock {
+                println!("{}\n", clock);
+            }
             if piece_selection.is_none() {
                 println!("Pieces:");
 
@@ -20,11 +36,33 @@ impl Player for HumanPlayer {
                     println!("{}: {:?}", i+1, p);
                 }
 
+                // Taking back a move means taking back the opponents reply too
+                let can_undo = game.get_move_history().len() >= 2;
+                if can_undo {
+                    println!("\nU: UNDO LAST MOVE");
+                }
+
                 let mut input_buffer: String = String::new();
-                print!("\nEnter Piece Selection: ");
+                print!("\nEnter Piece Selection or Move: ");
 
                 let _ = io::stdout().flush();
                 let _ = io::stdin().read_line(&mut input_buffer).expect("Error reading in piece");
+
+                if can_undo && input_buffer.trim().eq_ignore_ascii_case("u") {
+                    game.undo_move();
+                    game.undo_move();
+                    pieces = game.get_pieces();
+                    continue
+                }
+
+                // Moves can also be typed in directly, e.g. Nf3 or g1f3
+                let typed_move = game.parse_san(&input_buffer).ok()
+                    .or_else(|| game.parse_uci_move(&input_buffer).ok());
+                if let Some(move_) = typed_move {
+                    game.apply_unchecked_move(move_, true);
+                    return
+                }
+
                 let _ = input_buffer.trim().parse::<usize>().map(
                     |i| if i <= pieces.len() && i > 0 {piece_selection = Some(i-1)}
                 );
@@ -35,7 +73,7 @@ impl Player for HumanPlayer {
                 println!("Moves:");
 
                 for (i, m) in moves.iter().enumerate() {
-                    println!("{}: {:?}", i+1, m)
+                    println!("{}: {}", i+1, game.move_to_san(m))
                 }
 
                 println!("\n0: BACK");
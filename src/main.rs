@@ -1,49 +1,457 @@
-use clap::{Parser, ValueEnum};
-use chess;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use chess::render::{render, RenderOptions};
 
 #[derive(Parser)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Who will play as White
-    #[arg(short, long, value_enum)]
+    #[arg(short, long, value_enum, default_value_t = PlayerMode::Human)]
     white: PlayerMode,
     /// Who will play as Black
-    #[arg(short, long, value_enum)]
+    #[arg(short, long, value_enum, default_value_t = PlayerMode::Human)]
     black: PlayerMode,
+    /// Maximum depth engine players search to
+    #[arg(long)]
+    depth: Option<u32>,
+    /// Time in milliseconds engine players think for on each move, when
+    /// there is no time control
+    #[arg(long, default_value_t = 1000)]
+    movetime: u64,
+    /// Time control for both players, such as 5+3 for 5 minutes with a 3
+    /// second increment, 40/90 for 40 moves in 90 minutes, or 5b2 and 5d2
+    /// for a 2 second Bronstein or simple delay
+    #[arg(long)]
+    time_control: Option<TimeControl>,
+    /// Size of each engine player's transposition table in megabytes
+    #[arg(long, default_value_t = tt::DEFAULT_HASH_MB)]
+    hash: usize,
+    /// File to save the game to as PGN once it is over
+    #[arg(long)]
+    pgn_out: Option<PathBuf>,
+    /// Play Chess960 from the starting position with this number, where
+    /// 518 is the standard starting position
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..960))]
+    chess960: Option<u32>,
+    /// Polyglot opening book engine players play from before searching
+    #[arg(long)]
+    book: Option<PathBuf>,
+    /// Draw pieces with chess symbols instead of letters
+    #[arg(long)]
+    unicode: bool,
+    /// Colour the board and highlight the last move and checks
+    #[arg(long)]
+    colour: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Count the positions reachable in a number of moves
+    Perft {
+        /// Number of moves to search
+        depth: u32,
+        /// Position to count from
+        #[arg(long, default_value = chess::START_FEN)]
+        fen: String,
+        /// Show the count after each move from the position
+        #[arg(long)]
+        divide: bool,
+    },
+    /// Talk to a chess GUI using the Universal Chess Interface
+    Uci,
+    /// Show how the engine evaluates a position
+    Eval {
+        /// Position to evaluate
+        #[arg(long, default_value = chess::START_FEN)]
+        fen: String,
+    },
+    /// Play games between two engines and estimate the difference in Elo
+    Match(Box<MatchArgs>),
+    /// Run the engine on a test suite of EPD positions and score the moves
+    /// it finds against their bm and am operations
+    Epd {
+        /// EPD file of positions to search
+        file: PathBuf,
+        /// Depth to search each position to
+        #[arg(long)]
+        depth: Option<u32>,
+        /// Time in milliseconds to search each position for, one second if
+        /// no depth is given either
+        #[arg(long)]
+        movetime: Option<u64>,
+        /// Size of the transposition table in megabytes
+        #[arg(long, default_value_t = tt::DEFAULT_HASH_MB)]
+        hash: usize,
+    },
+}
+
+#[derive(Args)]
+struct MatchArgs {
+    /// Settings for the first engine as comma separated key=value pairs,
+    /// from name, depth, movetime in milliseconds, nodes and hash
+    #[arg(long, default_value = "")]
+    first: EngineConfig,
+    /// Settings for the second engine, in the same form as the first
+    #[arg(long, default_value = "")]
+    second: EngineConfig,
+    /// Number of games to play. Engines swap colours each game
+    #[arg(long, default_value_t = 100)]
+    games: usize,
+    /// PGN, FEN or EPD file of positions to start the games from, each
+    /// played twice so both engines get both sides
+    #[arg(long)]
+    openings: Option<PathBuf>,
+    /// Time control for both engines, in the same form as for local games
+    #[arg(long)]
+    time_control: Option<TimeControl>,
+    /// Polyglot opening book both engines play from before searching
+    #[arg(long)]
+    book: Option<PathBuf>,
+    /// Number of games to play at the same time
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+    /// File to save all the games to as PGN
+    #[arg(long)]
+    pgn_out: Option<PathBuf>,
+    /// Elo difference of the null hypothesis in the SPRT
+    #[arg(long, default_value_t = 0.0)]
+    elo0: f64,
+    /// Elo difference of the alternative hypothesis in the SPRT
+    #[arg(long, default_value_t = 5.0)]
+    elo1: f64,
+    /// Chance of a false positive in the SPRT
+    #[arg(long, default_value_t = 0.05)]
+    alpha: f64,
+    /// Chance of a false negative in the SPRT
+    #[arg(long, default_value_t = 0.05)]
+    beta: f64,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
 enum PlayerMode {
-    Human
+    Human,
+    Engine
 }
 
 trait Player {
-    fn make_move(&self, game: &mut chess::Game);
+    /// Makes a move in the game. `clock` is the game's clock if it has one,
+    /// already running for the side to move
+    fn make_move(&mut self, game: &mut chess::Game, clock: Option<&Clock>);
 }
 
+mod clock;
+use clock::{Clock, TimeControl};
+
 mod humanplayer;
 use humanplayer::HumanPlayer;
 
+mod engineplayer;
+use engineplayer::EnginePlayer;
+
+mod matchrunner;
+use matchrunner::{EngineConfig, MatchRunner};
+
+mod search;
+use search::SearchLimits;
+
+mod testsuite;
+
+mod timeman;
+
+mod tt;
+
+mod uci;
+
 fn main() {
     let cli = Cli::parse();
 
-    let mut game = chess::Game::new();
+    match cli.command {
+        Some(Command::Perft { depth, fen, divide }) => run_perft(depth, &fen, divide),
+        Some(Command::Uci) => uci::Uci::new().run(),
+        Some(Command::Eval { fen }) => {
+            let game = parse_fen_or_exit(&fen);
+            println!("{}\n", game);
+            println!("{}", chess::eval::Evaluation::new(&game));
+        },
+        Some(Command::Match(args)) => {
+            let MatchArgs {
+                first, second, games, openings, time_control, book, concurrency, pgn_out, elo0, elo1, alpha, beta
+            } = *args;
+
+            let openings = match openings {
+                Some(path) => matchrunner::read_openings(&path).unwrap_or_else(|e| {
+                    eprintln!("Could not read openings from {}: {}", path.display(), e);
+                    std::process::exit(1)
+                }),
+                None => Vec::new()
+            };
+
+            // Engines that don't choose moves at random play the same two
+            // games over and over without something to vary them
+            if openings.is_empty() && book.is_none() {
+                eprintln!("Warning: no --openings or --book given, so every pair of games will likely be the same");
+            }
+
+            let runner = MatchRunner {
+                first,
+                second,
+                games,
+                openings,
+                time_control,
+                book: book.map(|path| Arc::new(read_book_or_exit(&path))),
+                concurrency,
+                sprt: matchrunner::Sprt { elo0, elo1, alpha, beta },
+            };
+
+            let games = runner.run();
+
+            if let Some(path) = pgn_out {
+                matchrunner::save_games(&games, &path);
+            }
+        },
+        Some(Command::Epd { file, depth, movetime, hash }) => {
+            let limits = SearchLimits {
+                depth,
+                movetime: match (depth, movetime) {
+                    (Some(_), None) => None,
+                    (_, movetime) => Some(Duration::from_millis(movetime.unwrap_or(1000)))
+                },
+                time_left: None,
+                nodes: None
+            };
+
+            testsuite::run(&read_epd_or_exit(&file), limits, hash);
+        },
+        None => {
+            let limits = SearchLimits {
+                depth: cli.depth,
+                movetime: match cli.time_control {
+                    Some(_) => None,
+                    None => Some(Duration::from_millis(cli.movetime))
+                },
+                time_left: None,
+                nodes: None
+            };
+
+            let start = match cli.chess960 {
+                Some(number) => chess::Game::new_chess960(number).unwrap(),
+                None => chess::Game::new()
+            };
+
+            let book = cli.book.map(|path| Arc::new(read_book_or_exit(&path)));
+
+            let board = RenderOptions {
+                unicode: cli.unicode,
+                colour: cli.colour,
+                ..RenderOptions::default()
+            };
+
+            let (game, flagged) = play_game(
+                start,
+                decode_player_arg(cli.white, limits, cli.hash, book.clone(), board), 
+                decode_player_arg(cli.black, limits, cli.hash, book, board),
+                cli.time_control.map(Clock::new),
+                &board
+            );
+
+            if let Some(path) = cli.pgn_out {
+                save_pgn(&game, &path, cli.white, cli.black, flagged);
+            }
+        }
+    }
+}
+
+/// Plays the game out, returning it and the side that ran out of time if
+/// the game ended that way
+fn play_game(
+    mut game: chess::Game,
+    mut white: Box<dyn Player>,
+    mut black: Box<dyn Player>,
+    mut clock: Option<Clock>,
+    board: &RenderOptions
+) -> (chess::Game, Option<chess::Side>) {
+    let flagged = play_out(&mut game, white.as_mut(), black.as_mut(), clock.as_mut());
+
+    println!("{}\n", render(&game, board));
+    if let Some(clock) = &clock {
+        println!("{}", clock);
+    }
+
+    match flagged {
+        Some(side) if game.has_mating_material(side.other()) =>
+            println!("{:?} wins on time", side.other()),
+        Some(side) =>
+            println!("Draw as {:?} ran out of time but {:?} can't checkmate", side, side.other()),
+        None => println!("{}", game.get_status())
+    }
+
+    (game, flagged)
+}
+
+/// Makes moves until the game is over or a side runs out of time, returning
+/// the side that did
+fn play_out(
+    game: &mut chess::Game,
+    white: &mut dyn Player,
+    black: &mut dyn Player,
+    mut clock: Option<&mut Clock>
+) -> Option<chess::Side> {
+    while !game.get_status().is_over() {
+        let side = game.get_side_to_play();
+
+        if let Some(clock) = clock.as_mut() {
+            clock.start(side);
+        }
+
+        match side {
+            chess::Side::White => white.make_move(game, clock.as_deref()),
+            chess::Side::Black => black.make_move(game, clock.as_deref())
+        }
+
+        // A move made after the flag fell doesn't count
+        if clock.as_mut().is_some_and(|c| !c.stop()) {
+            game.undo_move();
+            return Some(side)
+        }
+    }
+
+    None
+}
+
+/// Fills in the result of a game that ended on time, which is a draw if the
+/// side with time left has no way to checkmate
+fn record_flag_fall(pgn: &mut chess::pgn::PgnGame, game: &chess::Game, flagged: chess::Side) {
+    pgn.result = match flagged.other() {
+        winner if !game.has_mating_material(winner) => chess::pgn::PgnResult::Draw,
+        chess::Side::White => chess::pgn::PgnResult::WhiteWins,
+        chess::Side::Black => chess::pgn::PgnResult::BlackWins
+    };
+    pgn.set_tag("Result", &pgn.result.to_string());
+    pgn.set_tag("Termination", "time forfeit");
+}
+
+fn save_pgn(game: &chess::Game, path: &PathBuf, white: PlayerMode, black: PlayerMode, flagged: Option<chess::Side>) {
+    let player_name = |mode| match mode {
+        PlayerMode::Human => "Human".to_string(),
+        PlayerMode::Engine => format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    };
+
+    let mut pgn = chess::pgn::PgnGame::from_game(game);
+    pgn.set_tag("Event", "Local game");
+    pgn.set_tag("Date", &pgn_date());
+    pgn.set_tag("White", &player_name(white));
+    pgn.set_tag("Black", &player_name(black));
+
+    if let Some(side) = flagged {
+        record_flag_fall(&mut pgn, game, side);
+    }
+
+    match std::fs::write(path, pgn.to_string()) {
+        Ok(()) => println!("Saved game to {}", path.display()),
+        Err(e) => eprintln!("Could not save game to {}: {}", path.display(), e)
+    }
+}
+
+/// Today's date in the `YYYY.MM.DD` form PGN uses
+fn pgn_date() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+    // Converts days since 1970-01-01 to a date, counting years from March
+    // so leap days come at the end
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
 
-    let white: Box<dyn Player> = decode_player_arg(cli.white);
-    let black: Box<dyn Player> = decode_player_arg(cli.black);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn parse_fen_or_exit(fen: &str) -> chess::Game {
+    match chess::Game::from_fen(fen) {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("Invalid FEN: {}", e);
+            std::process::exit(1)
+        }
+    }
+}
 
-    while game.get_winner().is_none() {
-        match game.get_side_to_play() {
-            chess::Side::White => white.make_move(&mut game),
-            chess::Side::Black => black.make_move(&mut game)
+fn read_book_or_exit(path: &Path) -> chess::book::Book {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Could not read book {}: {}", path.display(), e);
+        std::process::exit(1)
+    });
+
+    match chess::book::Book::from_bytes(&bytes) {
+        Ok(book) => book,
+        Err(e) => {
+            eprintln!("Invalid book {}: {}", path.display(), e);
+            std::process::exit(1)
         }
     }
+}
 
-    println!("{:?}", game)
+fn read_epd_or_exit(path: &Path) -> Vec<chess::epd::EpdRecord> {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Could not read {}: {}", path.display(), e);
+        std::process::exit(1)
+    });
 
+    match chess::epd::parse_epd(&text) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Invalid EPD in {}: {}", path.display(), e);
+            std::process::exit(1)
+        }
+    }
 }
 
-fn decode_player_arg(arg: PlayerMode) -> Box<dyn Player> {
+fn run_perft(depth: u32, fen: &str, divide: bool) {
+    let game = parse_fen_or_exit(fen);
+
+    let start = Instant::now();
+
+    let nodes = if divide {
+        let mut results = chess::perft_divide(&game, depth);
+        results.sort_by_key(|(m, _)| game.move_to_uci(m));
+
+        for (move_, nodes) in &results {
+            println!("{}: {}", game.move_to_uci(move_), nodes);
+        }
+        println!();
+
+        results.iter().map(|(_, n)| n).sum()
+    } else {
+        chess::perft(&game, depth)
+    };
+
+    let elapsed = start.elapsed();
+
+    println!("Nodes: {}", nodes);
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!("Nodes/s: {:.0}", nodes as f64 / elapsed.as_secs_f64().max(1e-9));
+}
+
+fn decode_player_arg(
+    arg: PlayerMode,
+    limits: SearchLimits,
+    hash_mb: usize,
+    book: Option<Arc<chess::book::Book>>,
+    board: RenderOptions
+) -> Box<dyn Player> {
     match arg {
-        PlayerMode::Human => Box::new(HumanPlayer {})
+        PlayerMode::Human => Box::new(HumanPlayer { board }),
+        PlayerMode::Engine => Box::new(EnginePlayer::new(limits, hash_mb, book))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,414 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use chess::book::Book;
+use chess::pgn::{PgnGame, PgnResult};
+
+use crate::clock::{Clock, TimeControl};
+use crate::engineplayer::EnginePlayer;
+use crate::search::SearchLimits;
+use crate::tt;
+
+/// Time each move gets when an engine has no other limit and there is no
+/// time control
+const DEFAULT_MOVETIME: Duration = Duration::from_millis(100);
+
+/// Settings for one of the engines in a match
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub name: Option<String>,
+    pub limits: SearchLimits,
+    pub hash_mb: usize,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            limits: SearchLimits::default(),
+            hash_mb: tt::DEFAULT_HASH_MB,
+        }
+    }
+}
+
+/// Parses comma separated `key=value` pairs, e.g. `name=deep,depth=6`. The
+/// keys are `name`, `depth`, `movetime` in milliseconds, `nodes` and `hash`
+/// in megabytes.
+impl FromStr for EngineConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+
+        for setting in s.split(',').filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=')
+                .ok_or_else(|| format!("expected key=value, found {}", setting))?;
+
+            let invalid = || format!("invalid value {} for {}", value, key);
+
+            match key {
+                "name" => config.name = Some(value.to_string()),
+                "depth" => config.limits.depth = Some(value.parse().map_err(|_| invalid())?),
+                "movetime" => config.limits.movetime = Some(Duration::from_millis(value.parse().map_err(|_| invalid())?)),
+                "nodes" => config.limits.nodes = Some(value.parse().map_err(|_| invalid())?),
+                "hash" => config.hash_mb = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown setting {}, expected name, depth, movetime, nodes or hash", key))
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Bounds of the sequential probability ratio test, which decides whether
+/// the first engine is `elo0` or `elo1` stronger than the second
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Chance of accepting `elo1` when `elo0` is true
+    pub alpha: f64,
+    /// Chance of accepting `elo0` when `elo1` is true
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// Log likelihood ratio below which `elo0` is accepted
+    pub fn get_lower_bound(&self) -> f64 {
+        (self.beta / (1.0 - self.alpha)).ln()
+    }
+
+    /// Log likelihood ratio above which `elo1` is accepted
+    pub fn get_upper_bound(&self) -> f64 {
+        ((1.0 - self.beta) / self.alpha).ln()
+    }
+}
+
+/// Wins, draws and losses from the point of view of the first engine
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn get_games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Average points per game, from 0 to 1
+    pub fn get_score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.get_games() as f64
+    }
+
+    /// Variance of the points from a single game. Results that are all the
+    /// same have none, so it is never taken as less than it would be if one
+    /// of the games had been drawn instead
+    fn get_variance(&self) -> f64 {
+        let score = self.get_score();
+        let games = self.get_games() as f64;
+
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2)) / games;
+
+        variance.max(0.25 / games)
+    }
+
+    /// Elo difference along with the margin of its 95% confidence interval.
+    /// Scores of 0% and 100% would be infinitely many Elo apart, so scores
+    /// are kept at least half a game away from them
+    pub fn get_elo(&self) -> (f64, f64) {
+        let games = self.get_games() as f64;
+        let clamp = |score: f64| score.clamp(0.5 / games, 1.0 - 0.5 / games);
+
+        let score = self.get_score();
+        let error = 1.96 * (self.get_variance() / games).sqrt();
+
+        let low = score_to_elo(clamp(score - error));
+        let high = score_to_elo(clamp(score + error));
+
+        (score_to_elo(clamp(score)), (high - low) / 2.0)
+    }
+
+    /// Log likelihood ratio of `elo1` over `elo0` using the normal
+    /// approximation to the game results
+    pub fn get_llr(&self, sprt: &Sprt) -> f64 {
+        let variance = self.get_variance();
+        let s0 = elo_to_score(sprt.elo0);
+        let s1 = elo_to_score(sprt.elo1);
+
+        self.get_games() as f64 * (s1 - s0) * (2.0 * self.get_score() - s0 - s1) / (2.0 * variance)
+    }
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Plays games between two engines, each opening twice with the colours
+/// swapped
+pub struct MatchRunner {
+    pub first: EngineConfig,
+    pub second: EngineConfig,
+    pub games: usize,
+    /// Positions the games start from in turn, the standard starting
+    /// position if empty
+    pub openings: Vec<chess::Game>,
+    pub time_control: Option<TimeControl>,
+    /// Opening book both engines play from before searching, which is what
+    /// makes games from the same position differ
+    pub book: Option<Arc<Book>>,
+    /// Number of games played at once
+    pub concurrency: usize,
+    pub sprt: Sprt,
+}
+
+impl MatchRunner {
+    /// Plays the match, printing each result as it comes in, and returns
+    /// the games in the order they were started
+    pub fn run(&self) -> Vec<PgnGame> {
+        let next_game = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+
+        let mut games = vec![None; self.games];
+        let mut score = MatchScore::default();
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.clamp(1, self.games.max(1)) {
+                let sender = sender.clone();
+                let next_game = &next_game;
+
+                scope.spawn(move || loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= self.games {
+                        break
+                    }
+
+                    if sender.send((index, self.play(index))).is_err() {
+                        break
+                    }
+                });
+            }
+            drop(sender);
+
+            for (index, pgn) in receiver {
+                let first_is_white = index.is_multiple_of(2);
+                match (pgn.result, first_is_white) {
+                    (PgnResult::WhiteWins, true) | (PgnResult::BlackWins, false) => score.wins += 1,
+                    (PgnResult::WhiteWins, false) | (PgnResult::BlackWins, true) => score.losses += 1,
+                    _ => score.draws += 1
+                }
+
+                println!(
+                    "Game {} of {}: {} vs {} {}  Score {}-{}-{}",
+                    index + 1,
+                    self.games,
+                    pgn.get_tag("White").unwrap_or("?"),
+                    pgn.get_tag("Black").unwrap_or("?"),
+                    pgn.result,
+                    score.wins,
+                    score.losses,
+                    score.draws
+                );
+
+                games[index] = Some(pgn);
+            }
+        });
+
+        self.print_summary(&score);
+
+        games.into_iter().flatten().collect()
+    }
+
+    /// Plays a single game of the match
+    fn play(&self, index: usize) -> PgnGame {
+        let mut game = match self.openings.is_empty() {
+            true => chess::Game::new(),
+            false => self.openings[index / 2 % self.openings.len()].clone()
+        };
+
+        let (white, black) = match index.is_multiple_of(2) {
+            true => (&self.first, &self.second),
+            false => (&self.second, &self.first)
+        };
+
+        let mut white_player = self.make_player(white);
+        let mut black_player = self.make_player(black);
+        let mut clock = self.time_control.map(Clock::new);
+
+        let flagged = crate::play_out(&mut game, &mut white_player, &mut black_player, clock.as_mut());
+
+        let mut pgn = PgnGame::from_game(&game);
+        pgn.set_tag("Event", "Engine match");
+        pgn.set_tag("Date", &crate::pgn_date());
+        pgn.set_tag("Round", &(index + 1).to_string());
+        pgn.set_tag("White", &self.get_name(white));
+        pgn.set_tag("Black", &self.get_name(black));
+
+        if let Some(side) = flagged {
+            crate::record_flag_fall(&mut pgn, &game, side);
+        }
+
+        pgn
+    }
+
+    fn make_player(&self, config: &EngineConfig) -> EnginePlayer {
+        let mut limits = config.limits;
+        let unlimited = limits.depth.is_none() && limits.movetime.is_none() && limits.nodes.is_none();
+        if unlimited && self.time_control.is_none() {
+            limits.movetime = Some(DEFAULT_MOVETIME);
+        }
+
+        let mut player = EnginePlayer::new(limits, config.hash_mb, self.book.clone());
+        player.verbose = false;
+        player
+    }
+
+    fn get_name(&self, config: &EngineConfig) -> String {
+        let default = if std::ptr::eq(config, &self.first) { "First" } else { "Second" };
+        config.name.clone().unwrap_or_else(|| default.to_string())
+    }
+
+    fn print_summary(&self, score: &MatchScore) {
+        if score.get_games() == 0 {
+            return
+        }
+
+        let (elo, margin) = score.get_elo();
+        let llr = score.get_llr(&self.sprt);
+        let (lower, upper) = (self.sprt.get_lower_bound(), self.sprt.get_upper_bound());
+
+        let verdict = if llr >= upper {
+            format!("H1 accepted, {} is at least {} Elo stronger", self.get_name(&self.first), self.sprt.elo1)
+        } else if llr <= lower {
+            format!("H0 accepted, {} is no more than {} Elo stronger", self.get_name(&self.first), self.sprt.elo0)
+        } else {
+            "inconclusive".to_string()
+        };
+
+        println!();
+        println!("{} vs {}", self.get_name(&self.first), self.get_name(&self.second));
+        println!("Games: {}  W: {}  L: {}  D: {}", score.get_games(), score.wins, score.losses, score.draws);
+        println!("Score: {:.1}%", score.get_score() * 100.0);
+        println!("Elo difference: {:.1} +/- {:.1}", elo, margin);
+        println!(
+            "SPRT ({}, {}): LLR {:.2} ({:.2}, {:.2}) {}",
+            self.sprt.elo0, self.sprt.elo1, llr, lower, upper, verdict
+        );
+    }
+}
+
+/// Reads starting positions from a PGN file, where the moves of each game
+/// are played out, or from a file with a FEN or EPD position on each line
+pub fn read_openings(path: &Path) -> Result<Vec<chess::Game>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pgn")) {
+        let games = chess::pgn::parse_pgn(&text).map_err(|e| e.to_string())?;
+        return Ok(games.iter().map(PgnGame::to_game).collect())
+    }
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            // EPD lines have operations after the first four FEN fields
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            chess::Game::from_fen(line)
+                .or_else(|_| chess::Game::from_fen(&fields[..fields.len().min(4)].join(" ")))
+                .map_err(|e| format!("line {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+/// Writes all the games of a match to one PGN file
+pub fn save_games(games: &[PgnGame], path: &PathBuf) {
+    let text = games.iter()
+        .map(|pgn| pgn.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match std::fs::write(path, text) {
+        Ok(()) => println!("Saved {} games to {}", games.len(), path.display()),
+        Err(e) => eprintln!("Could not save games to {}: {}", path.display(), e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRT: Sprt = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+
+    #[test]
+    fn elo() {
+        let even = MatchScore { wins: 30, draws: 40, losses: 30 };
+        let (elo, margin) = even.get_elo();
+        assert!(elo.abs() < 1e-9);
+        assert!(margin > 0.0 && margin < 100.0);
+
+        let ahead = MatchScore { wins: 50, draws: 40, losses: 10 };
+        let (elo, _) = ahead.get_elo();
+        assert!((elo - 147.2).abs() < 0.1, "{}", elo);
+
+        for score in [MatchScore { wins: 10, draws: 0, losses: 0 }, MatchScore { wins: 0, draws: 0, losses: 10 }] {
+            let (elo, margin) = score.get_elo();
+            assert!(elo.is_finite() && margin.is_finite(), "{:?}", score);
+            assert!(margin > 0.0, "{:?}", score);
+        }
+
+        assert!(MatchScore { wins: 10, draws: 0, losses: 0 }.get_elo().0 > 300.0);
+        assert!(MatchScore { wins: 0, draws: 0, losses: 10 }.get_elo().0 < -300.0);
+    }
+
+    #[test]
+    fn llr() {
+        let even = MatchScore { wins: 30, draws: 40, losses: 30 };
+        assert!(even.get_llr(&SPRT) < 0.0);
+
+        let ahead = MatchScore { wins: 600, draws: 300, losses: 400 };
+        assert!(ahead.get_llr(&SPRT) > SPRT.get_upper_bound());
+
+        // Clean sweeps and only draws still give an answer
+        let sweep = MatchScore { wins: 30, draws: 0, losses: 0 };
+        assert!(sweep.get_llr(&SPRT) > SPRT.get_upper_bound());
+
+        let drawn = MatchScore { wins: 0, draws: 500, losses: 0 };
+        assert!(drawn.get_llr(&SPRT) < SPRT.get_lower_bound());
+
+        let swept = MatchScore { wins: 0, draws: 0, losses: 30 };
+        assert!(swept.get_llr(&SPRT) < SPRT.get_lower_bound());
+    }
+
+    #[test]
+    fn sprt_bounds() {
+        assert!((SPRT.get_lower_bound() + 2.944).abs() < 0.001);
+        assert!((SPRT.get_upper_bound() - 2.944).abs() < 0.001);
+    }
+
+    #[test]
+    fn engine_config_from_str() {
+        assert_eq!("".parse::<EngineConfig>(), Ok(EngineConfig::default()));
+
+        let config: EngineConfig = "name=deep,depth=6,movetime=250,nodes=1000,hash=32".parse().unwrap();
+        assert_eq!(config.name.as_deref(), Some("deep"));
+        assert_eq!(config.limits.depth, Some(6));
+        assert_eq!(config.limits.movetime, Some(Duration::from_millis(250)));
+        assert_eq!(config.limits.nodes, Some(1000));
+        assert_eq!(config.hash_mb, 32);
+
+        assert!("depth".parse::<EngineConfig>().is_err());
+        assert!("depth=deep".parse::<EngineConfig>().is_err());
+        assert!("movetime=-1".parse::<EngineConfig>().is_err());
+        assert!("speed=3".parse::<EngineConfig>().is_err());
+    }
+}
@@ -0,0 +1,595 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chess::{Game, GenMode, Move, MoveList, PackedMove, PieceType};
+use chess::eval::{evaluate, PIECE_VALUES};
+use chess::tb::{self, Wdl};
+
+use crate::timeman::{TimeLeft, TimeManager};
+use crate::tt::{Bound, TranspositionTable};
+
+/// Score for delivering checkmate on the current move. Mates further away
+/// score less so the quickest mate is preferred
+pub const MATE_SCORE: i32 = 30_000;
+
+/// Scores above this are mates
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1_000;
+
+/// Score for a position the tablebases say is won. Wins further away score
+/// less, and all of them less than any mate the search finds
+pub const TB_WIN_SCORE: i32 = MATE_THRESHOLD - 1_000;
+
+/// Scores above this are mates or tablebase wins, which are both counted
+/// from the root
+pub const TB_WIN_THRESHOLD: i32 = TB_WIN_SCORE - 1_000;
+
+const INFINITY: i32 = 32_000;
+
+/// Depth searched to when there is no other limit on the search
+const MAX_DEPTH: u32 = 64;
+
+/// How many nodes are searched between checking the clock and stop flag
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Limits on how long a search can run for. When no limit is given the
+/// search runs until it is stopped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    /// Time to spend on the move, used instead of the clock if both are given
+    pub movetime: Option<Duration>,
+    /// Clock of the side to move, which the time to spend is worked out from
+    pub time_left: Option<TimeLeft>,
+    pub nodes: Option<u64>,
+}
+
+/// Progress after each iteration of the search
+#[derive(Copy, Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u32,
+    /// Score from the point of view of the side to move in centipawns
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    /// Number of positions found in the tablebases
+    pub tb_hits: u64,
+    pub best_move: Option<Move>,
+    /// How full the transposition table is in permille
+    pub hashfull: usize,
+}
+
+impl SearchInfo {
+    /// Returns the number of moves until mate if the score is a mate, negative
+    /// if the side to move is being mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_THRESHOLD {
+            return None
+        }
+
+        let plies = MATE_SCORE - self.score.abs();
+        let moves = (plies + 1) / 2;
+
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+/// Iterative deepening negamax search with alpha-beta pruning
+pub struct Search<'a> {
+    game: Game,
+    limits: SearchLimits,
+    time: TimeManager,
+    stop: &'a AtomicBool,
+    tt: &'a mut TranspositionTable,
+    start: Instant,
+    nodes: u64,
+    tb_hits: u64,
+    /// Most pieces a position can have to be probed in the tablebases
+    tb_pieces: usize,
+    /// Set once a limit is hit, after which results are thrown away
+    stopped: bool,
+    /// Deepest iteration completed so far. The search can't stop until the
+    /// first iteration completes so there is always a move to play
+    completed_depth: u32,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(game: &Game, limits: SearchLimits, stop: &'a AtomicBool, tt: &'a mut TranspositionTable) -> Self {
+        Self {
+            game: game.clone(),
+            limits,
+            time: TimeManager::new(&limits),
+            stop,
+            tt,
+            start: Instant::now(),
+            nodes: 0,
+            tb_hits: 0,
+            tb_pieces: tb::max_pieces(),
+            stopped: false,
+            completed_depth: 0,
+        }
+    }
+
+    /// Runs the search, calling `on_iteration` after each depth is completed.
+    /// Returns the result of the deepest completed iteration.
+    pub fn run(&mut self, mut on_iteration: impl FnMut(&SearchInfo)) -> SearchInfo {
+        self.start = Instant::now();
+        self.tt.new_search();
+
+        let mut result = SearchInfo {
+            depth: 0,
+            score: 0,
+            nodes: 0,
+            time: Duration::ZERO,
+            tb_hits: 0,
+            best_move: self.game.get_valid_moves().first().copied(),
+            hashfull: 0,
+        };
+
+        let root_probe = self.probe_root();
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).max(1);
+
+        for depth in 1..=max_depth {
+            let root_moves = root_probe.as_ref().map(|(moves, _)| moves.as_slice());
+            let (mut score, best_move) = self.search_root(depth, result.best_move, root_moves);
+
+            // A partial iteration can't be trusted as not every move was
+            // searched
+            if self.stopped {
+                break
+            }
+
+            self.completed_depth = depth;
+            self.time.on_iteration(score, best_move);
+
+            // The tablebases know better unless the search has found a mate
+            if let Some((_, tb_score)) = root_probe {
+                if score.abs() < MATE_THRESHOLD {
+                    score = tb_score;
+                }
+            }
+
+            result = SearchInfo {
+                depth,
+                score,
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+                tb_hits: self.tb_hits,
+                best_move,
+                hashfull: self.tt.get_hashfull(),
+            };
+            on_iteration(&result);
+
+            // No point searching deeper once a forced mate is found
+            if best_move.is_none() || score.abs() >= MATE_THRESHOLD {
+                break
+            }
+
+            // Another iteration takes longer than all the ones before it, so
+            // don't start one that can't finish
+            if self.time.should_stop_iterating(self.start.elapsed()) {
+                break
+            }
+        }
+
+        result
+    }
+
+    /// Uses the DTZ tables to find the root moves that keep the best result
+    /// while getting closest to the next capture or pawn move, so a won
+    /// endgame is won before the fifty move rule. Returns the moves and the
+    /// score of the position.
+    fn probe_root(&mut self) -> Option<(Vec<Move>, i32)> {
+        if self.tb_pieces == 0 {
+            return None
+        }
+
+        let halfmove_clock = self.game.get_halfmove_clock() as i32;
+
+        let mut moves = MoveList::new();
+        self.game.generate_legal_moves(GenMode::All, &mut moves);
+
+        let mut ranked = Vec::with_capacity(moves.len());
+
+        for move_ in moves {
+            let undo = self.game.make_move(move_);
+
+            // DTZ counted from the root, in plies
+            let dtz = if self.game.get_halfmove_clock() == 0 {
+                tb::probe_wdl(&mut self.game).map(|wdl| (-wdl).dtz_before_zeroing())
+            } else {
+                tb::probe_dtz(&mut self.game).map(|dtz| -dtz - dtz.signum())
+            };
+
+            let mates = self.game.is_side_in_check(self.game.get_side_to_play()) &&
+                self.game.get_valid_moves().is_empty();
+
+            self.game.unmake_move(undo);
+
+            let dtz = if mates { 1 } else { dtz? };
+
+            ranked.push((move_, dtz, rank_tb_move(dtz, mates, halfmove_clock)));
+        }
+
+        let &(_, _, best_rank) = ranked.iter().max_by_key(|(_, _, rank)| *rank)?;
+        self.tb_hits += ranked.len() as u64;
+
+        let score = match best_rank.0 {
+            2 => TB_WIN_SCORE,
+            -2 => -TB_WIN_SCORE,
+            _ => 0
+        };
+
+        let moves = ranked.into_iter()
+            .filter(|&(_, _, rank)| rank == best_rank)
+            .map(|(move_, _, _)| move_)
+            .collect();
+
+        Some((moves, score))
+    }
+
+    /// Searches every legal move, or only `root_moves` if given
+    fn search_root(&mut self, depth: u32, previous_best: Option<Move>, root_moves: Option<&[Move]>) -> (i32, Option<Move>) {
+        let mut moves = MoveList::new();
+        self.game.generate_legal_moves(GenMode::All, &mut moves);
+        if let Some(root_moves) = root_moves {
+            moves.retain(|m| root_moves.contains(m));
+        }
+        if moves.is_empty() {
+            return (self.terminal_score(0), None)
+        }
+
+        order_moves(&self.game, &mut moves, previous_best.map(PackedMove::from));
+
+        let mut alpha = -INFINITY;
+        let mut best_move = moves[0];
+
+        for move_ in moves {
+            let undo = self.game.make_move(move_);
+            let score = -self.negamax(depth - 1, 1, -INFINITY, -alpha);
+            self.game.unmake_move(undo);
+
+            if self.stopped {
+                break
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = move_;
+            }
+        }
+
+        if !self.stopped {
+            self.tt.store(self.game.hash(), depth, Bound::Exact, alpha, Some(best_move), 0);
+        }
+
+        (alpha, Some(best_move))
+    }
+
+    fn negamax(&mut self, depth: u32, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0
+        }
+
+        if self.is_draw() {
+            return 0
+        }
+
+        if depth == 0 {
+            return self.quiescence(ply, alpha, beta)
+        }
+
+        self.nodes += 1;
+
+        let key = self.game.hash();
+
+        // Right after a capture or pawn move the fifty move rule is reset,
+        // so the tablebase result is exact
+        if self.tb_pieces > 0 && self.game.get_halfmove_clock() == 0 {
+            if let Some(wdl) = tb::probe_wdl(&mut self.game) {
+                self.tb_hits += 1;
+
+                let score = match wdl {
+                    Wdl::Win => TB_WIN_SCORE - ply as i32,
+                    Wdl::Loss => -TB_WIN_SCORE + ply as i32,
+                    _ => 0
+                };
+
+                self.tt.store(key, MAX_DEPTH, Bound::Exact, score, None, ply);
+                return score
+            }
+        }
+
+        let entry = self.tt.probe(key);
+
+        if let Some(entry) = entry.filter(|e| e.depth >= depth) {
+            let score = entry.get_score(ply);
+
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha
+            };
+
+            if cutoff {
+                return score
+            }
+        }
+
+        let mut moves = MoveList::new();
+        self.game.generate_legal_moves(GenMode::All, &mut moves);
+        if moves.is_empty() {
+            return self.terminal_score(ply)
+        }
+
+        order_moves(&self.game, &mut moves, entry.and_then(|e| e.best_move));
+
+        let mut bound = Bound::Upper;
+        let mut best_move = None;
+
+        for move_ in moves {
+            let undo = self.game.make_move(move_);
+            let score = -self.negamax(depth - 1, ply + 1, -beta, -alpha);
+            self.game.unmake_move(undo);
+
+            // The score is meaningless once the search has been stopped
+            if self.stopped {
+                return 0
+            }
+
+            if score >= beta {
+                self.tt.store(key, depth, Bound::Lower, beta, Some(move_), ply);
+                return beta
+            }
+
+            if score > alpha {
+                alpha = score;
+                bound = Bound::Exact;
+                best_move = Some(move_);
+            }
+        }
+
+        self.tt.store(key, depth, bound, alpha, best_move, ply);
+
+        alpha
+    }
+
+    /// Searches captures until the position is quiet so the evaluation isn't
+    /// taken in the middle of an exchange
+    fn quiescence(&mut self, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0
+        }
+
+        self.nodes += 1;
+
+        let mut moves = MoveList::new();
+
+        // Every way out of check has to be searched, otherwise the side to
+        // move can choose not to capture
+        if self.game.is_side_in_check(self.game.get_side_to_play()) {
+            self.game.generate_legal_moves(GenMode::Evasions, &mut moves);
+            if moves.is_empty() {
+                return self.terminal_score(ply)
+            }
+        } else {
+            let stand_pat = evaluate(&self.game);
+            if stand_pat >= beta {
+                return beta
+            }
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
+
+            self.game.generate_legal_moves(GenMode::Captures, &mut moves);
+        }
+
+        order_moves(&self.game, &mut moves, None);
+
+        for move_ in moves {
+            let undo = self.game.make_move(move_);
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.game.unmake_move(undo);
+
+            if score >= beta {
+                return beta
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        alpha
+    }
+
+    /// Score when the side to move has no moves
+    fn terminal_score(&self, ply: u32) -> i32 {
+        if self.game.is_side_in_check(self.game.get_side_to_play()) {
+            -MATE_SCORE + ply as i32
+        } else {
+            0
+        }
+    }
+
+    /// Draws by the fifty move rule or repetition. A single repetition is
+    /// enough as the side that repeated could do so again
+    fn is_draw(&self) -> bool {
+        self.game.get_halfmove_clock() >= 100 || self.game.get_repetition_count() >= 2
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true
+        }
+
+        if self.completed_depth > 0 && self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            let out_of_time = self.time.is_out_of_time(self.start.elapsed());
+            let out_of_nodes = self.limits.nodes
+                .is_some_and(|n| self.nodes >= n);
+
+            self.stopped = out_of_time || out_of_nodes || self.stop.load(Ordering::Relaxed);
+        }
+
+        self.stopped
+    }
+}
+
+/// Ranks a root move from its DTZ counted from the root, higher being
+/// better. Wins come first, then wins the fifty move rule turns into draws,
+/// then draws and losses the same way round, with quicker wins and slower
+/// losses first within each. A win or loss is only drawn if the move that
+/// resets the fifty move rule comes after the clock has run out.
+fn rank_tb_move(dtz: i32, mates: bool, halfmove_clock: i32) -> (i32, i32) {
+    let drawn = !mates && dtz.abs() + halfmove_clock > 99;
+
+    let result = match dtz.signum() {
+        1 if drawn => 1,
+        1 => 2,
+        -1 if drawn => -1,
+        -1 => -2,
+        _ => 0
+    };
+
+    (result, -dtz)
+}
+
+/// Sorts moves so the best ones are searched first, which lets alpha-beta
+/// cut off more of the tree. The previous best move goes first, followed by
+/// captures of the most valuable pieces by the least valuable attackers.
+fn order_moves(game: &Game, moves: &mut [Move], best_move: Option<PackedMove>) {
+    moves.sort_by_cached_key(|m| {
+        if Some(PackedMove::from(*m)) == best_move {
+            return i32::MIN
+        }
+
+        let victim = match m {
+            Move::EnPassant(_) => piece_value(PieceType::Pawn),
+            _ => game.get_piece_at(m.get_to()).map_or(0, |p| piece_value(p.get_type()))
+        };
+
+        let promotion = match m {
+            Move::Promotion(p) => piece_value(p.new_piece.get_type()),
+            _ => 0
+        };
+
+        if victim == 0 && promotion == 0 {
+            return 0
+        }
+
+        -(victim * 10 + promotion - piece_value(m.get_piece().get_type()))
+    });
+}
+
+fn piece_value(piece_type: PieceType) -> i32 {
+    PIECE_VALUES[piece_type as usize].mg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(fen: &str, limits: SearchLimits) -> SearchInfo {
+        let game = Game::from_fen(fen).unwrap();
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::new(1);
+
+        Search::new(&game, limits, &stop, &mut tt).run(|_| {})
+    }
+
+    fn to_depth(depth: u32) -> SearchLimits {
+        SearchLimits { depth: Some(depth), ..Default::default() }
+    }
+
+    #[test]
+    fn finds_mates() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let result = search(&game.to_fen(), to_depth(4));
+        assert_eq!(result.best_move, Some(game.parse_san("Ra8#").unwrap()));
+        assert_eq!(result.mate_in(), Some(1));
+
+        let game = Game::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let result = search(&game.to_fen(), to_depth(6));
+        assert_eq!(result.mate_in(), Some(2));
+        assert!(["Kb6", "Kc7"].map(|san| game.parse_san(san).unwrap()).contains(&result.best_move.unwrap()));
+
+        // The side being mated sees it coming
+        let result = search("k7/8/1K6/8/8/8/8/7R b - - 0 1", to_depth(4));
+        assert_eq!(result.mate_in(), Some(-1));
+    }
+
+    #[test]
+    fn avoids_stalemate() {
+        // Taking the pawn leaves Black with no moves
+        let game = Game::from_fen("kB6/2K5/8/7p/6P1/8/8/8 w - - 0 1").unwrap();
+        let result = search(&game.to_fen(), to_depth(4));
+
+        assert_ne!(result.best_move, Some(game.parse_san("gxh5").unwrap()));
+        assert!(result.score > 0);
+    }
+
+    #[test]
+    fn tb_move_ranking() {
+        // Quicker wins, then slower losses
+        assert!(rank_tb_move(3, false, 0) > rank_tb_move(9, false, 0));
+        assert!(rank_tb_move(-9, false, 0) > rank_tb_move(-3, false, 0));
+        assert!(rank_tb_move(-3, false, 0) < rank_tb_move(0, false, 0));
+
+        // Wins that reset the clock after it has run out are below every win
+        // that doesn't, but still above a draw
+        assert_eq!(rank_tb_move(69, false, 30).0, 2);
+        assert_eq!(rank_tb_move(70, false, 30).0, 1);
+        assert!(rank_tb_move(70, false, 30) > rank_tb_move(0, false, 30));
+        assert_eq!(rank_tb_move(3, false, 0).0, 2);
+        assert_eq!(rank_tb_move(3, false, 97).0, 1);
+
+        // Cursed wins from the tables are the same
+        assert_eq!(rank_tb_move(105, false, 0).0, rank_tb_move(70, false, 30).0);
+
+        // A loss the clock saves is better than one it doesn't
+        assert!(rank_tb_move(-70, false, 30) > rank_tb_move(-60, false, 30));
+        assert!(rank_tb_move(-70, false, 30) < rank_tb_move(0, false, 30));
+
+        // Mating ends the game before the clock runs out
+        assert_eq!(rank_tb_move(1, true, 99), rank_tb_move(1, true, 0));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let game = Game::new();
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::new(1);
+
+        let mut depths = Vec::new();
+        let result = Search::new(&game, to_depth(3), &stop, &mut tt).run(|info| depths.push(info.depth));
+
+        assert_eq!(depths, [1, 2, 3]);
+        assert_eq!(result.depth, 3);
+    }
+
+    #[test]
+    fn node_limit() {
+        let limits = SearchLimits { nodes: Some(5000), ..Default::default() };
+        let result = search(chess::START_FEN, limits);
+
+        // The limit is checked every so many nodes, and only once the first
+        // iteration is done
+        assert!(result.depth >= 1);
+        assert!(result.nodes <= 5000 + CHECK_INTERVAL, "{}", result.nodes);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn stopped_search_has_a_move() {
+        let game = Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let stop = AtomicBool::new(true);
+        let mut tt = TranspositionTable::new(1);
+
+        let result = Search::new(&game, SearchLimits::default(), &stop, &mut tt).run(|_| {});
+
+        // The first iteration always finishes, and the stop flag is only
+        // checked every so many nodes after that
+        assert!(result.depth >= 1 && result.depth < MAX_DEPTH);
+        assert!(game.get_valid_moves().contains(&result.best_move.unwrap()));
+    }
+}
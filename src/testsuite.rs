@@ -0,0 +1,92 @@
+use std::sync::atomic::AtomicBool;
+
+use chess::epd::EpdRecord;
+use chess::Move;
+
+use crate::search::{Search, SearchLimits};
+use crate::tt::TranspositionTable;
+
+/// Points given for a move the way Strategic Test Suite positions do, from
+/// a `c0` comment like `"f5=10, Be5+=2, Bf2=3"`. Returns `None` if there is
+/// no comment of that form.
+fn get_move_points(record: &EpdRecord, move_: &Move) -> Option<(u32, u32)> {
+    let comment = record.get_comment(0)?;
+
+    let mut best = 0;
+    let mut points = 0;
+
+    for entry in comment.split(',') {
+        let (san, value) = entry.trim().split_once('=')?;
+        let value = value.trim().parse::<u32>().ok()?;
+        let entry_move = record.game.parse_san(san.trim()).ok()?;
+
+        best = best.max(value);
+        if entry_move == *move_ {
+            points = value;
+        }
+    }
+
+    Some((points, best))
+}
+
+/// Searches each position and checks the move found against its `bm` and
+/// `am` operations, printing how every position went and the total score
+pub fn run(records: &[EpdRecord], limits: SearchLimits, hash_mb: usize) {
+    let mut solved = 0;
+    let mut points = 0;
+    let mut max_points = 0;
+
+    for (i, record) in records.iter().enumerate() {
+        let name = record.get_id().map_or_else(|| format!("#{}", i + 1), str::to_string);
+
+        let mut tt = TranspositionTable::new(hash_mb);
+        let stop = AtomicBool::new(false);
+        let result = Search::new(&record.game, limits, &stop, &mut tt).run(|_| {});
+
+        let Some(best_move) = result.best_move else {
+            println!("{}: no legal moves", name);
+            continue
+        };
+
+        let correct = (record.best_moves.is_empty() || record.best_moves.contains(&best_move))
+            && !record.avoid_moves.contains(&best_move);
+
+        // Positions with a point list are scored by it, the rest get a point
+        // when they are solved
+        let (move_points, position_points) = get_move_points(record, &best_move)
+            .unwrap_or((u32::from(correct), 1));
+
+        if correct {
+            solved += 1;
+        }
+        points += move_points;
+        max_points += position_points;
+
+        let expected = record.best_moves.iter()
+            .map(|m| format!("bm {}", record.game.move_to_san(m)))
+            .chain(record.avoid_moves.iter().map(|m| format!("am {}", record.game.move_to_san(m))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        println!(
+            "{}: {} {} ({}, {}/{} points, depth {}, score {})",
+            name,
+            if correct { "solved" } else { "failed" },
+            record.game.move_to_san(&best_move),
+            expected,
+            move_points,
+            position_points,
+            result.depth,
+            result.score
+        );
+    }
+
+    println!();
+    println!(
+        "Solved {} of {} ({:.1}%)",
+        solved,
+        records.len(),
+        100.0 * solved as f64 / records.len().max(1) as f64
+    );
+    println!("Score: {} of {} points", points, max_points);
+}
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use crate::search::SearchLimits;
+
+/// Time kept back on every move for talking to the GUI, so the clock doesn't
+/// run out while the move is being sent
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+/// Number of moves the remaining time is spread over when it isn't known
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// Most the hard limit can be compared to the soft limit
+const HARD_LIMIT_FACTOR: u32 = 3;
+
+/// Score drop between iterations in centipawns treated as a fail low
+const FAIL_LOW_MARGIN: i32 = 30;
+
+/// Most the soft limit can be stretched by fail lows and best move changes
+const MAX_SCALE: f64 = 2.5;
+
+/// Time left on the clock of the side to move
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeLeft {
+    pub remaining: Duration,
+    /// Time added after each move
+    pub increment: Duration,
+    /// Moves until the next time control, if there is one
+    pub moves_to_go: Option<u32>,
+}
+
+/// Decides how long to think about a move. The soft limit is checked after
+/// each iteration and is stretched when the search is unsure, while the hard
+/// limit is checked during the search and is never passed.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    /// How much the soft limit is currently stretched by
+    scale: f64,
+    previous_score: Option<i32>,
+    previous_best: Option<chess::PackedMove>,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits) -> Self {
+        let (soft, hard) = match (limits.movetime, limits.time_left) {
+            // A fixed time per move is used in full
+            (Some(movetime), _) => (Some(movetime), Some(movetime)),
+            (None, Some(time_left)) => {
+                let (soft, hard) = allocate(&time_left);
+                (Some(soft), Some(hard))
+            },
+            (None, None) => (None, None)
+        };
+
+        Self {
+            soft,
+            hard,
+            scale: 1.0,
+            previous_score: None,
+            previous_best: None,
+        }
+    }
+
+    /// Updates how unsure the search is after an iteration completes. The
+    /// soft limit grows when the score drops or the best move changes, and
+    /// shrinks back while the best move stays the same.
+    pub fn on_iteration(&mut self, score: i32, best_move: Option<chess::Move>) {
+        let best_move = best_move.map(chess::PackedMove::from);
+
+        let failed_low = self.previous_score.is_some_and(|s| score < s - FAIL_LOW_MARGIN);
+        let best_changed = self.previous_best.is_some() && best_move != self.previous_best;
+
+        if failed_low {
+            self.scale *= 1.5;
+        }
+        if best_changed {
+            self.scale *= 1.3;
+        }
+        if !failed_low && !best_changed {
+            self.scale *= 0.9;
+        }
+        self.scale = self.scale.clamp(0.5, MAX_SCALE);
+
+        self.previous_score = Some(score);
+        self.previous_best = best_move;
+    }
+
+    /// Whether to stop before starting another iteration
+    pub fn should_stop_iterating(&self, elapsed: Duration) -> bool {
+        let Some(soft) = self.soft else { return false };
+
+        let soft = soft.mul_f64(self.scale);
+        elapsed >= self.hard.map_or(soft, |hard| soft.min(hard))
+    }
+
+    /// Whether the search has to stop right away
+    pub fn is_out_of_time(&self, elapsed: Duration) -> bool {
+        self.hard.is_some_and(|hard| elapsed >= hard)
+    }
+}
+
+/// Splits the time left over the moves to go, returning the soft and hard
+/// limits for this move
+fn allocate(time_left: &TimeLeft) -> (Duration, Duration) {
+    let available = time_left.remaining.saturating_sub(MOVE_OVERHEAD);
+    let moves_to_go = time_left.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+    let soft = (available / moves_to_go + time_left.increment * 3 / 4).min(available);
+
+    // Half the clock is kept back unless this is the last move before the
+    // time control
+    let hard = (soft * HARD_LIMIT_FACTOR).min(available / 2).max(soft);
+
+    (soft, hard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time_left(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> TimeLeft {
+        TimeLeft {
+            remaining: Duration::from_millis(remaining_ms),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+        }
+    }
+
+    #[test]
+    fn allocate_limits() {
+        // A minute spread over 30 moves, with the hard limit three times
+        // the soft one
+        let (soft, hard) = allocate(&time_left(60_000, 0, None));
+        assert_eq!(soft, Duration::from_nanos(1_998_333_333));
+        assert_eq!(hard, Duration::from_nanos(5_994_999_999));
+
+        // Most of the increment is used on top
+        let (soft, hard) = allocate(&time_left(10_000, 1_000, None));
+        assert_eq!(soft, Duration::from_nanos(1_081_666_666));
+        assert_eq!(hard, Duration::from_nanos(3_244_999_998));
+
+        // The hard limit keeps half the clock back
+        let (soft, hard) = allocate(&time_left(10_000, 0, Some(3)));
+        assert_eq!(soft, Duration::from_nanos(3_316_666_666));
+        assert_eq!(hard, Duration::from_millis(4_975));
+
+        // Everything but the overhead can go on the last move before the
+        // time control, and no moves to go is treated the same
+        assert_eq!(allocate(&time_left(5_000, 0, Some(1))), (Duration::from_millis(4_950), Duration::from_millis(4_950)));
+        assert_eq!(allocate(&time_left(5_000, 0, Some(0))), (Duration::from_millis(4_950), Duration::from_millis(4_950)));
+    }
+
+    #[test]
+    fn allocate_little_time_left() {
+        // The increment isn't spent before it has been added
+        let (soft, hard) = allocate(&time_left(200, 2_000, None));
+        assert_eq!(soft, Duration::from_millis(150));
+        assert_eq!(hard, Duration::from_millis(150));
+
+        // With less than the overhead left, moves are played right away
+        assert_eq!(allocate(&time_left(40, 0, None)), (Duration::ZERO, Duration::ZERO));
+        assert_eq!(allocate(&time_left(50, 1_000, Some(1))), (Duration::ZERO, Duration::ZERO));
+    }
+
+    #[test]
+    fn movetime_is_used_in_full() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(500)),
+            time_left: Some(time_left(60_000, 0, None)),
+            ..SearchLimits::default()
+        };
+        let time = TimeManager::new(&limits);
+
+        assert!(!time.should_stop_iterating(Duration::from_millis(499)));
+        assert!(time.should_stop_iterating(Duration::from_millis(500)));
+        assert!(time.is_out_of_time(Duration::from_millis(500)));
+
+        let time = TimeManager::new(&SearchLimits::default());
+        assert!(!time.should_stop_iterating(Duration::from_secs(3600)));
+        assert!(!time.is_out_of_time(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn iteration_scaling() {
+        let game = chess::Game::new();
+        let e4 = game.parse_san("e4").ok();
+        let d4 = game.parse_san("d4").ok();
+
+        let limits = SearchLimits { time_left: Some(time_left(60_000, 0, None)), ..SearchLimits::default() };
+        let mut time = TimeManager::new(&limits);
+        let soft = time.soft.unwrap();
+
+        // The first iteration has nothing to compare to, so counts as stable
+        time.on_iteration(20, e4);
+        assert_eq!(time.scale, 0.9);
+
+        // A fail low, then a new best move, then both
+        time.on_iteration(-20, e4);
+        assert_eq!(time.scale, 0.9 * 1.5);
+        time.on_iteration(-20, d4);
+        assert_eq!(time.scale, 0.9 * 1.5 * 1.3);
+        time.on_iteration(-60, e4);
+        assert_eq!(time.scale, MAX_SCALE);
+
+        // Stretched past the hard limit, which still applies
+        assert!(!time.should_stop_iterating(soft * 2));
+        assert!(time.should_stop_iterating(time.hard.unwrap()));
+
+        // A drop within the margin with the same move shrinks it back down
+        // to half
+        time.on_iteration(-80, e4);
+        assert_eq!(time.scale, MAX_SCALE * 0.9);
+        for _ in 0..20 {
+            time.on_iteration(-80, e4);
+        }
+        assert_eq!(time.scale, 0.5);
+        assert!(!time.should_stop_iterating(soft.mul_f64(0.5) - Duration::from_millis(1)));
+        assert!(time.should_stop_iterating(soft.mul_f64(0.5)));
+    }
+}
@@ -0,0 +1,239 @@
+use chess::{Move, PackedMove};
+
+use crate::search::TB_WIN_THRESHOLD;
+
+/// Size of the table in megabytes when none is given
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// Largest table size allowed in megabytes
+pub const MAX_HASH_MB: usize = 4096;
+
+/// How a stored score relates to the true score of the position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact
+    Exact,
+    /// The search failed high, so the true score is at least this
+    Lower,
+    /// The search failed low, so the true score is at most this
+    Upper,
+}
+
+/// Result of searching a position, stored so it doesn't have to be
+/// searched again when the position is reached by another move order
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u32,
+    pub bound: Bound,
+    /// Score from the point of view of the side to move. Mate and
+    /// tablebase win scores are stored relative to this position rather
+    /// than the root
+    score: i32,
+    /// Packed to keep entries small, so more fit in the table
+    pub best_move: Option<PackedMove>,
+    /// Which search stored the entry
+    age: u8,
+}
+
+impl Entry {
+    /// Gets the score with mates and tablebase wins measured from the root,
+    /// `ply` moves from this position
+    pub fn get_score(&self, ply: u32) -> i32 {
+        if self.score >= TB_WIN_THRESHOLD {
+            self.score - ply as i32
+        } else if self.score <= -TB_WIN_THRESHOLD {
+            self.score + ply as i32
+        } else {
+            self.score
+        }
+    }
+}
+
+/// Fixed size hash table of search results indexed by position hash
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    /// Incremented for each new search so old entries can be replaced first
+    age: u8,
+}
+
+impl TranspositionTable {
+    /// Makes a table using about `mb` megabytes of memory
+    pub fn new(mb: usize) -> Self {
+        let entry_size = std::mem::size_of::<Option<Entry>>();
+        let len = (mb.clamp(1, MAX_HASH_MB) * 1024 * 1024 / entry_size).max(1);
+
+        Self {
+            entries: vec![None; len],
+            age: 0,
+        }
+    }
+
+    /// Removes every entry
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.age = 0;
+    }
+
+    /// Marks the start of a new search. Entries from earlier searches are
+    /// kept but are the first to be replaced
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    fn get_index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    /// Gets the entry for the position, if it has one
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.entries[self.get_index(key)].filter(|e| e.key == key)
+    }
+
+    /// Stores the result of searching a position `ply` moves from the root.
+    /// An entry from the current search is only replaced by a search that
+    /// was at least as deep, unless the new score is exact.
+    pub fn store(&mut self, key: u64, depth: u32, bound: Bound, score: i32, best_move: Option<Move>, ply: u32) {
+        let index = self.get_index(key);
+
+        if let Some(old) = self.entries[index] {
+            let replace = old.age != self.age || old.key == key ||
+                depth >= old.depth || bound == Bound::Exact;
+
+            if !replace {
+                return
+            }
+        }
+
+        // Mate and tablebase win scores are stored from this position so
+        // they are still right when it is reached at a different ply
+        let score = if score >= TB_WIN_THRESHOLD {
+            score + ply as i32
+        } else if score <= -TB_WIN_THRESHOLD {
+            score - ply as i32
+        } else {
+            score
+        };
+
+        // Keep the old best move when the new search didn't find one
+        let best_move = best_move.map(PackedMove::from).or_else(|| {
+            self.entries[index].filter(|e| e.key == key).and_then(|e| e.best_move)
+        });
+
+        self.entries[index] = Some(Entry { key, depth, bound, score, best_move, age: self.age });
+    }
+
+    /// Gets how full the table is in permille, from a sample of the entries
+    pub fn get_hashfull(&self) -> usize {
+        let sample = self.entries.len().min(1000);
+
+        self.entries[..sample].iter()
+            .filter(|e| e.is_some_and(|e| e.age == self.age))
+            .count() * 1000 / sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{MATE_SCORE, TB_WIN_SCORE};
+
+    #[test]
+    fn mate_scores_move_with_ply() {
+        let mut tt = TranspositionTable::new(1);
+
+        // Mate in 3 plies from a position 2 plies from the root
+        tt.store(1, 4, Bound::Exact, MATE_SCORE - 5, None, 2);
+        tt.store(2, 4, Bound::Lower, -MATE_SCORE + 6, None, 3);
+        tt.store(3, 4, Bound::Upper, 250, None, 3);
+
+        let entry = tt.probe(1).unwrap();
+        assert_eq!(entry.get_score(2), MATE_SCORE - 5);
+        assert_eq!(entry.get_score(6), MATE_SCORE - 9);
+        assert_eq!(entry.get_score(0), MATE_SCORE - 3);
+
+        let entry = tt.probe(2).unwrap();
+        assert_eq!(entry.get_score(3), -MATE_SCORE + 6);
+        assert_eq!(entry.get_score(1), -MATE_SCORE + 4);
+        assert_eq!(entry.bound, Bound::Lower);
+
+        // Other scores don't depend on the ply
+        assert_eq!(tt.probe(3).unwrap().get_score(9), 250);
+        assert_eq!(tt.probe(4), None);
+    }
+
+    #[test]
+    fn tb_scores_move_with_ply() {
+        let mut tt = TranspositionTable::new(1);
+
+        // A tablebase win found 3 plies from the root
+        tt.store(1, 64, Bound::Exact, TB_WIN_SCORE - 3, None, 3);
+        tt.store(2, 64, Bound::Exact, -TB_WIN_SCORE + 5, None, 5);
+
+        assert_eq!(tt.probe(1).unwrap().get_score(7), TB_WIN_SCORE - 7);
+        assert_eq!(tt.probe(2).unwrap().get_score(1), -TB_WIN_SCORE + 1);
+    }
+
+    #[test]
+    fn replacement() {
+        let mut tt = TranspositionTable::new(1);
+        let len = tt.entries.len() as u64;
+        let moves = chess::Game::new().get_valid_moves();
+
+        // Keys that share a slot
+        let (a, b) = (7, 7 + len);
+
+        tt.store(a, 5, Bound::Lower, 10, Some(moves[0]), 0);
+        tt.store(b, 3, Bound::Lower, 20, None, 0);
+        assert_eq!(tt.probe(a).unwrap().depth, 5);
+        assert_eq!(tt.probe(b), None);
+
+        // Deeper and exact results replace it
+        tt.store(b, 5, Bound::Upper, 20, None, 0);
+        assert_eq!(tt.probe(b).unwrap().depth, 5);
+        assert_eq!(tt.probe(a), None);
+
+        tt.store(a, 1, Bound::Exact, 30, None, 0);
+        assert_eq!(tt.probe(a).unwrap().get_score(0), 30);
+
+        // The same position is always replaced, keeping its best move
+        tt.store(a, 6, Bound::Lower, 40, Some(moves[1]), 0);
+        tt.store(a, 2, Bound::Upper, 50, None, 0);
+        let entry = tt.probe(a).unwrap();
+        assert_eq!((entry.depth, entry.bound), (2, Bound::Upper));
+        assert_eq!(entry.best_move, Some(PackedMove::from(moves[1])));
+
+        // Anything replaces entries from an earlier search
+        tt.new_search();
+        tt.store(b, 1, Bound::Lower, 60, None, 0);
+        assert_eq!(tt.probe(b).unwrap().depth, 1);
+        assert_eq!(tt.probe(a), None);
+
+        // A move stored for another position isn't kept
+        assert_eq!(tt.probe(b).unwrap().best_move, None);
+
+        tt.clear();
+        assert_eq!(tt.probe(b), None);
+    }
+
+    #[test]
+    fn hashfull() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.get_hashfull(), 0);
+
+        for key in 0..500 {
+            tt.store(key, 1, Bound::Exact, 0, None, 0);
+        }
+        assert_eq!(tt.get_hashfull(), 500);
+
+        for key in 500..1000 {
+            tt.store(key, 1, Bound::Exact, 0, None, 0);
+        }
+        assert_eq!(tt.get_hashfull(), 1000);
+
+        // Entries from earlier searches don't count
+        tt.new_search();
+        tt.store(0, 1, Bound::Exact, 0, None, 0);
+        assert_eq!(tt.get_hashfull(), 1);
+    }
+}
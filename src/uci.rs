@@ -0,0 +1,371 @@
+use std::io::{self, BufRead};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::search::{Search, SearchInfo, SearchLimits};
+use crate::timeman::TimeLeft;
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+
+/// Limits sent with the `go` command. Times are in milliseconds
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GoParams {
+    pub depth: Option<u32>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub nodes: Option<u64>,
+    pub infinite: bool,
+}
+
+impl GoParams {
+    /// Parses the arguments of a `go` command. Unknown arguments are ignored
+    pub fn parse(args: &[&str]) -> Self {
+        let mut params = GoParams::default();
+        let mut i = 0;
+
+        while i < args.len() {
+            let value = args.get(i + 1);
+
+            match args[i] {
+                "depth" => params.depth = value.and_then(|v| v.parse().ok()),
+                "movetime" => params.movetime = value.and_then(|v| v.parse().ok()),
+                "wtime" => params.wtime = value.and_then(|v| parse_time(v)),
+                "btime" => params.btime = value.and_then(|v| parse_time(v)),
+                "winc" => params.winc = value.and_then(|v| parse_time(v)),
+                "binc" => params.binc = value.and_then(|v| parse_time(v)),
+                "movestogo" => params.movestogo = value.and_then(|v| v.parse().ok()),
+                "nodes" => params.nodes = value.and_then(|v| v.parse().ok()),
+                "infinite" => {
+                    params.infinite = true;
+                    i += 1;
+                    continue
+                },
+                _ => {
+                    i += 1;
+                    continue
+                }
+            }
+
+            i += 2;
+        }
+
+        params
+    }
+}
+
+impl GoParams {
+    /// Works out the limits to search with for the side to move
+    pub fn get_limits(&self, side: chess::Side) -> SearchLimits {
+        if self.infinite {
+            return SearchLimits { depth: self.depth, ..Default::default() }
+        }
+
+        let (time, inc) = match side {
+            chess::Side::White => (self.wtime, self.winc),
+            chess::Side::Black => (self.btime, self.binc)
+        };
+
+        let time_left = time.map(|t| TimeLeft {
+            remaining: Duration::from_millis(t),
+            increment: Duration::from_millis(inc.unwrap_or(0)),
+            moves_to_go: self.movestogo,
+        });
+
+        SearchLimits {
+            depth: self.depth,
+            movetime: self.movetime.map(Duration::from_millis),
+            time_left,
+            nodes: self.nodes
+        }
+    }
+}
+
+/// Some GUIs send negative times when a clock has run out
+fn parse_time(value: &str) -> Option<u64> {
+    value.parse::<i64>().ok().map(|t| t.max(0) as u64)
+}
+
+/// Speaks the Universal Chess Interface over stdin and stdout
+pub struct Uci {
+    game: chess::Game,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+    /// Shared with the search thread, which holds the lock while searching
+    tt: Arc<Mutex<TranspositionTable>>,
+    /// Set by the `UCI_Chess960` option
+    chess960: bool,
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Self {
+            game: chess::Game::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            search: None,
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_MB))),
+            chess960: false,
+        }
+    }
+
+    /// Reads commands until `quit` is received or stdin is closed
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+
+        for line in stdin.lock().lines() {
+            let Ok(line) = line else { break };
+
+            if !self.handle_command(&line) {
+                break
+            }
+        }
+
+        self.stop_search();
+    }
+
+    /// Handles a single line of input. Returns `false` once the engine
+    /// should quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        let Some(&command) = tokens.first() else { return true };
+
+        match command {
+            "uci" => {
+                println!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                if !env!("CARGO_PKG_AUTHORS").is_empty() {
+                    println!("id author {}", env!("CARGO_PKG_AUTHORS"));
+                }
+                println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+                println!("option name UCI_Chess960 type check default false");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("uciok");
+            },
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop_search();
+                self.game = chess::Game::new();
+                self.game.set_chess960(self.chess960);
+                self.tt.lock().unwrap().clear();
+            },
+            "position" => {
+                self.stop_search();
+
+                if let Err(e) = self.set_position(&tokens[1..]) {
+                    println!("info string {}", e);
+                }
+            },
+            "go" => {
+                self.stop_search();
+                self.start_search(GoParams::parse(&tokens[1..]));
+            },
+            "stop" => self.stop_search(),
+            "setoption" => {
+                if let Err(e) = self.set_option(&tokens[1..]) {
+                    println!("info string {}", e);
+                }
+            },
+            "quit" => return false,
+            _ => println!("info string unknown command {}", command)
+        }
+
+        true
+    }
+
+    /// Handles `position [startpos | fen <fen>] [moves <move>...]`
+    fn set_position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_start = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+
+        let mut game = match args.first() {
+            Some(&"startpos") => chess::Game::new(),
+            Some(&"fen") => chess::Game::from_fen(&args[1..moves_start].join(" "))
+                .map_err(|e| format!("invalid fen: {}", e))?,
+            _ => return Err("expected startpos or fen".to_string())
+        };
+
+        // Castling moves are written differently in Chess960
+        game.set_chess960(self.chess960);
+
+        for &move_str in args.iter().skip(moves_start + 1) {
+            let move_ = game.parse_uci_move(move_str)
+                .map_err(|e| format!("{} {}", e, move_str))?;
+
+            game.apply_unchecked_move(move_, false);
+        }
+
+        self.game = game;
+
+        Ok(())
+    }
+
+    /// Handles `setoption name <id> [value <x>]`
+    fn set_option(&mut self, args: &[&str]) -> Result<(), String> {
+        let value_start = args.iter().position(|&t| t == "value").unwrap_or(args.len());
+        let name = args.get(1..value_start).map(|n| n.join(" ")).unwrap_or_default();
+        let value = args.get(value_start + 1..).map(|v| v.join(" ")).unwrap_or_default();
+
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => {
+                    self.stop_search();
+                    *self.tt.lock().unwrap() = TranspositionTable::new(mb);
+                },
+                Err(_) => return Err(format!("invalid Hash value {}", value))
+            },
+            "uci_chess960" => match value.as_str() {
+                "true" | "false" => self.chess960 = value == "true",
+                _ => return Err(format!("invalid UCI_Chess960 value {}", value))
+            },
+            "syzygypath" => {
+                self.stop_search();
+                let count = chess::tb::init(&value);
+                println!("info string found {} tablebases", count);
+            },
+            _ => return Err(format!("unknown option {}", name))
+        }
+
+        Ok(())
+    }
+
+    fn start_search(&mut self, params: GoParams) {
+        self.stop.store(false, Ordering::SeqCst);
+
+        let game = self.game.clone();
+        let stop = Arc::clone(&self.stop);
+        let tt = Arc::clone(&self.tt);
+
+        self.search = Some(thread::spawn(move || {
+            let limits = params.get_limits(game.get_side_to_play());
+            let mut tt = tt.lock().unwrap();
+            let result = Search::new(&game, limits, &stop, &mut tt).run(|info| print_info(&game, info));
+
+            // The best move can't be sent until told to stop
+            while params.infinite && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            match result.best_move {
+                Some(m) => println!("bestmove {}", game.move_to_uci(&m)),
+                None => println!("bestmove 0000")
+            }
+        }));
+    }
+
+    /// Stops any running search, waiting for it to send its best move
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.search.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn print_info(game: &chess::Game, info: &SearchInfo) {
+    let score = match info.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score)
+    };
+
+    let millis = info.time.as_millis();
+    let nps = info.nodes as u128 * 1000 / millis.max(1);
+
+    print!(
+        "info depth {} score {} nodes {} nps {} hashfull {} tbhits {} time {}", 
+        info.depth, score, info.nodes, nps, info.hashfull, info.tb_hits, millis
+    );
+
+    match info.best_move {
+        Some(m) => println!(" pv {}", game.move_to_uci(&m)),
+        None => println!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_params() {
+        let params = GoParams::parse(&["wtime", "60000", "btime", "-250", "winc", "x", "movestogo", "20", "ponder"]);
+        assert_eq!(params.wtime, Some(60000));
+        assert_eq!(params.btime, Some(0));
+        assert_eq!(params.winc, None);
+        assert_eq!(params.movestogo, Some(20));
+
+        let limits = params.get_limits(chess::Side::Black);
+        let time_left = limits.time_left.unwrap();
+        assert_eq!(time_left.remaining, Duration::ZERO);
+        assert_eq!(time_left.increment, Duration::ZERO);
+        assert_eq!(time_left.moves_to_go, Some(20));
+
+        let params = GoParams::parse(&["depth", "7", "movetime", "1500", "nodes", "5000"]);
+        let limits = params.get_limits(chess::Side::White);
+        assert_eq!(limits.depth, Some(7));
+        assert_eq!(limits.movetime, Some(Duration::from_millis(1500)));
+        assert_eq!(limits.nodes, Some(5000));
+        assert_eq!(limits.time_left, None);
+
+        // An infinite search ignores the clocks
+        let params = GoParams::parse(&["infinite", "wtime", "1000", "btime", "1000"]);
+        assert!(params.infinite);
+        assert_eq!(params.get_limits(chess::Side::White), SearchLimits::default());
+
+        // A limit missing its value is left unset
+        assert_eq!(GoParams::parse(&["depth"]), GoParams::default());
+    }
+
+    #[test]
+    fn position() {
+        let mut uci = Uci::new();
+
+        uci.set_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(uci.game.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        uci.set_position(&["fen", "4k3/8/8/8/8/8/4P3/4K3", "w", "-", "-", "0", "1", "moves", "e2e4", "e8d7"]).unwrap();
+        assert_eq!(uci.game.to_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+
+        uci.set_position(&["startpos"]).unwrap();
+        assert_eq!(uci.game.to_fen(), chess::Game::new().to_fen());
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let mut uci = Uci::new();
+        uci.set_position(&["startpos", "moves", "e2e4"]).unwrap();
+        let fen = uci.game.to_fen();
+
+        // The position is only changed once every move has been made
+        assert!(uci.set_position(&["startpos", "moves", "e2e4", "e7e6", "e4e6"]).is_err());
+        assert!(uci.set_position(&["startpos", "moves", "e2e5"]).is_err());
+        assert!(uci.set_position(&["startpos", "moves", "e2"]).is_err());
+        assert!(uci.set_position(&["fen", "8/8/8", "w", "-", "-", "0", "1"]).is_err());
+        assert!(uci.set_position(&["fen"]).is_err());
+        assert!(uci.set_position(&[]).is_err());
+        assert!(uci.set_position(&["moves", "e2e4"]).is_err());
+
+        assert_eq!(uci.game.to_fen(), fen);
+    }
+
+    #[test]
+    fn options() {
+        let mut uci = Uci::new();
+
+        uci.set_option(&["name", "UCI_Chess960", "value", "true"]).unwrap();
+        assert!(uci.chess960);
+        uci.set_option(&["name", "uci_chess960", "value", "false"]).unwrap();
+        assert!(!uci.chess960);
+
+        uci.set_option(&["name", "Hash", "value", "1"]).unwrap();
+
+        assert!(uci.set_option(&["name", "Hash", "value", "lots"]).is_err());
+        assert!(uci.set_option(&["name", "UCI_Chess960", "value", "yes"]).is_err());
+        assert!(uci.set_option(&["name", "Contempt", "value", "10"]).is_err());
+        assert!(uci.set_option(&["name", "Move", "Overhead", "value", "10"]).is_err());
+        assert!(uci.set_option(&[]).is_err());
+    }
+}